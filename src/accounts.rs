@@ -0,0 +1,213 @@
+//! Multiple identities in one installation
+//!
+//! Each account lives in `~/.marmot-cli/accounts/<npub>/` with its own MDK
+//! database (and the usual sidecars: `marmot.bunker.json`, `marmot.ncryptsec.json`,
+//! audit log) plus an `account.json` describing how it signs. The active account
+//! is recorded in `~/.marmot-cli/accounts/active`.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use nostr::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Default accounts directory
+pub const DEFAULT_ACCOUNTS_DIR: &str = "~/.marmot-cli/accounts";
+
+/// How an account signs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum AccountSigner {
+    /// Direct key stored in `account.json` (owner-only permissions)
+    Nsec { nsec: String },
+    /// NIP-46 bunker; connection details live in the account's `marmot.bunker.json`
+    Bunker,
+    /// NIP-49 encrypted key in the account's `marmot.ncryptsec.json`
+    Ncryptsec,
+}
+
+/// Contents of `account.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountRecord {
+    pub npub: String,
+    /// Optional human-friendly name usable instead of the npub
+    pub label: Option<String>,
+    pub signer: AccountSigner,
+    pub created_at: String,
+}
+
+impl AccountRecord {
+    pub fn new(public_key: &PublicKey, label: Option<String>, signer: AccountSigner) -> Self {
+        Self {
+            npub: public_key.to_bech32().unwrap_or_else(|_| public_key.to_hex()),
+            label,
+            signer,
+            created_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// Label if set, otherwise a shortened npub
+    pub fn display_name(&self) -> String {
+        self.label.clone().unwrap_or_else(|| format!("{}…", &self.npub[..20.min(self.npub.len())]))
+    }
+
+    fn matches(&self, selector: &str) -> bool {
+        self.label.as_deref() == Some(selector)
+            || self.npub == selector
+            || PublicKey::parse(selector).ok().and_then(|pk| pk.to_bech32().ok()).as_deref() == Some(self.npub.as_str())
+    }
+}
+
+/// The accounts directory
+pub struct Accounts {
+    root: PathBuf,
+}
+
+impl Accounts {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn account_dir(&self, npub: &str) -> PathBuf {
+        self.root.join(npub)
+    }
+
+    fn active_path(&self) -> PathBuf {
+        self.root.join("active")
+    }
+
+    /// Database path for an account
+    pub fn db_path(&self, record: &AccountRecord) -> PathBuf {
+        self.account_dir(&record.npub).join("marmot.db")
+    }
+
+    /// Create the account directory and record (fails if it already exists)
+    pub fn add(&self, record: &AccountRecord) -> Result<PathBuf> {
+        if let Some(label) = &record.label {
+            if self.list()?.iter().any(|a| a.label.as_deref() == Some(label)) {
+                anyhow::bail!("An account labelled '{}' already exists", label);
+            }
+        }
+        let dir = self.account_dir(&record.npub);
+        if dir.join("account.json").exists() {
+            anyhow::bail!("Account {} already exists", record.npub);
+        }
+        std::fs::create_dir_all(&dir).context("Failed to create account directory")?;
+        let path = dir.join("account.json");
+        std::fs::write(&path, serde_json::to_string_pretty(record)?)
+            .context("Failed to write account.json")?;
+
+        // account.json may hold an nsec
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))?;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(dir)
+    }
+
+    /// All accounts, sorted by npub
+    pub fn list(&self) -> Result<Vec<AccountRecord>> {
+        if !self.root.exists() {
+            return Ok(Vec::new());
+        }
+        let mut records = Vec::new();
+        for entry in std::fs::read_dir(&self.root).context("Failed to read accounts directory")? {
+            let path = entry?.path().join("account.json");
+            if path.exists() {
+                let content = std::fs::read_to_string(&path)?;
+                let record: AccountRecord = serde_json::from_str(&content)
+                    .with_context(|| format!("Failed to parse {}", path.display()))?;
+                records.push(record);
+            }
+        }
+        records.sort_by(|a, b| a.npub.cmp(&b.npub));
+        Ok(records)
+    }
+
+    /// Find an account by label, npub or hex pubkey
+    pub fn find(&self, selector: &str) -> Result<AccountRecord> {
+        self.list()?
+            .into_iter()
+            .find(|a| a.matches(selector))
+            .with_context(|| format!("No account matching '{}'. See: marmot-cli accounts list", selector))
+    }
+
+    /// Make an account the default for subsequent commands
+    pub fn set_active(&self, record: &AccountRecord) -> Result<()> {
+        std::fs::write(self.active_path(), &record.npub).context("Failed to record active account")
+    }
+
+    /// The active account, if one is set and still exists
+    pub fn active(&self) -> Result<Option<AccountRecord>> {
+        let path = self.active_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let npub = std::fs::read_to_string(&path)?;
+        Ok(self.list()?.into_iter().find(|a| a.npub == npub.trim()))
+    }
+
+    /// Delete an account's directory (database, keys, audit log)
+    pub fn remove(&self, record: &AccountRecord) -> Result<()> {
+        if self.active()?.is_some_and(|a| a.npub == record.npub) {
+            std::fs::remove_file(self.active_path())?;
+        }
+        std::fs::remove_dir_all(self.account_dir(&record.npub))
+            .context("Failed to remove account directory")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nsec_record(label: Option<&str>) -> (Keys, AccountRecord) {
+        let keys = Keys::generate();
+        let signer = AccountSigner::Nsec { nsec: keys.secret_key().to_bech32().unwrap() };
+        let record = AccountRecord::new(&keys.public_key(), label.map(str::to_string), signer);
+        (keys, record)
+    }
+
+    #[test]
+    fn test_add_list_find() {
+        let dir = tempfile::tempdir().unwrap();
+        let accounts = Accounts::new(dir.path().join("accounts"));
+        assert!(accounts.list().unwrap().is_empty());
+
+        let (keys, kai) = nsec_record(Some("kai"));
+        let (_, bot) = nsec_record(None);
+        accounts.add(&kai).unwrap();
+        accounts.add(&bot).unwrap();
+
+        assert_eq!(accounts.list().unwrap().len(), 2);
+        assert_eq!(accounts.find("kai").unwrap().npub, kai.npub);
+        assert_eq!(accounts.find(&keys.public_key().to_hex()).unwrap().npub, kai.npub);
+        assert_eq!(accounts.find(&bot.npub).unwrap().npub, bot.npub);
+        assert!(accounts.find("nobody").is_err());
+
+        assert!(accounts.db_path(&kai).ends_with(format!("{}/marmot.db", kai.npub)));
+
+        // Duplicates are refused
+        assert!(accounts.add(&kai).is_err());
+        let (_, other_kai) = nsec_record(Some("kai"));
+        assert!(accounts.add(&other_kai).is_err());
+    }
+
+    #[test]
+    fn test_active_and_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let accounts = Accounts::new(dir.path().join("accounts"));
+        let (_, kai) = nsec_record(Some("kai"));
+        accounts.add(&kai).unwrap();
+
+        assert!(accounts.active().unwrap().is_none());
+        accounts.set_active(&kai).unwrap();
+        assert_eq!(accounts.active().unwrap().unwrap().npub, kai.npub);
+
+        accounts.remove(&kai).unwrap();
+        assert!(accounts.active().unwrap().is_none());
+        assert!(accounts.list().unwrap().is_empty());
+    }
+}
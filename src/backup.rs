@@ -0,0 +1,398 @@
+//! Encrypted, versioned backups of the full marmot-cli state
+//!
+//! A backup bundles a consistent snapshot of the MDK SQLite store with the
+//! sidecar files that live next to it (`marmot.bunker.json`, the audit log
+//! and passphrase KDF parameters). The bundle is sealed with
+//! XChaCha20-Poly1305 under an Argon2id key derived from a backup passphrase.
+//!
+//! File layout: `MARMOTBK` | format version (1 byte) | salt (16) | nonce (24) | ciphertext
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use nostr::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::nip46::audit;
+use crate::storage;
+
+const MAGIC: &[u8; 8] = b"MARMOTBK";
+const FORMAT_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = MAGIC.len() + 1 + SALT_LEN + NONCE_LEN;
+
+/// Database file key inside the bundle
+const DB_ENTRY: &str = "db";
+
+/// Sidecar files (by extension relative to the db path) included when present
+const SIDECARS: &[&str] = &[
+    "bunker.json",
+    "ncryptsec.json",
+    "audit.jsonl",
+    "audit.head",
+    "dbkey.json",
+    "policy.toml",
+    "verified.json",
+    "retention.json",
+    "contacts.json",
+    "blocked.json",
+    "health.json",
+];
+
+/// Rotated audit log archives (`audit.<stamp>.jsonl.gz` entries) next to `db_path`
+fn audit_archives(db_path: &Path) -> Vec<(String, PathBuf)> {
+    let prefix = format!("{}.", db_path.file_stem().unwrap_or_default().to_string_lossy());
+    audit::archives(&db_path.with_extension("audit.jsonl"))
+        .into_iter()
+        .filter_map(|path| {
+            let name = path.file_name()?.to_string_lossy().strip_prefix(&prefix)?.to_string();
+            Some((name, path))
+        })
+        .collect()
+}
+
+/// Whether `name` is a file this build knows how to restore
+fn is_known_entry(name: &str) -> bool {
+    let archive = name.starts_with("audit.")
+        && name.ends_with(".jsonl.gz")
+        && !name.contains(['/', '\\'])
+        && !name.contains("..");
+    name == DB_ENTRY || SIDECARS.contains(&name) || archive
+}
+
+/// Metadata describing what a backup contains
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub format_version: u8,
+    pub created_at: String,
+    pub marmot_cli_version: String,
+    /// Nostr identity (hex) the MLS state belongs to
+    pub user_pubkey: Option<String>,
+    /// MDK migration version of the database snapshot
+    pub schema_version: Option<i64>,
+    /// Whether the database snapshot is SQLCipher-encrypted (key not included)
+    pub db_encrypted: bool,
+}
+
+/// Decrypted backup contents
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Backup {
+    pub manifest: BackupManifest,
+    /// File name (`db` or sidecar extension) → hex contents
+    files: BTreeMap<String, String>,
+}
+
+impl Backup {
+    /// Capture the current state next to `db_path`
+    pub fn capture(db_path: &Path, db_key: Option<&[u8; 32]>, user_pubkey: Option<PublicKey>) -> Result<Self> {
+        if !db_path.exists() {
+            anyhow::bail!("Database {} does not exist", db_path.display());
+        }
+        let schema_version = storage::schema_version(&storage::open_connection(db_path, db_key)?)?;
+
+        let mut files = BTreeMap::new();
+        files.insert(DB_ENTRY.to_string(), hex::encode(storage::snapshot(db_path, db_key)?));
+        for ext in SIDECARS {
+            let path = db_path.with_extension(ext);
+            if path.exists() {
+                let data = std::fs::read(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                files.insert(ext.to_string(), hex::encode(data));
+            }
+        }
+        // Rotation markers in the log name their archives; verification fails without them
+        for (name, path) in audit_archives(db_path) {
+            let data = std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            files.insert(name, hex::encode(data));
+        }
+
+        Ok(Self {
+            manifest: BackupManifest {
+                format_version: FORMAT_VERSION,
+                created_at: chrono::Utc::now().to_rfc3339(),
+                marmot_cli_version: env!("CARGO_PKG_VERSION").to_string(),
+                user_pubkey: user_pubkey.map(|pk| pk.to_hex()),
+                schema_version,
+                db_encrypted: db_key.is_some(),
+            },
+            files,
+        })
+    }
+
+    /// Names of the files contained in this backup
+    pub fn file_names(&self) -> Vec<&str> {
+        self.files.keys().map(String::as_str).collect()
+    }
+
+    /// Identity recorded in the manifest
+    pub fn user_pubkey(&self) -> Option<PublicKey> {
+        self.manifest.user_pubkey.as_deref().and_then(|hex| PublicKey::from_hex(hex).ok())
+    }
+
+    /// Encrypt and serialize the backup
+    pub fn seal(&self, passphrase: &str) -> Result<Vec<u8>> {
+        let salt = storage::random_bytes::<SALT_LEN>();
+        let nonce = storage::random_bytes::<NONCE_LEN>();
+        let key = storage::argon2_key(passphrase, &salt)?;
+        let plaintext = serde_json::to_vec(self).context("Failed to serialize backup")?;
+        let ciphertext = XChaCha20Poly1305::new(&key.into())
+            .encrypt(XNonce::from_slice(&nonce), plaintext.as_slice())
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt backup"))?;
+
+        let mut out = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+        out.extend_from_slice(MAGIC);
+        out.push(FORMAT_VERSION);
+        out.extend_from_slice(&salt);
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&ciphertext);
+        Ok(out)
+    }
+
+    /// Decrypt and parse a backup file's bytes
+    pub fn open(data: &[u8], passphrase: &str) -> Result<Self> {
+        if data.len() < HEADER_LEN || &data[..MAGIC.len()] != MAGIC {
+            anyhow::bail!("Not a marmot-cli backup file");
+        }
+        let version = data[MAGIC.len()];
+        if version != FORMAT_VERSION {
+            anyhow::bail!(
+                "Unsupported backup format version {} (this build reads version {})",
+                version, FORMAT_VERSION
+            );
+        }
+        let salt = &data[MAGIC.len() + 1..MAGIC.len() + 1 + SALT_LEN];
+        let nonce = &data[MAGIC.len() + 1 + SALT_LEN..HEADER_LEN];
+        let key = storage::argon2_key(passphrase, salt)?;
+        let plaintext = XChaCha20Poly1305::new(&key.into())
+            .decrypt(XNonce::from_slice(nonce), &data[HEADER_LEN..])
+            .map_err(|_| anyhow::anyhow!("Wrong passphrase or corrupted backup"))?;
+        let backup: Backup = serde_json::from_slice(&plaintext).context("Corrupted backup contents")?;
+        if !backup.files.contains_key(DB_ENTRY) {
+            anyhow::bail!("Backup does not contain a database");
+        }
+        Ok(backup)
+    }
+
+    /// Write the backup's files next to `db_path`, moving existing files aside
+    ///
+    /// Every file is staged to a temp file first; if anything fails after the
+    /// current files were moved aside they are put back, so a restore either
+    /// completes or leaves the previous state as it was. Returns the paths of
+    /// the previous files (renamed with a unique `.pre-restore-<time>` suffix).
+    pub fn restore(&self, db_path: &Path) -> Result<Vec<PathBuf>> {
+        if let Some(parent) = db_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // Decode everything up front so a corrupt entry can't leave a half-restored state
+        let mut staged = Vec::new();
+        for (name, data) in &self.files {
+            if !is_known_entry(name) {
+                anyhow::bail!("Backup contains an unexpected entry '{}'", name);
+            }
+            let target = if name == DB_ENTRY { db_path.to_path_buf() } else { db_path.with_extension(name) };
+            let bytes = hex::decode(data).with_context(|| format!("Corrupted entry '{}'", name))?;
+            staged.push((storage::with_suffix(&target, ".restoring"), target, bytes));
+        }
+
+        let staging = staged
+            .iter()
+            .try_for_each(|(tmp, _, bytes)| storage::write_with_mode(tmp, bytes, storage::PRIVATE_FILE_MODE, false));
+        if let Err(e) = staging {
+            for (tmp, _, _) in &staged {
+                let _ = std::fs::remove_file(tmp);
+            }
+            return Err(e);
+        }
+
+        // Every local file the backup could contain goes aside, not only the ones
+        // it does contain, so no current sidecar is mixed with the restored state
+        let mut existing: Vec<PathBuf> = staged.iter().map(|(_, target, _)| target.clone()).collect();
+        existing.extend(SIDECARS.iter().map(|ext| db_path.with_extension(ext)));
+        existing.extend(audit_archives(db_path).into_iter().map(|(_, path)| path));
+        existing.push(storage::with_suffix(db_path, "-wal"));
+        existing.push(storage::with_suffix(db_path, "-shm"));
+        existing.sort();
+        existing.dedup();
+        existing.retain(|p| p.exists());
+        let suffix = pre_restore_suffix(&existing);
+
+        let mut moved: Vec<(PathBuf, PathBuf)> = Vec::new();
+        let mut placed: Vec<&Path> = Vec::new();
+        let result = (|| -> Result<()> {
+            for path in existing {
+                let aside = storage::with_suffix(&path, &suffix);
+                std::fs::rename(&path, &aside)
+                    .with_context(|| format!("Failed to move {} aside", path.display()))?;
+                moved.push((path, aside));
+            }
+            for (tmp, target, _) in &staged {
+                std::fs::rename(tmp, target)
+                    .with_context(|| format!("Failed to restore {}", target.display()))?;
+                placed.push(target);
+            }
+            Ok(())
+        })();
+
+        if let Err(e) = result {
+            for target in placed {
+                let _ = std::fs::remove_file(target);
+            }
+            for (path, aside) in moved.iter().rev() {
+                let _ = std::fs::rename(aside, path);
+            }
+            for (tmp, _, _) in &staged {
+                let _ = std::fs::remove_file(tmp);
+            }
+            return Err(e.context("Restore failed; the previous files were put back"));
+        }
+        Ok(moved.into_iter().map(|(_, aside)| aside).collect())
+    }
+}
+
+/// Suffix for files moved aside by a restore, unique so earlier copies survive
+fn pre_restore_suffix(paths: &[PathBuf]) -> String {
+    let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut suffix = format!(".pre-restore-{}", stamp);
+    let mut n = 1;
+    while paths.iter().any(|p| storage::with_suffix(p, &suffix).exists()) {
+        n += 1;
+        suffix = format!(".pre-restore-{}-{}", stamp, n);
+    }
+    suffix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_state(dir: &Path) -> PathBuf {
+        let db_path = dir.join("marmot.db");
+        let conn = storage::open_connection(&db_path, None).unwrap();
+        conn.execute_batch("CREATE TABLE t (v TEXT); INSERT INTO t VALUES ('state');").unwrap();
+        std::fs::write(db_path.with_extension("audit.jsonl"), "{\"operation\":\"x\"}\n").unwrap();
+        db_path
+    }
+
+    #[test]
+    fn test_seal_open_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = sample_state(dir.path());
+        let pubkey = Keys::generate().public_key();
+
+        let backup = Backup::capture(&db_path, None, Some(pubkey)).unwrap();
+        assert_eq!(backup.file_names(), vec!["audit.jsonl", "db"]);
+
+        let sealed = backup.seal("hunter2").unwrap();
+        assert!(sealed.starts_with(MAGIC));
+
+        let opened = Backup::open(&sealed, "hunter2").unwrap();
+        assert_eq!(opened.user_pubkey(), Some(pubkey));
+        assert!(!opened.manifest.db_encrypted);
+
+        assert!(Backup::open(&sealed, "wrong").is_err());
+        assert!(Backup::open(b"garbage", "hunter2").is_err());
+    }
+
+    #[test]
+    fn test_restore_moves_existing_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = sample_state(dir.path());
+        let backup = Backup::capture(&db_path, None, None).unwrap();
+
+        std::fs::write(db_path.with_extension("audit.jsonl"), "changed\n").unwrap();
+        let moved = backup.restore(&db_path).unwrap();
+
+        assert!(moved.iter().any(|p| p.to_string_lossy().contains("marmot.db.pre-restore-")));
+        let audit = std::fs::read_to_string(db_path.with_extension("audit.jsonl")).unwrap();
+        assert!(audit.contains("operation"));
+        let conn = storage::open_connection(&db_path, None).unwrap();
+        let v: String = conn.query_row("SELECT v FROM t", [], |r| r.get(0)).unwrap();
+        assert_eq!(v, "state");
+    }
+
+    #[test]
+    fn test_restore_moves_sidecars_missing_from_backup_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = sample_state(dir.path());
+        let backup = Backup::capture(&db_path, None, None).unwrap();
+
+        // Written after the backup, so not in it
+        let contacts = db_path.with_extension("contacts.json");
+        std::fs::write(&contacts, "{}").unwrap();
+        let moved = backup.restore(&db_path).unwrap();
+
+        assert!(!contacts.exists());
+        assert!(moved.iter().any(|p| p.to_string_lossy().contains("marmot.contacts.json.pre-restore-")));
+    }
+
+    #[test]
+    fn test_backup_restore_keeps_rotated_audit_log_verifiable() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("marmot.db");
+        storage::open_connection(&db_path, None).unwrap().execute_batch("CREATE TABLE t (v TEXT);").unwrap();
+        let mut log = audit::AuditLog::new(&db_path);
+        log.set_signing_keys(Keys::generate());
+        log.record("sign_event_success", "before rotation");
+        log.set_rotation(audit::RotationPolicy { max_bytes: 1, max_age: None });
+        log.record("sign_event_success", "after rotation");
+        let archives = audit::archives(log.path());
+        assert_eq!(archives.len(), 1);
+
+        let backup = Backup::capture(&db_path, None, None).unwrap();
+        assert!(backup.file_names().iter().any(|n| n.starts_with("audit.") && n.ends_with(".jsonl.gz")));
+
+        // Lose the archive locally, then restore it from the backup
+        std::fs::remove_file(&archives[0]).unwrap();
+        backup.restore(&db_path).unwrap();
+        assert!(archives[0].exists());
+        let report = audit::verify(log.path(), false).unwrap();
+        assert!(report.is_intact(), "{:?}", report.problem);
+        assert_eq!(report.archives, 1);
+    }
+
+    #[test]
+    fn test_restore_rejects_unknown_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = sample_state(dir.path());
+        let mut backup = Backup::capture(&db_path, None, None).unwrap();
+        backup.files.insert("audit.x/../../evil.jsonl.gz".to_string(), String::new());
+        assert!(backup.restore(&db_path).is_err());
+    }
+
+    #[test]
+    fn test_restore_keeps_every_previous_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = sample_state(dir.path());
+        let backup = Backup::capture(&db_path, None, None).unwrap();
+
+        let first = backup.restore(&db_path).unwrap();
+        let second = backup.restore(&db_path).unwrap();
+        assert!(first.iter().chain(&second).all(|p| p.exists()));
+        assert!(first.iter().all(|p| !second.contains(p)));
+    }
+
+    #[test]
+    fn test_failed_staging_leaves_state_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = sample_state(dir.path());
+        let backup = Backup::capture(&db_path, None, None).unwrap();
+        std::fs::write(db_path.with_extension("audit.jsonl"), "current\n").unwrap();
+
+        // A directory where the staged db file would go makes staging fail
+        std::fs::create_dir(storage::with_suffix(&db_path, ".restoring")).unwrap();
+        assert!(backup.restore(&db_path).is_err());
+
+        let audit = std::fs::read_to_string(db_path.with_extension("audit.jsonl")).unwrap();
+        assert_eq!(audit, "current\n");
+        assert!(!storage::with_suffix(&db_path.with_extension("audit.jsonl"), ".restoring").exists());
+        let names: Vec<_> = std::fs::read_dir(dir.path()).unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert!(names.iter().all(|n| !n.contains("pre-restore")));
+    }
+}
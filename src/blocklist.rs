@@ -0,0 +1,238 @@
+//! Local block list and welcome rate cap
+//!
+//! Anyone who finds our key package can invite us to groups, and every
+//! welcome that `receive` processes is stored. `marmot.blocked.json` keeps
+//! the users we've blocked (their welcomes are dropped and their group
+//! messages hidden) and, per sender, when their recent welcomes arrived so a
+//! single sender can't flood storage with invites. `block --publish` also
+//! publishes the list as a public NIP-51 mute list (kind 10000).
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use nostr::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Default welcomes accepted per sender per hour
+pub const DEFAULT_WELCOMES_PER_HOUR: u32 = 5;
+
+/// Window for the welcome rate cap, in seconds
+const WELCOME_WINDOW_SECS: i64 = 3600;
+
+/// One blocked user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockedUser {
+    pub npub: String,
+    pub blocked_at: String,
+}
+
+/// Contents of `marmot.blocked.json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BlockList {
+    /// Blocked users, keyed by public key (hex)
+    #[serde(default)]
+    pub blocked: BTreeMap<String, BlockedUser>,
+    /// Arrival times (unix seconds) of recent welcomes, keyed by sender (hex)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    welcomes: BTreeMap<String, Vec<i64>>,
+}
+
+impl BlockList {
+    pub fn config_path(db_path: &Path) -> PathBuf {
+        db_path.with_extension("blocked.json")
+    }
+
+    /// Load the block list (missing file = nobody blocked)
+    pub fn load(db_path: &Path) -> Result<Self> {
+        let path = Self::config_path(db_path);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Save atomically with owner-only permissions
+    pub fn save(&self, db_path: &Path) -> Result<()> {
+        crate::storage::save_json_atomic(&Self::config_path(db_path), self, crate::storage::PRIVATE_FILE_MODE)
+    }
+
+    pub fn is_blocked(&self, public_key: &PublicKey) -> bool {
+        self.blocked.contains_key(&public_key.to_hex())
+    }
+
+    /// Block a user; false if they already were
+    pub fn block(&mut self, public_key: &PublicKey) -> bool {
+        let hex = public_key.to_hex();
+        if self.blocked.contains_key(&hex) {
+            return false;
+        }
+        self.blocked.insert(
+            hex,
+            BlockedUser {
+                npub: public_key.to_bech32().unwrap_or_else(|_| public_key.to_hex()),
+                blocked_at: chrono::Utc::now().to_rfc3339(),
+            },
+        );
+        true
+    }
+
+    /// Unblock a user; false if they weren't blocked
+    pub fn unblock(&mut self, public_key: &PublicKey) -> bool {
+        self.blocked.remove(&public_key.to_hex()).is_some()
+    }
+
+    /// Whether another welcome from `sender` fits under `per_hour` at `now`
+    pub fn welcome_allowed(&mut self, sender: &PublicKey, now: i64, per_hour: u32) -> bool {
+        let recent = self.welcomes.entry(sender.to_hex()).or_default();
+        recent.retain(|t| now - t < WELCOME_WINDOW_SECS);
+        recent.len() < per_hour as usize
+    }
+
+    /// Count a welcome from `sender` that was stored
+    pub fn record_welcome(&mut self, sender: &PublicKey, now: i64) {
+        self.welcomes.entry(sender.to_hex()).or_default().push(now);
+    }
+
+    /// Drop rate-cap history that no longer matters
+    pub fn prune(&mut self, now: i64) {
+        for times in self.welcomes.values_mut() {
+            times.retain(|t| now - t < WELCOME_WINDOW_SECS);
+        }
+        self.welcomes.retain(|_, times| !times.is_empty());
+    }
+
+    /// NIP-51 mute list (kind 10000) with a public `p` tag per blocked user
+    ///
+    /// Kind 10000 is replaceable, so the list is built on top of our latest
+    /// published one (`existing`): its other tags (words, hashtags, threads,
+    /// people muted from other clients) and its content, which carries the
+    /// encrypted private items, are kept as they are. Only the `p` tags of
+    /// `unblocked` users are removed, and missing ones for blocked users added.
+    pub fn mute_list(&self, existing: Option<&Event>, unblocked: &[PublicKey]) -> Result<EventBuilder> {
+        let blocked = self
+            .blocked
+            .keys()
+            .map(PublicKey::from_hex)
+            .collect::<Result<Vec<_>, _>>()
+            .context("Invalid key in block list")?;
+        let muted_key = |tag: &Tag| match tag.as_standardized() {
+            Some(TagStandard::PublicKey { public_key, .. }) => Some(*public_key),
+            _ => None,
+        };
+
+        let mut tags: Vec<Tag> = existing
+            .map(|event| {
+                event.tags.iter()
+                    .filter(|tag| muted_key(tag).is_none_or(|key| !unblocked.contains(&key)))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        for public_key in blocked {
+            if !tags.iter().any(|tag| muted_key(tag) == Some(public_key)) {
+                tags.push(Tag::public_key(public_key));
+            }
+        }
+
+        let content = existing.map(|event| event.content.clone()).unwrap_or_default();
+        let mut builder = EventBuilder::new(Kind::MuteList, content).tags(tags);
+        // Relays keep only the newest version; never publish one that looks older
+        if let Some(event) = existing {
+            if event.created_at >= Timestamp::now() {
+                builder = builder.custom_created_at(event.created_at + 1);
+            }
+        }
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_unblock_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("marmot.db");
+        let spammer = Keys::generate().public_key();
+
+        let mut list = BlockList::load(&db_path).unwrap();
+        assert!(!list.is_blocked(&spammer));
+        assert!(list.block(&spammer));
+        assert!(!list.block(&spammer));
+        list.save(&db_path).unwrap();
+
+        let mut list = BlockList::load(&db_path).unwrap();
+        assert!(list.is_blocked(&spammer));
+        assert!(list.unblock(&spammer));
+        assert!(!list.unblock(&spammer));
+    }
+
+    #[test]
+    fn test_welcome_rate_cap() {
+        let sender = Keys::generate().public_key();
+        let other = Keys::generate().public_key();
+        let mut list = BlockList::default();
+        for i in 0..3 {
+            assert!(list.welcome_allowed(&sender, 1000 + i, 3));
+            list.record_welcome(&sender, 1000 + i);
+        }
+        assert!(!list.welcome_allowed(&sender, 1010, 3));
+        assert!(list.welcome_allowed(&other, 1010, 3));
+        // The window slides
+        assert!(list.welcome_allowed(&sender, 1000 + WELCOME_WINDOW_SECS + 1, 3));
+
+        list.prune(1000 + 2 * WELCOME_WINDOW_SECS);
+        assert!(list.welcomes.is_empty());
+    }
+
+    #[test]
+    fn test_mute_list_tags() {
+        let mut list = BlockList::default();
+        let a = Keys::generate().public_key();
+        let b = Keys::generate().public_key();
+        list.block(&a);
+        list.block(&b);
+        let event = list.mute_list(None, &[]).unwrap().build(Keys::generate().public_key());
+        assert_eq!(event.kind, Kind::MuteList);
+        let muted: Vec<PublicKey> = event.tags.public_keys().copied().collect();
+        assert_eq!(muted.len(), 2);
+        assert!(muted.contains(&a) && muted.contains(&b));
+    }
+
+    #[test]
+    fn test_mute_list_merges_with_published() {
+        let keys = Keys::generate();
+        let muted_elsewhere = Keys::generate().public_key();
+        let unblocked = Keys::generate().public_key();
+        let blocked = Keys::generate().public_key();
+        let existing = EventBuilder::new(Kind::MuteList, "encrypted-private-items")
+            .tags([
+                Tag::public_key(muted_elsewhere),
+                Tag::public_key(unblocked),
+                Tag::hashtag("spam"),
+                Tag::custom(TagKind::custom("word"), ["airdrop"]),
+            ])
+            .sign_with_keys(&keys)
+            .unwrap();
+
+        let mut list = BlockList::default();
+        list.block(&blocked);
+        let event = list.mute_list(Some(&existing), &[unblocked]).unwrap().build(keys.public_key());
+
+        assert_eq!(event.content, "encrypted-private-items");
+        let muted: Vec<PublicKey> = event.tags.public_keys().copied().collect();
+        assert_eq!(muted, vec![muted_elsewhere, blocked]);
+        assert!(event.tags.iter().any(|tag| tag == &Tag::hashtag("spam")));
+        assert!(event.tags.iter().any(|tag| tag.as_slice() == ["word", "airdrop"]));
+        assert!(event.created_at >= existing.created_at);
+
+        // Blocking someone already on the published list doesn't duplicate them
+        list.block(&muted_elsewhere);
+        let event = list.mute_list(Some(&existing), &[]).unwrap().build(keys.public_key());
+        assert_eq!(event.tags.public_keys().filter(|key| **key == muted_elsewhere).count(), 1);
+    }
+}
@@ -0,0 +1,454 @@
+//! TOML configuration file with named profiles
+//!
+//! `~/.marmot-cli/config.toml` holds one or more profiles so several
+//! identities can run side-by-side without wrapper scripts:
+//!
+//! ```toml
+//! default_profile = "kai"
+//!
+//! [profiles.kai]
+//! db = "~/.marmot-cli/kai.db"
+//! relays = ["wss://relay.damus.io", "wss://nos.lol"]
+//! bunker = "bunker://..."
+//! on_message = "node process-dm.js"
+//! auto_accept = "from"
+//! auto_accept_from = ["npub1..."]
+//! quiet = true
+//! ```
+//!
+//! CLI flags and environment variables always override profile values.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use nostr::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Default config file location
+pub const DEFAULT_CONFIG_PATH: &str = "~/.marmot-cli/config.toml";
+
+/// Profile name used by `config set` when none is selected
+pub const FALLBACK_PROFILE: &str = "default";
+
+/// Keys accepted by `config get` / `config set`
+pub const PROFILE_KEYS: &[&str] = &[
+    "account",
+    "db",
+    "db_key",
+    "nsec",
+    "bunker",
+    "relays",
+    "proxy",
+    "auth_relays",
+    "connect_quorum",
+    "connect_timeout",
+    "bunker_timeout",
+    "on_message",
+    "auto_accept",
+    "auto_accept_from",
+    "quiet",
+    "audit_max_size",
+    "audit_max_age",
+    "message_retention",
+    "message_expiration",
+    "max_welcomes_per_hour",
+];
+
+/// Expand a leading `~/` (or a bare `~`) to the user's home directory
+pub fn expand_home(path: &str) -> PathBuf {
+    let home = || PathBuf::from(std::env::var("HOME").unwrap_or_default());
+    match path.strip_prefix("~/") {
+        Some(rest) => home().join(rest),
+        None if path == "~" => home(),
+        None => PathBuf::from(path),
+    }
+}
+
+/// Whether `listen` accepts incoming welcomes without a manual `accept-welcome`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoAccept {
+    /// Never auto-accept (default)
+    #[default]
+    Never,
+    /// Accept welcomes from senders listed in `auto_accept_from`
+    From,
+    /// Accept every welcome
+    Always,
+}
+
+impl std::str::FromStr for AutoAccept {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "never" => Ok(AutoAccept::Never),
+            "from" => Ok(AutoAccept::From),
+            "always" => Ok(AutoAccept::Always),
+            other => anyhow::bail!("Invalid auto_accept '{}': expected never, from or always", other),
+        }
+    }
+}
+
+/// One named set of settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    pub account: Option<String>,
+    pub db: Option<String>,
+    pub db_key: Option<String>,
+    pub nsec: Option<String>,
+    pub bunker: Option<String>,
+    pub relays: Option<Vec<String>>,
+    pub proxy: Option<String>,
+    pub auth_relays: Option<Vec<String>>,
+    pub connect_quorum: Option<usize>,
+    pub connect_timeout: Option<u64>,
+    pub bunker_timeout: Option<u64>,
+    pub on_message: Option<String>,
+    pub auto_accept: Option<AutoAccept>,
+    pub auto_accept_from: Option<Vec<String>>,
+    pub quiet: Option<bool>,
+    /// Rotate the audit log beyond this many MiB
+    pub audit_max_size: Option<u64>,
+    /// Rotate the audit log once its oldest entry is this many days old
+    pub audit_max_age: Option<u64>,
+    /// Delete stored messages older than this (`7d`, `off`); per-group overrides win
+    pub message_retention: Option<String>,
+    /// Tag outgoing messages with a NIP-40 expiration matching the retention
+    pub message_expiration: Option<bool>,
+    /// Welcomes stored per sender per hour (default 5)
+    pub max_welcomes_per_hour: Option<u32>,
+}
+
+impl Profile {
+    /// Read a single key as a display string
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        let value = match key {
+            "account" => self.account.clone(),
+            "db" => self.db.clone(),
+            "db_key" => self.db_key.clone(),
+            "nsec" => self.nsec.clone(),
+            "bunker" => self.bunker.clone(),
+            "relays" => self.relays.as_ref().map(|r| r.join(",")),
+            "proxy" => self.proxy.clone(),
+            "auth_relays" => self.auth_relays.as_ref().map(|r| r.join(",")),
+            "connect_quorum" => self.connect_quorum.map(|v| v.to_string()),
+            "connect_timeout" => self.connect_timeout.map(|v| v.to_string()),
+            "bunker_timeout" => self.bunker_timeout.map(|v| v.to_string()),
+            "on_message" => self.on_message.clone(),
+            "auto_accept" => self.auto_accept.map(|v| format!("{:?}", v).to_lowercase()),
+            "auto_accept_from" => self.auto_accept_from.as_ref().map(|r| r.join(",")),
+            "quiet" => self.quiet.map(|v| v.to_string()),
+            "audit_max_size" => self.audit_max_size.map(|v| v.to_string()),
+            "audit_max_age" => self.audit_max_age.map(|v| v.to_string()),
+            "message_retention" => self.message_retention.clone(),
+            "message_expiration" => self.message_expiration.map(|v| v.to_string()),
+            "max_welcomes_per_hour" => self.max_welcomes_per_hour.map(|v| v.to_string()),
+            other => anyhow::bail!("Unknown config key '{}'. Valid keys: {}", other, PROFILE_KEYS.join(", ")),
+        };
+        Ok(value)
+    }
+
+    /// Set a single key from its string form; an empty value unsets it
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let value = value.trim();
+        let text = (!value.is_empty()).then(|| value.to_string());
+        let list = || text.as_ref().map(|v| split_list(v));
+        match key {
+            "account" => self.account = text.clone(),
+            "db" => self.db = text.clone(),
+            "db_key" => self.db_key = text.clone(),
+            "nsec" => self.nsec = text.clone(),
+            "bunker" => self.bunker = text.clone(),
+            "relays" => self.relays = list(),
+            "proxy" => self.proxy = text.clone(),
+            "auth_relays" => self.auth_relays = list(),
+            "connect_quorum" => {
+                self.connect_quorum = text.as_deref().map(str::parse).transpose()
+                    .context("connect_quorum must be a number")?;
+            }
+            "connect_timeout" => {
+                self.connect_timeout = text.as_deref().map(str::parse).transpose()
+                    .context("connect_timeout must be a number of seconds")?;
+            }
+            "bunker_timeout" => {
+                self.bunker_timeout = text.as_deref().map(str::parse).transpose()
+                    .context("bunker_timeout must be a number of seconds")?;
+            }
+            "on_message" => self.on_message = text.clone(),
+            "auto_accept" => self.auto_accept = text.as_deref().map(str::parse).transpose()?,
+            "auto_accept_from" => self.auto_accept_from = list(),
+            "quiet" => {
+                self.quiet = text.as_deref().map(str::parse).transpose()
+                    .context("quiet must be true or false")?;
+            }
+            "audit_max_size" => {
+                self.audit_max_size = text.as_deref().map(str::parse).transpose()
+                    .context("audit_max_size must be a number of MiB")?;
+            }
+            "audit_max_age" => {
+                self.audit_max_age = text.as_deref().map(str::parse).transpose()
+                    .context("audit_max_age must be a number of days")?;
+            }
+            "message_retention" => {
+                if let Some(value) = &text {
+                    crate::retention::parse_keep(value).context("message_retention must be a duration (7d) or off")?;
+                }
+                self.message_retention = text.clone();
+            }
+            "message_expiration" => {
+                self.message_expiration = text.as_deref().map(str::parse).transpose()
+                    .context("message_expiration must be true or false")?;
+            }
+            "max_welcomes_per_hour" => {
+                self.max_welcomes_per_hour = text.as_deref().map(str::parse).transpose()
+                    .context("max_welcomes_per_hour must be a number")?;
+            }
+            other => anyhow::bail!("Unknown config key '{}'. Valid keys: {}", other, PROFILE_KEYS.join(", ")),
+        }
+        Ok(())
+    }
+
+    /// Copy of this profile safe to print (secrets masked)
+    pub fn redacted(&self) -> Self {
+        let mut copy = self.clone();
+        if copy.nsec.is_some() {
+            copy.nsec = Some("<redacted>".to_string());
+        }
+        if let Some(bunker) = &copy.bunker {
+            if bunker.contains("secret=") {
+                copy.bunker = Some(format!("{}secret=<redacted>", bunker.split("secret=").next().unwrap_or_default()));
+            }
+        }
+        copy
+    }
+
+    /// Whether `listen` should accept a welcome sent by `welcomer`
+    pub fn should_auto_accept(&self, welcomer: &PublicKey) -> bool {
+        match self.auto_accept.unwrap_or_default() {
+            AutoAccept::Never => false,
+            AutoAccept::Always => true,
+            AutoAccept::From => self.auto_accept_from.iter().flatten().any(|entry| {
+                PublicKey::parse(entry).map(|pk| pk == *welcomer).unwrap_or(false)
+            }),
+        }
+    }
+}
+
+/// Parse a duration like `90m`, `24h`, `7d` or `2w`
+pub fn parse_duration(value: &str) -> Result<chrono::Duration> {
+    let value = value.trim();
+    let split = value.char_indices().last().map(|(i, _)| i).unwrap_or(0);
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount
+        .parse()
+        .with_context(|| format!("Invalid duration '{}': expected a number followed by m, h, d or w", value))?;
+    if amount <= 0 {
+        anyhow::bail!("Invalid duration '{}': must be positive", value);
+    }
+    match unit {
+        "m" => Ok(chrono::Duration::minutes(amount)),
+        "h" => Ok(chrono::Duration::hours(amount)),
+        "d" => Ok(chrono::Duration::days(amount)),
+        "w" => Ok(chrono::Duration::weeks(amount)),
+        _ => anyhow::bail!("Invalid duration unit in '{}': expected m, h, d or w", value),
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// The whole config file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConfigFile {
+    /// Profile used when `--profile` isn't given
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl ConfigFile {
+    /// Load the config file (missing file = empty config)
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))
+    }
+
+    /// Save the config file atomically with owner-only permissions
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp_path = path.with_extension("toml.tmp");
+        let content = toml::to_string_pretty(self).context("Failed to serialize config")?;
+        std::fs::write(&tmp_path, content).context("Failed to write config temp file")?;
+        std::fs::rename(&tmp_path, path).context("Failed to atomically save config")?;
+
+        // Profiles may contain an nsec or bunker secret
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(())
+    }
+
+    /// Name of the profile in effect: `--profile`, else `default_profile`
+    pub fn selected_name<'a>(&'a self, requested: Option<&'a str>) -> Option<&'a str> {
+        requested.or(self.default_profile.as_deref())
+    }
+
+    /// Resolve the profile in effect (an empty profile when none is selected)
+    pub fn profile(&self, requested: Option<&str>) -> Result<Profile> {
+        match self.selected_name(requested) {
+            None => Ok(Profile::default()),
+            Some(name) => self.profiles.get(name).cloned().with_context(|| {
+                let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+                format!(
+                    "Profile '{}' not found. Known profiles: {}",
+                    name,
+                    if known.is_empty() { "(none)".to_string() } else { known.join(", ") }
+                )
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_home_only_leading_tilde() {
+        let home = PathBuf::from(std::env::var("HOME").unwrap_or_default());
+        assert_eq!(expand_home("~/.marmot-cli/marmot.db"), home.join(".marmot-cli/marmot.db"));
+        assert_eq!(expand_home("~"), home);
+        assert_eq!(expand_home("/srv/agents/~bot/marmot.db"), PathBuf::from("/srv/agents/~bot/marmot.db"));
+        assert_eq!(expand_home("~bot/marmot.db"), PathBuf::from("~bot/marmot.db"));
+    }
+
+    #[test]
+    fn test_parse_profiles() {
+        let toml = r#"
+            default_profile = "kai"
+
+            [profiles.kai]
+            db = "~/.marmot-cli/kai.db"
+            relays = ["wss://relay.damus.io", "wss://nos.lol"]
+            auto_accept = "always"
+            quiet = true
+
+            [profiles.test]
+            db = "/tmp/test.db"
+        "#;
+        let config: ConfigFile = toml::from_str(toml).unwrap();
+        assert_eq!(config.profiles.len(), 2);
+
+        let kai = config.profile(None).unwrap();
+        assert_eq!(kai.db.as_deref(), Some("~/.marmot-cli/kai.db"));
+        assert_eq!(kai.relays.as_ref().unwrap().len(), 2);
+        assert_eq!(kai.auto_accept, Some(AutoAccept::Always));
+        assert_eq!(kai.quiet, Some(true));
+
+        let test = config.profile(Some("test")).unwrap();
+        assert_eq!(test.db.as_deref(), Some("/tmp/test.db"));
+
+        assert!(config.profile(Some("missing")).is_err());
+    }
+
+    #[test]
+    fn test_no_profile_selected_is_empty() {
+        let config = ConfigFile::default();
+        let profile = config.profile(None).unwrap();
+        assert!(profile.db.is_none());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90m").unwrap(), chrono::Duration::minutes(90));
+        assert_eq!(parse_duration("7d").unwrap(), chrono::Duration::days(7));
+        assert_eq!(parse_duration(" 2w ").unwrap(), chrono::Duration::weeks(2));
+        assert!(parse_duration("0d").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("5y").is_err());
+        assert!(parse_duration("5é").is_err());
+    }
+
+    #[test]
+    fn test_profile_get_set() {
+        let mut profile = Profile::default();
+        profile.set("relays", "wss://a.example, wss://b.example").unwrap();
+        profile.set("connect_quorum", "2").unwrap();
+        profile.set("quiet", "true").unwrap();
+
+        assert_eq!(profile.relays.as_ref().unwrap().len(), 2);
+        assert_eq!(profile.get("relays").unwrap().unwrap(), "wss://a.example,wss://b.example");
+        assert_eq!(profile.get("connect_quorum").unwrap().unwrap(), "2");
+
+        profile.set("relays", "").unwrap();
+        assert!(profile.relays.is_none());
+
+        assert!(profile.set("connect_quorum", "many").is_err());
+        assert!(profile.set("auto_accept", "sometimes").is_err());
+        assert!(profile.set("message_retention", "soon").is_err());
+        profile.set("message_retention", "off").unwrap();
+        assert!(profile.set("nonsense", "1").is_err());
+        assert!(profile.get("nonsense").is_err());
+    }
+
+    #[test]
+    fn test_save_load_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+
+        let mut config = ConfigFile::default();
+        let mut profile = Profile::default();
+        profile.set("db", "/tmp/kai.db").unwrap();
+        config.profiles.insert("kai".to_string(), profile);
+        config.default_profile = Some("kai".to_string());
+        config.save(&path).unwrap();
+
+        let loaded = ConfigFile::load(&path).unwrap();
+        assert_eq!(loaded.default_profile.as_deref(), Some("kai"));
+        assert_eq!(loaded.profile(None).unwrap().db.as_deref(), Some("/tmp/kai.db"));
+    }
+
+    #[test]
+    fn test_redacted_hides_secrets() {
+        let mut profile = Profile::default();
+        profile.set("nsec", "nsec1secret").unwrap();
+        profile.set("bunker", "bunker://abc?relay=wss://r.example&secret=TOKEN").unwrap();
+
+        let shown = profile.redacted();
+        assert_eq!(shown.nsec.as_deref(), Some("<redacted>"));
+        assert!(!shown.bunker.unwrap().contains("TOKEN"));
+    }
+
+    #[test]
+    fn test_auto_accept_policy() {
+        let alice = Keys::generate().public_key();
+        let bob = Keys::generate().public_key();
+
+        let mut profile = Profile::default();
+        assert!(!profile.should_auto_accept(&alice));
+
+        profile.auto_accept = Some(AutoAccept::From);
+        profile.auto_accept_from = Some(vec![alice.to_bech32().unwrap()]);
+        assert!(profile.should_auto_accept(&alice));
+        assert!(!profile.should_auto_accept(&bob));
+
+        profile.auto_accept = Some(AutoAccept::Always);
+        assert!(profile.should_auto_accept(&bob));
+    }
+}
@@ -0,0 +1,166 @@
+//! Contact book: local aliases (petnames) for Nostr identities
+//!
+//! Aliases live in `marmot.contacts.json` next to the database, so each
+//! account has its own. Anywhere a user is expected (`create-chat`,
+//! `add-members`, `fetch-key-package`) an alias can stand in for the npub, and
+//! `send -g <alias>` picks the one-to-one chat with that contact. Output and
+//! `--on-message` payloads show the alias instead of a truncated npub.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use nostr::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Longest accepted alias
+const MAX_ALIAS_LEN: usize = 32;
+
+/// One contact
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Contact {
+    pub npub: String,
+    /// NIP-05 identifier the contact was added by, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nip05: Option<String>,
+    pub added_at: String,
+}
+
+impl Contact {
+    pub fn public_key(&self) -> Result<PublicKey> {
+        PublicKey::parse(&self.npub).with_context(|| format!("Invalid npub '{}' in contacts", self.npub))
+    }
+}
+
+/// Reject aliases that could be mistaken for keys, group IDs or identifiers
+pub fn validate_alias(alias: &str) -> Result<()> {
+    if alias.is_empty() || alias.len() > MAX_ALIAS_LEN {
+        anyhow::bail!("Alias must be 1-{} characters", MAX_ALIAS_LEN);
+    }
+    if !alias.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.') {
+        anyhow::bail!("Alias '{}' may only contain letters, digits, '-', '_' and '.'", alias);
+    }
+    if alias.starts_with("npub1") || alias.starts_with("nsec1") || alias.starts_with("nprofile1") {
+        anyhow::bail!("Alias '{}' looks like a Nostr key", alias);
+    }
+    if alias.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("Alias '{}' looks like a hex key or group ID", alias);
+    }
+    Ok(())
+}
+
+/// Contents of `marmot.contacts.json`: alias -> contact
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Contacts {
+    #[serde(default)]
+    pub contacts: BTreeMap<String, Contact>,
+}
+
+impl Contacts {
+    pub fn config_path(db_path: &Path) -> PathBuf {
+        db_path.with_extension("contacts.json")
+    }
+
+    /// Load the contact book (missing file = empty)
+    pub fn load(db_path: &Path) -> Result<Self> {
+        let path = Self::config_path(db_path);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Save atomically with owner-only permissions
+    pub fn save(&self, db_path: &Path) -> Result<()> {
+        crate::storage::save_json_atomic(&Self::config_path(db_path), self, crate::storage::PRIVATE_FILE_MODE)
+    }
+
+    /// Add `alias` for `public_key`; an alias already taken by someone else is refused
+    pub fn add(&mut self, alias: &str, public_key: &PublicKey) -> Result<()> {
+        validate_alias(alias)?;
+        let npub = public_key.to_bech32().unwrap_or_else(|_| public_key.to_hex());
+        if let Some(existing) = self.contacts.get(alias) {
+            if existing.npub != npub {
+                anyhow::bail!("Alias '{}' is already used for {}; remove it first", alias, existing.npub);
+            }
+        }
+        self.contacts.insert(
+            alias.to_string(),
+            Contact { npub, nip05: None, added_at: chrono::Utc::now().to_rfc3339() },
+        );
+        Ok(())
+    }
+
+    /// Remove an alias; returns whether it existed
+    pub fn remove(&mut self, alias: &str) -> bool {
+        self.contacts.remove(alias).is_some()
+    }
+
+    /// Key for an alias, if it is one
+    pub fn lookup(&self, alias: &str) -> Result<Option<PublicKey>> {
+        self.contacts.get(alias).map(Contact::public_key).transpose()
+    }
+
+    /// Resolve an alias, npub or hex key
+    pub fn resolve(&self, input: &str) -> Result<PublicKey> {
+        if let Some(public_key) = self.lookup(input)? {
+            return Ok(public_key);
+        }
+        PublicKey::parse(input).with_context(|| format!("'{}' is not a contact alias, npub or hex key", input))
+    }
+
+    /// First alias recorded for `public_key`
+    pub fn alias_for(&self, public_key: &PublicKey) -> Option<&str> {
+        let npub = public_key.to_bech32().ok()?;
+        self.contacts.iter().find(|(_, c)| c.npub == npub).map(|(alias, _)| alias.as_str())
+    }
+
+    /// Alias if known, otherwise the full npub
+    pub fn display(&self, public_key: &PublicKey) -> String {
+        match self.alias_for(public_key) {
+            Some(alias) => alias.to_string(),
+            None => public_key.to_bech32().unwrap_or_else(|_| public_key.to_hex()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_alias() {
+        assert!(validate_alias("alice").is_ok());
+        assert!(validate_alias("bob.work-2").is_ok());
+        assert!(validate_alias("").is_err());
+        assert!(validate_alias("alice smith").is_err());
+        assert!(validate_alias("alice@example.com").is_err());
+        assert!(validate_alias("npub1abc").is_err());
+        assert!(validate_alias("cafe").is_err());
+        assert!(validate_alias(&"a".repeat(MAX_ALIAS_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_resolve_and_display() {
+        let alice = Keys::generate().public_key();
+        let bob = Keys::generate().public_key();
+        let mut contacts = Contacts::default();
+        contacts.add("alice", &alice).unwrap();
+
+        assert_eq!(contacts.resolve("alice").unwrap(), alice);
+        assert_eq!(contacts.resolve(&bob.to_bech32().unwrap()).unwrap(), bob);
+        assert_eq!(contacts.resolve(&bob.to_hex()).unwrap(), bob);
+        assert!(contacts.resolve("carol").is_err());
+
+        assert_eq!(contacts.display(&alice), "alice");
+        assert_eq!(contacts.display(&bob), bob.to_bech32().unwrap());
+
+        // Re-adding the same key is fine, taking someone else's alias is not
+        contacts.add("alice", &alice).unwrap();
+        assert!(contacts.add("alice", &bob).is_err());
+        assert!(contacts.remove("alice"));
+        assert!(!contacts.remove("alice"));
+    }
+}
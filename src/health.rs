@@ -0,0 +1,348 @@
+//! Per-group health tracking: decryption failures, epoch mismatches, forks
+//!
+//! MLS groups silently break when members end up on different branches of
+//! the epoch history (two commits for the same epoch, a missed commit, a
+//! commit we created but never merged). Symptoms are all the same from the
+//! outside: messages stop decrypting. `receive` feeds every processing
+//! outcome into [`HealthLog`] (stored as `marmot.health.json`) so a pattern
+//! of failures can be told apart from the odd undecryptable event, and
+//! `group health` / `receive` can say when a group looks forked and what to
+//! do about it.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use mdk_core::prelude::*;
+use mdk_sqlite_storage::MdkSqliteStorage;
+use serde::{Deserialize, Serialize};
+
+use crate::verification::load_mls_group;
+
+/// Outcomes kept for the recent failure rate
+const RECENT_WINDOW: usize = 50;
+
+/// Event IDs remembered so repeated fetches aren't counted twice
+const SEEN_CAP: usize = 1000;
+
+/// Consecutive failures (with no success in between) that suggest a fork
+const FORK_STREAK: u32 = 5;
+
+/// Recent failure rate above which a group is degraded
+const DEGRADED_RATE: f64 = 0.2;
+
+/// Why a message couldn't be processed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    /// Message is for an epoch we're not in (missed or conflicting commit)
+    EpochMismatch,
+    /// Anything else that stopped decryption
+    Decryption,
+}
+
+/// Classify a `process_message` error; `None` for errors that aren't a
+/// health problem (our own messages echoed back)
+pub fn classify(error: &mdk_core::Error) -> Option<FailureKind> {
+    match error {
+        mdk_core::Error::CannotDecryptOwnMessage { .. } => None,
+        mdk_core::Error::ProcessMessageWrongEpoch { .. } => Some(FailureKind::EpochMismatch),
+        _ => Some(FailureKind::Decryption),
+    }
+}
+
+/// Assessment of one group
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Healthy,
+    /// Some failures or an unmerged commit; may recover on its own
+    Degraded(String),
+    /// We have most likely diverged from the rest of the group
+    Forked(String),
+}
+
+impl Verdict {
+    pub fn is_healthy(&self) -> bool {
+        matches!(self, Verdict::Healthy)
+    }
+
+    /// What the user can do about it
+    pub fn recovery(&self) -> Option<&'static str> {
+        match self {
+            Verdict::Healthy => None,
+            Verdict::Degraded(_) => Some(
+                "Keep receiving; a missed commit usually arrives with the next poll. \
+                 If failures continue, check that every group relay is reachable (`relays status`).",
+            ),
+            Verdict::Forked(_) => Some(
+                "This device is on a different epoch history than the other members. \
+                 Back up (`backup`), publish a fresh key package (`publish-key-package`) \
+                 and ask another member to remove and re-add you, or start a new chat \
+                 (`create-chat <npub>`). See TROUBLESHOOTING.md, \"Group State Desync\".",
+            ),
+        }
+    }
+}
+
+/// Counters for one group
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GroupHealth {
+    pub processed: u64,
+    pub failed: u64,
+    pub epoch_mismatches: u64,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<String>,
+    pub last_success_at: Option<String>,
+    /// When we last published a self-update commit (`rotate-keys`)
+    pub last_key_rotation: Option<String>,
+    /// When scheduled rotation first saw the group, if it was never rotated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation_scheduled_since: Option<String>,
+    /// Recent outcomes, oldest first (true = processed)
+    #[serde(default)]
+    recent: VecDeque<bool>,
+    #[serde(default)]
+    seen: VecDeque<String>,
+    /// Seen events whose last attempt failed; a later success still counts
+    #[serde(default)]
+    unresolved: VecDeque<String>,
+}
+
+impl GroupHealth {
+    /// Remember `event_id`; false if it was already counted
+    fn first_sighting(&mut self, event_id: &str) -> bool {
+        if self.seen.iter().any(|id| id == event_id) {
+            return false;
+        }
+        if self.seen.len() == SEEN_CAP {
+            self.seen.pop_front();
+        }
+        self.seen.push_back(event_id.to_string());
+        true
+    }
+
+    fn push_recent(&mut self, ok: bool) {
+        if self.recent.len() == RECENT_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(ok);
+    }
+
+    pub fn record_success(&mut self, event_id: &str) {
+        // A failed event that decrypts on a later poll (the missing commit
+        // arrived) is a success; any other repeat was already counted
+        let retried = self.unresolved
+            .iter()
+            .position(|id| id == event_id)
+            .and_then(|index| self.unresolved.remove(index))
+            .is_some();
+        if !self.first_sighting(event_id) && !retried {
+            return;
+        }
+        self.processed += 1;
+        self.consecutive_failures = 0;
+        self.last_success_at = Some(chrono::Utc::now().to_rfc3339());
+        self.push_recent(true);
+    }
+
+    pub fn record_failure(&mut self, event_id: &str, kind: FailureKind, error: &str) {
+        if !self.first_sighting(event_id) {
+            return;
+        }
+        if self.unresolved.len() == SEEN_CAP {
+            self.unresolved.pop_front();
+        }
+        self.unresolved.push_back(event_id.to_string());
+        self.failed += 1;
+        if kind == FailureKind::EpochMismatch {
+            self.epoch_mismatches += 1;
+        }
+        self.consecutive_failures += 1;
+        self.last_error = Some(error.to_string());
+        self.last_error_at = Some(chrono::Utc::now().to_rfc3339());
+        self.push_recent(false);
+    }
+
+    pub fn record_key_rotation(&mut self) {
+        self.last_key_rotation = Some(chrono::Utc::now().to_rfc3339());
+    }
+
+    /// Whether our leaf key is older than `every`
+    ///
+    /// A group never rotated is timed from the first check (which records it),
+    /// so turning on a schedule doesn't rotate every group at once.
+    pub fn key_rotation_due(&mut self, every: chrono::Duration) -> bool {
+        let now = chrono::Utc::now();
+        let since = self
+            .last_key_rotation
+            .as_deref()
+            .or(self.rotation_scheduled_since.as_deref())
+            .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok());
+        match since {
+            Some(t) => now - t.with_timezone(&chrono::Utc) >= every,
+            None => {
+                self.rotation_scheduled_since = Some(now.to_rfc3339());
+                false
+            }
+        }
+    }
+
+    /// Share of recent messages that failed
+    pub fn failure_rate(&self) -> f64 {
+        if self.recent.is_empty() {
+            return 0.0;
+        }
+        self.recent.iter().filter(|ok| !**ok).count() as f64 / self.recent.len() as f64
+    }
+
+    /// Assess the group; `pending_commit` and `epoch_drift` come from the MLS state
+    pub fn verdict(&self, pending_commit: bool, epoch_drift: bool) -> Verdict {
+        if epoch_drift {
+            return Verdict::Forked("stored group epoch differs from the MLS state".to_string());
+        }
+        if self.consecutive_failures >= FORK_STREAK {
+            let reason = if self.epoch_mismatches > 0 {
+                format!("last {} messages failed, including epoch mismatches", self.consecutive_failures)
+            } else {
+                format!("last {} messages failed to decrypt", self.consecutive_failures)
+            };
+            return Verdict::Forked(reason);
+        }
+        if pending_commit {
+            return Verdict::Degraded("a commit we created was never merged".to_string());
+        }
+        let rate = self.failure_rate();
+        if rate > DEGRADED_RATE {
+            return Verdict::Degraded(format!("{:.0}% of recent messages failed", rate * 100.0));
+        }
+        Verdict::Healthy
+    }
+}
+
+/// What the MLS state itself says about a group
+pub struct MlsState {
+    pub epoch: u64,
+    /// A commit we created is waiting to be merged
+    pub pending_commit: bool,
+}
+
+pub fn mls_state(mdk: &MDK<MdkSqliteStorage>, group_id: &GroupId) -> Result<MlsState> {
+    let group = load_mls_group(mdk, group_id)?;
+    Ok(MlsState {
+        epoch: group.epoch().as_u64(),
+        pending_commit: group.pending_commit().is_some(),
+    })
+}
+
+/// Contents of `marmot.health.json`, keyed by MLS group ID (hex)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HealthLog {
+    #[serde(default)]
+    pub groups: BTreeMap<String, GroupHealth>,
+}
+
+impl HealthLog {
+    pub fn config_path(db_path: &Path) -> PathBuf {
+        db_path.with_extension("health.json")
+    }
+
+    /// Load the health log (missing or unreadable file = fresh counters)
+    pub fn load(db_path: &Path) -> Self {
+        std::fs::read_to_string(Self::config_path(db_path))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    /// Save atomically with owner-only permissions
+    pub fn save(&self, db_path: &Path) -> Result<()> {
+        crate::storage::save_json_atomic(&Self::config_path(db_path), self, crate::storage::PRIVATE_FILE_MODE)
+    }
+
+    pub fn group(&mut self, group_id: &str) -> &mut GroupHealth {
+        self.groups.entry(group_id.to_string()).or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify(&mdk_core::Error::CannotDecryptOwnMessage), None);
+    }
+
+    #[test]
+    fn test_failed_event_that_later_decrypts_counts() {
+        let mut health = GroupHealth::default();
+        for i in 0..FORK_STREAK {
+            health.record_failure(&format!("e{}", i), FailureKind::EpochMismatch, "epoch");
+        }
+        assert!(matches!(health.verdict(false, false), Verdict::Forked(_)));
+
+        // The missing commit arrived and a previously failed message now decrypts
+        health.record_success("e0");
+        assert_eq!(health.consecutive_failures, 0);
+        assert_eq!(health.processed, 1);
+        assert!(!matches!(health.verdict(false, false), Verdict::Forked(_)));
+
+        // Fetching it again doesn't count twice
+        health.record_success("e0");
+        assert_eq!(health.processed, 1);
+    }
+
+    #[test]
+    fn test_duplicates_counted_once() {
+        let mut health = GroupHealth::default();
+        for _ in 0..10 {
+            health.record_failure("e1", FailureKind::Decryption, "boom");
+        }
+        assert_eq!(health.failed, 1);
+        assert_eq!(health.consecutive_failures, 1);
+    }
+
+    #[test]
+    fn test_verdicts() {
+        let mut health = GroupHealth::default();
+        for i in 0..8 {
+            health.record_success(&format!("ok{}", i));
+        }
+        assert!(health.verdict(false, false).is_healthy());
+        assert!(matches!(health.verdict(true, false), Verdict::Degraded(_)));
+        assert!(matches!(health.verdict(false, true), Verdict::Forked(_)));
+
+        for i in 0..3 {
+            health.record_failure(&format!("bad{}", i), FailureKind::Decryption, "x");
+        }
+        assert!(matches!(health.verdict(false, false), Verdict::Degraded(_)));
+
+        for i in 3..5 {
+            health.record_failure(&format!("bad{}", i), FailureKind::EpochMismatch, "epoch");
+        }
+        match health.verdict(false, false) {
+            Verdict::Forked(reason) => assert!(reason.contains("epoch")),
+            other => panic!("expected fork, got {:?}", other),
+        }
+
+        // One good message ends the streak
+        health.record_success("ok-late");
+        assert!(!matches!(health.verdict(false, false), Verdict::Forked(_)));
+    }
+
+    #[test]
+    fn test_key_rotation_due() {
+        let mut health = GroupHealth::default();
+        // The first check only starts the clock
+        assert!(!health.key_rotation_due(chrono::Duration::days(7)));
+        assert!(health.rotation_scheduled_since.is_some());
+        assert!(!health.key_rotation_due(chrono::Duration::days(7)));
+        health.rotation_scheduled_since = Some((chrono::Utc::now() - chrono::Duration::days(8)).to_rfc3339());
+        assert!(health.key_rotation_due(chrono::Duration::days(7)));
+
+        health.record_key_rotation();
+        assert!(!health.key_rotation_due(chrono::Duration::days(7)));
+        health.last_key_rotation = Some((chrono::Utc::now() - chrono::Duration::days(8)).to_rfc3339());
+        assert!(health.key_rotation_due(chrono::Duration::days(7)));
+    }
+}
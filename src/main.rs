@@ -6,7 +6,20 @@
 // - Direct nsec (legacy, convenient for development)
 // - NIP-46 remote signing via bunker:// (recommended for production/agents)
 
+mod accounts;
+mod backup;
+mod blocklist;
+mod config;
+mod contacts;
+mod health;
+mod metadata;
+mod nip05;
 mod nip46;
+mod relays;
+mod retention;
+mod storage;
+mod tui;
+mod verification;
 
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
@@ -15,25 +28,57 @@ use mdk_sqlite_storage::MdkSqliteStorage;
 use nostr::prelude::*;
 use nostr_sdk::prelude::*;
 use serde::Serialize;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
 use std::sync::Arc;
 use std::io::Write;
 use tokio::sync::Mutex;
 
-use nip46::{AuditLog, BunkerConfig, MarmotSigner, SigningMode};
+use accounts::{AccountRecord, AccountSigner, Accounts};
+use backup::Backup;
+use blocklist::BlockList;
+use config::{ConfigFile, Profile};
+use contacts::Contacts;
+use health::{HealthLog, Verdict};
+use metadata::ProfileCache;
+use nip05::Nip05Resolver;
+use nip46::audit::{AuditFilter, RotationPolicy};
+use nip46::{
+    connect_bunker, open_nostr_connect, AuditLog, BunkerConfig, MarmotSigner, NcryptsecConfig, NostrConnectPairing,
+    OfflineQueue, PassphraseSource, PolicyViolation, SigningMode, SigningPolicy,
+};
+use relays::{AuthPolicy, ConnectOptions, NetworkOptions};
+use retention::{GroupRetention, Retention, RetentionSettings};
+use storage::DbKeySource;
+use verification::{MemberStatus, Verifications};
+
+/// Database path used when neither `--db` nor the profile sets one
+const DEFAULT_DB: &str = "~/.marmot-cli/marmot.db";
+
+/// Relays used when neither `--relays` nor the profile sets them
+const DEFAULT_RELAYS: &str = "wss://relay.damus.io,wss://relay.primal.net,wss://nos.lol";
 
 /// JSON payload for --on-message callback
 #[derive(Serialize)]
 struct MessagePayload {
+    /// npub of the local account that received the message
+    account: String,
     message_id: String,
     group_id: String,
     group_name: String,
     sender: String,
     sender_hex: String,
+    /// Sender's alias from `contacts`, if any
+    sender_alias: Option<String>,
+    /// Sender's kind-0 display name (or name), if they published one
+    sender_name: Option<String>,
     content: String,
     timestamp: u64,
     is_me: bool,
+    /// Sender's verification state: verified, unverified or key_changed
+    sender_verification: String,
 }
 
 #[derive(Parser)]
@@ -41,9 +86,25 @@ struct MessagePayload {
 #[command(about = "E2E encrypted messaging over Nostr using Marmot/MLS protocol")]
 #[command(version)]
 struct Cli {
-    /// Path to the database file
-    #[arg(short, long, default_value = "~/.marmot-cli/marmot.db")]
-    db: String,
+    /// Config file with named profiles
+    #[arg(long, env = "MARMOT_CONFIG", default_value = config::DEFAULT_CONFIG_PATH)]
+    config: String,
+
+    /// Profile from the config file to use (defaults to `default_profile`)
+    #[arg(short = 'p', long, env = "MARMOT_PROFILE")]
+    profile: Option<String>,
+
+    /// Account to use (label or npub, see `accounts list`)
+    #[arg(short = 'a', long, env = "MARMOT_ACCOUNT")]
+    account: Option<String>,
+
+    /// Path to the database file [default: ~/.marmot-cli/marmot.db]
+    #[arg(short, long)]
+    db: Option<String>,
+
+    /// Database encryption key source: keyring, prompt, file:<path> or env:<VAR>
+    #[arg(long, env = "MARMOT_DB_KEY")]
+    db_key: Option<String>,
 
     /// Nostr private key (nsec or hex) — use bunker mode for production
     #[arg(short, long, env = "NOSTR_NSEC", hide_env_values = true)]
@@ -53,13 +114,37 @@ struct Cli {
     #[arg(short, long, env = "NOSTR_BUNKER")]
     bunker: Option<String>,
 
-    /// Relay URLs (comma-separated)
-    #[arg(short, long, default_value = "wss://relay.damus.io,wss://relay.primal.net,wss://nos.lol")]
-    relays: String,
+    /// Read the ncryptsec passphrase from this file descriptor instead of prompting
+    #[arg(long, env = "MARMOT_PASSPHRASE_FD")]
+    passphrase_fd: Option<i32>,
+
+    /// Relay URLs (comma-separated) [default: wss://relay.damus.io,wss://relay.primal.net,wss://nos.lol]
+    #[arg(short, long)]
+    relays: Option<String>,
+
+    /// Suppress relay connection logs (`--quiet=false` overrides a profile's `quiet = true`)
+    #[arg(short, long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    quiet: Option<bool>,
+
+    /// SOCKS5 proxy for relay and bunker connections (e.g. socks5://127.0.0.1:9050 for Tor)
+    #[arg(long, env = "MARMOT_PROXY")]
+    proxy: Option<String>,
 
-    /// Suppress relay connection logs
-    #[arg(short, long, default_value_t = false)]
-    quiet: bool,
+    /// Relays we may answer NIP-42 AUTH challenges for (comma-separated, `*` for all)
+    #[arg(long, env = "MARMOT_AUTH_RELAYS")]
+    auth_relays: Option<String>,
+
+    /// Number of relays that must be connected before a command runs [default: 1]
+    #[arg(long, env = "MARMOT_CONNECT_QUORUM")]
+    connect_quorum: Option<usize>,
+
+    /// Maximum seconds to wait for the relay quorum [default: 10]
+    #[arg(long, env = "MARMOT_CONNECT_TIMEOUT")]
+    connect_timeout: Option<u64>,
+
+    /// Seconds to wait for each bunker (NIP-46) response [default: 30]
+    #[arg(long, env = "MARMOT_BUNKER_TIMEOUT")]
+    bunker_timeout: Option<u64>,
 
     #[command(subcommand)]
     command: Commands,
@@ -75,6 +160,22 @@ enum Commands {
         /// Use NIP-46 bunker for remote signing (recommended for agents)
         #[arg(long)]
         bunker: Option<String>,
+        /// Import an existing NIP-49 encrypted key (ncryptsec1...)
+        #[arg(long, conflicts_with_all = ["nsec", "bunker"])]
+        ncryptsec: Option<String>,
+        /// Store the key encrypted with a passphrase (NIP-49) instead of passing --nsec each run
+        #[arg(long, conflicts_with = "bunker")]
+        encrypt_key: bool,
+        /// Pair with a signer app by showing a nostrconnect:// URI and QR code
+        /// (optionally: comma-separated relays to pair over)
+        #[arg(
+            long,
+            value_name = "RELAYS",
+            num_args = 0..=1,
+            default_missing_value = nip46::pairing::DEFAULT_PAIRING_RELAY,
+            conflicts_with_all = ["nsec", "bunker", "ncryptsec", "encrypt_key"]
+        )]
+        nostrconnect: Option<String>,
     },
     /// Show current identity info
     Whoami,
@@ -82,7 +183,7 @@ enum Commands {
     PublishKeyPackage,
     /// Create a new group/chat with another user
     CreateChat {
-        /// The npub of the user to chat with
+        /// The user to chat with (npub, hex, contact alias or NIP-05 name@domain)
         npub: String,
         /// Optional group name
         #[arg(short, long)]
@@ -90,9 +191,32 @@ enum Commands {
     },
     /// List all groups/chats
     ListChats,
+    /// Show stored messages of a group
+    History {
+        /// Group ID (hex, from list-chats, can be partial) or a contact alias
+        #[arg(short, long)]
+        group: String,
+        /// Number of most recent messages to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Compare safety numbers with a group member and mark them verified
+    Verify {
+        /// Group ID (hex, from list-chats). Can be partial.
+        #[arg(short, long)]
+        group: String,
+        /// Member npub, hex or contact alias (omit to list members and their status)
+        member: Option<String>,
+        /// Record the member as verified after comparing the safety number
+        #[arg(long, requires = "member", conflicts_with = "unmark")]
+        mark: bool,
+        /// Forget an earlier verification
+        #[arg(long, requires = "member")]
+        unmark: bool,
+    },
     /// Send a message to a group
     Send {
-        /// Group ID (hex, from list-chats). Can be partial.
+        /// Group ID (hex, from list-chats, can be partial) or a contact alias for your chat with them
         #[arg(short, long)]
         group: String,
         /// Message content
@@ -113,10 +237,54 @@ enum Commands {
         /// Script/command to execute for each message (receives JSON via stdin)
         #[arg(long)]
         on_message: Option<String>,
+        /// Also serve every account in `accounts list`, concurrently (each with its own relay connections)
+        #[arg(long)]
+        all_accounts: bool,
+        /// Publish a self-update commit in each group this often (e.g. 24h, 7d)
+        #[arg(long, value_name = "DURATION")]
+        rotate_every: Option<String>,
+    },
+    /// Interactive terminal chat: chat list, live messages, replies and reactions
+    Tui,
+    /// Rotate our MLS leaf key (self-update commit) for post-compromise security
+    RotateKeys {
+        /// Group ID (hex, from list-chats). Can be partial.
+        #[arg(short, long, required_unless_present = "all", conflicts_with = "all")]
+        group: Option<String>,
+        /// Rotate in every group
+        #[arg(long)]
+        all: bool,
+    },
+    /// Drop welcomes from a user and hide their messages
+    Block {
+        /// User to block (npub, hex, contact alias or NIP-05 name@domain)
+        user: String,
+        /// Also publish the block list as a public NIP-51 mute list (kind 10000)
+        #[arg(long)]
+        publish: bool,
+    },
+    /// Remove a user from the block list
+    Unblock {
+        /// User to unblock (npub, hex, contact alias or NIP-05 name@domain)
+        user: String,
+        /// Also publish the updated NIP-51 mute list
+        #[arg(long)]
+        publish: bool,
+    },
+    /// List blocked users
+    Blocked,
+    /// Invite more users into an existing group
+    AddMembers {
+        /// Group ID (hex, from list-chats). Can be partial.
+        #[arg(short, long)]
+        group: String,
+        /// Users to add (npub, hex, contact alias or NIP-05 name@domain)
+        #[arg(required = true)]
+        members: Vec<String>,
     },
     /// Fetch key package for a user
     FetchKeyPackage {
-        /// The npub to fetch key package for
+        /// The user to fetch the key package for (npub, hex, contact alias or NIP-05 name@domain)
         npub: String,
     },
     /// Migrate from direct nsec to NIP-46 bunker signing (atomic)
@@ -125,7 +293,11 @@ enum Commands {
         bunker: String,
     },
     /// Show signing mode and bunker connection status
-    SignerStatus,
+    SignerStatus {
+        /// List the NIP-46 permissions requested from the bunker
+        #[arg(long)]
+        permissions: bool,
+    },
     /// Leave a group/chat (removes yourself from the group)
     LeaveChat {
         /// Group ID (hex, from list-chats). Can be partial.
@@ -135,6 +307,229 @@ enum Commands {
         #[arg(long)]
         force: bool,
     },
+    /// Show or edit profiles in the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Write an encrypted snapshot of the database, bunker config and audit log
+    Backup {
+        /// Output file
+        file: PathBuf,
+        /// Read the backup passphrase from this env var instead of prompting
+        #[arg(long)]
+        passphrase_env: Option<String>,
+    },
+    /// Replace the current state with a backup (validates identity and schema first)
+    Restore {
+        /// Backup file created by `backup`
+        file: PathBuf,
+        /// Read the backup passphrase from this env var instead of prompting
+        #[arg(long)]
+        passphrase_env: Option<String>,
+        /// Restore even if the identity or schema version doesn't match
+        #[arg(long)]
+        force: bool,
+    },
+    /// Local aliases for the people you talk to
+    Contacts {
+        #[command(subcommand)]
+        command: ContactsCommand,
+    },
+    /// Manage multiple identities (each with its own database and signer)
+    Accounts {
+        #[command(subcommand)]
+        command: AccountsCommand,
+    },
+    /// Database maintenance
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+    /// Relay diagnostics
+    Relays {
+        #[command(subcommand)]
+        command: RelaysCommand,
+    },
+    /// Group diagnostics and settings
+    Group {
+        #[command(subcommand)]
+        command: GroupCommand,
+    },
+    /// Inspect the signing audit log
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum GroupCommand {
+    /// Decryption failure rates, epoch mismatches, unmerged commits and fork warnings
+    Health {
+        /// Group ID (hex, from list-chats). Can be partial. Default: all groups
+        #[arg(short, long)]
+        group: Option<String>,
+    },
+    /// Show or set how long decrypted messages are kept locally
+    Retention {
+        /// Group ID (hex, from list-chats). Can be partial.
+        #[arg(short, long)]
+        group: String,
+        /// Keep messages this long (e.g. 24h, 7d) or "off" to keep them forever
+        keep: Option<String>,
+        /// Also tag messages we send with a matching NIP-40 expiration
+        #[arg(long, requires = "keep")]
+        expire_outgoing: bool,
+        /// Drop this group's setting and use the profile's message_retention
+        #[arg(long, conflicts_with = "keep")]
+        clear: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum RelaysCommand {
+    /// Probe every configured relay (global, per-group, bunker)
+    Status,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print the config file (secrets redacted)
+    Show,
+    /// Print one key from the selected profile
+    Get {
+        /// Key name (e.g. db, relays, bunker, on_message, auto_accept)
+        key: String,
+    },
+    /// Set one key in the selected profile (empty value unsets it)
+    Set {
+        /// Key name, or `default_profile`
+        key: String,
+        /// New value (lists are comma-separated)
+        value: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum AccountsCommand {
+    /// Add an account (generates a new key unless --nsec or --bunker is given)
+    Add {
+        /// Existing nsec for this account
+        #[arg(long)]
+        nsec: Option<String>,
+        /// Bunker URI for NIP-46 remote signing
+        #[arg(long)]
+        bunker: Option<String>,
+        /// Store the key as a NIP-49 ncryptsec instead of a plain nsec
+        #[arg(long, conflicts_with = "bunker")]
+        encrypt_key: bool,
+        /// Short name to refer to the account
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// List accounts (→ marks the active one)
+    List,
+    /// Make an account the default for subsequent commands
+    Use {
+        /// Label or npub
+        account: String,
+    },
+    /// Delete an account's directory, database and keys
+    Remove {
+        /// Label or npub
+        account: String,
+        /// Skip confirmation prompt
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ContactsCommand {
+    /// Remember a user under a short alias
+    Add {
+        /// Alias (letters, digits, '-', '_' and '.')
+        alias: String,
+        /// Their npub, hex public key or NIP-05 identifier (name@domain)
+        npub: String,
+    },
+    /// List contacts
+    List,
+    /// Forget an alias
+    Remove {
+        alias: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum DbCommand {
+    /// Report schema version, integrity, group and pending welcome counts
+    Check,
+    /// Encrypt an existing unencrypted database in place (uses --db-key)
+    Encrypt {
+        /// Keep the plaintext original as <db>.plaintext.bak
+        #[arg(long)]
+        keep_plaintext: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum AuditCommand {
+    /// Check the audit log's hash chain and signatures for tampering or truncation
+    Verify {
+        /// Warn instead of failing when rotated archives were deleted
+        #[arg(long)]
+        allow_missing_archives: bool,
+    },
+    /// Print audit entries, optionally filtered
+    Show {
+        /// Only entries newer than this (24h, 7d, 2w, YYYY-MM-DD or RFC 3339)
+        #[arg(long)]
+        since: Option<String>,
+        /// Only this operation (a trailing * matches a prefix, e.g. sign_*)
+        #[arg(long)]
+        operation: Option<String>,
+        /// Only entries about events of this kind
+        #[arg(long)]
+        kind: Option<u16>,
+        /// Also read rotated archives
+        #[arg(long)]
+        all: bool,
+        /// Print raw entries as JSON Lines
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+impl Commands {
+    /// Whether this command talks to relays (purely local commands skip connecting)
+    fn needs_network(&self) -> bool {
+        !matches!(
+            self,
+            Commands::Whoami
+                | Commands::ListChats
+                | Commands::History { .. }
+                | Commands::Verify { .. }
+                | Commands::Group { .. }
+                | Commands::Blocked
+                | Commands::Block { publish: false, .. }
+                | Commands::Unblock { publish: false, .. }
+                | Commands::Init { .. }
+        )
+    }
+}
+
+/// Settings shared by every account `listen` serves
+struct ListenOptions {
+    /// Poll interval in seconds
+    interval: u64,
+    on_message: Option<String>,
+    rotate_every: Option<chrono::Duration>,
+    retention: Retention,
+    profile: Profile,
+    /// Prefix poll summaries with the account (`--all-accounts`)
+    label_accounts: bool,
 }
 
 struct MarmotCli {
@@ -142,48 +537,220 @@ struct MarmotCli {
     mdk: MDK<MdkSqliteStorage>,
     relays: Vec<RelayUrl>,
     client: Client,
-    #[allow(dead_code)]
     db_path: PathBuf,
+    /// SQLCipher key of the store, for maintenance MDK doesn't expose (message purge)
+    db_key: Option<[u8; 32]>,
+    proxy: Option<SocketAddr>,
+    bunker_timeout: std::time::Duration,
+    /// Work parked while the bunker is offline (only in `listen`)
+    offline_queue: Option<std::sync::Mutex<OfflineQueue>>,
+    /// Welcomes stored per sender per hour
+    welcomes_per_hour: u32,
 }
 
 impl MarmotCli {
     async fn new(
         db_path: PathBuf,
+        db_key: Option<DbKeySource>,
         nsec: Option<String>,
         bunker_uri: Option<String>,
-        relay_urls: Vec<String>,
+        passphrase: &PassphraseSource,
+        network: NetworkOptions,
     ) -> Result<Self> {
-        if let Some(parent) = db_path.parent() {
-            std::fs::create_dir_all(parent)?;
+        let (signer, mdk, db_key) = Self::open_account(&db_path, db_key, nsec, bunker_uri, passphrase, &network).await?;
+        let NetworkOptions { relay_urls, auth, proxy, bunker_timeout, connect } = network;
+
+        let relays: Vec<RelayUrl> = relay_urls
+            .iter()
+            .filter_map(|url| RelayUrl::parse(url).ok())
+            .collect();
+
+        let client = signer.build_client(auth).await?;
+        if let Some(opts) = connect {
+            for relay in &relays {
+                client.add_relay(relay.as_str()).await?;
+            }
+            client.connect().await;
+
+            let quorum = opts.effective_quorum(relays.len());
+            let connected = relays::wait_for_quorum(&client, opts).await;
+            if connected == 0 {
+                eprintln!("⚠️  No relays connected after {}s — commands will likely fail", opts.timeout.as_secs());
+            } else if connected < quorum {
+                eprintln!("⚠️  Only {}/{} required relays connected, continuing", connected, quorum);
+            } else {
+                tracing::debug!("{} relay(s) connected", connected);
+            }
         }
 
-        let audit = Arc::new(Mutex::new(AuditLog::new(&db_path)));
+        Ok(Self {
+            signer,
+            mdk,
+            relays,
+            client,
+            db_path,
+            db_key,
+            proxy,
+            bunker_timeout,
+            offline_queue: None,
+            welcomes_per_hour: blocklist::DEFAULT_WELCOMES_PER_HOUR,
+        })
+    }
 
-        let signing_mode = SigningMode::resolve(
-            nsec.as_deref(),
-            bunker_uri.as_deref(),
-            &db_path,
-        )?;
+    /// Override the per-sender welcome rate cap
+    fn with_welcome_cap(mut self, per_hour: Option<u32>) -> Self {
+        if let Some(per_hour) = per_hour {
+            self.welcomes_per_hour = per_hour;
+        }
+        self
+    }
 
-        let signer = MarmotSigner::new(signing_mode, &db_path, audit).await?;
+    /// Park undecryptable gift wraps and unsigned events instead of failing
+    /// while the bunker is offline; they are retried on every poll
+    fn enable_offline_queue(&mut self) {
+        self.offline_queue = Some(std::sync::Mutex::new(OfflineQueue::default()));
+    }
 
-        let storage = MdkSqliteStorage::new_unencrypted(&db_path)
-            .context("Failed to create SQLite storage")?;
-        let mdk = MDK::new(storage);
+    /// Queue for deferred work, if enabled and the bunker is currently down
+    fn offline_queue(&self) -> Option<std::sync::MutexGuard<'_, OfflineQueue>> {
+        if self.signer.is_online() {
+            return None;
+        }
+        self.offline_queue.as_ref()?.lock().ok()
+    }
 
-        let relays: Vec<RelayUrl> = relay_urls
+    /// Sign an event, or queue it if the bunker is offline and queueing is enabled
+    async fn sign_or_defer(&self, builder: EventBuilder) -> Result<Option<Event>> {
+        let unsigned = builder.build(self.signer.public_key());
+        match self.signer.sign_unsigned(unsigned.clone()).await {
+            Ok(event) => Ok(Some(event)),
+            // Retrying later won't change a policy decision
+            Err(e) if e.is::<PolicyViolation>() => Err(e),
+            Err(e) => match self.offline_queue() {
+                Some(mut queue) => {
+                    queue.defer_outgoing(unsigned);
+                    eprintln!("⏸  Bunker offline — event queued until it returns");
+                    Ok(None)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Record where an event was published (per-relay results) in the audit log
+    async fn audit_publish(&self, operation: &str, event: &Event, output: &Output<EventId>, group_id: Option<&GroupId>) {
+        let failed: serde_json::Map<String, serde_json::Value> = output
+            .failed
             .iter()
-            .filter_map(|url| RelayUrl::parse(url).ok())
+            .map(|(relay, error)| (relay.to_string(), error.clone().into()))
             .collect();
+        let mut context = serde_json::json!({
+            "event_id": event.id.to_hex(),
+            "kind": event.kind.as_u16(),
+            "relays_ok": output.success.iter().map(|r| r.to_string()).collect::<Vec<_>>(),
+            "relays_failed": failed,
+        });
+        if let Some(group_id) = group_id {
+            context["group_id"] = hex::encode(group_id.as_slice()).into();
+        }
+        let details = format!("published to {}/{} relays", output.success.len(), output.success.len() + output.failed.len());
+        self.signer.audit(operation, &details, context).await;
+    }
+
+    /// Sign and publish events queued while the bunker was offline
+    async fn flush_offline_queue(&self) -> Result<usize> {
+        let Some(queue) = &self.offline_queue else { return Ok(0) };
+        let (unsent, pending) = queue
+            .lock()
+            .map(|mut q| (q.take_unsent(), q.take_outgoing()))
+            .unwrap_or_default();
+        let mut published = 0;
+
+        // Events signed on an earlier poll go out first, keeping the original order
+        let mut unsent = unsent.into_iter();
+        while let Some(event) = unsent.next() {
+            if let Err(e) = self.publish_queued(&event).await {
+                if let Ok(mut q) = queue.lock() {
+                    q.defer_unsent(event);
+                    unsent.for_each(|later| q.defer_unsent(later));
+                    pending.into_iter().for_each(|u| q.defer_outgoing(u));
+                }
+                return Err(e);
+            }
+            published += 1;
+        }
+
+        let mut remaining = pending.into_iter();
+        while let Some(unsigned) = remaining.next() {
+            match self.signer.sign_unsigned(unsigned.clone()).await {
+                Ok(event) => {
+                    if let Err(e) = self.publish_queued(&event).await {
+                        // Keep the signed event and everything after it for the next poll
+                        if let Ok(mut q) = queue.lock() {
+                            q.defer_unsent(event);
+                            remaining.for_each(|u| q.defer_outgoing(u));
+                        }
+                        return Err(e);
+                    }
+                    published += 1;
+                }
+                Err(e) if e.is::<PolicyViolation>() => {
+                    eprintln!("⚠️  Dropping queued event: {}", e);
+                }
+                Err(_) => {
+                    // Still offline: put this and everything after it back
+                    if let Ok(mut q) = queue.lock() {
+                        q.defer_outgoing(unsigned);
+                        remaining.for_each(|u| q.defer_outgoing(u));
+                    }
+                    break;
+                }
+            }
+        }
+        Ok(published)
+    }
+
+    /// Publish an event from the offline queue; fails unless a relay accepted it
+    async fn publish_queued(&self, event: &Event) -> Result<()> {
+        let output = self.client.send_event(event).await?;
+        if output.success.is_empty() {
+            anyhow::bail!("No relay accepted queued event {}", event.id);
+        }
+        self.audit_publish("queued_event_published", event, &output, None).await;
+        Ok(())
+    }
 
-        let client = signer.build_client().await?;
-        for relay in &relays {
-            client.add_relay(relay.as_str()).await?;
+    /// Set up the signer and MDK storage for one identity
+    async fn open_account(
+        db_path: &Path,
+        db_key: Option<DbKeySource>,
+        nsec: Option<String>,
+        bunker_uri: Option<String>,
+        passphrase: &PassphraseSource,
+        network: &NetworkOptions,
+    ) -> Result<(MarmotSigner, MDK<MdkSqliteStorage>, Option<[u8; 32]>)> {
+        if let Some(parent) = db_path.parent() {
+            std::fs::create_dir_all(parent)?;
         }
-        client.connect().await;
-        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
 
-        Ok(Self { signer, mdk, relays, client, db_path })
+        let audit = Arc::new(Mutex::new(AuditLog::new(db_path)));
+
+        let signing_mode = SigningMode::resolve(
+            nsec.as_deref(),
+            bunker_uri.as_deref(),
+            db_path,
+            passphrase,
+        )?;
+
+        let signer =
+            MarmotSigner::new(signing_mode, db_path, audit, network.proxy, network.bunker_timeout, &network.auth).await?;
+
+        let key = db_key
+            .map(|source| source.load_key(db_path, !db_path.exists()))
+            .transpose()?;
+        storage::ensure_compatible(db_path, key.as_ref())?;
+        let storage = storage::open_storage(db_path, key)?;
+        Ok((signer, MDK::new(storage), key))
     }
 
     fn whoami(&self) {
@@ -214,8 +781,11 @@ impl MarmotCli {
             .tags(tags)
             .tag(Tag::custom(TagKind::custom("encoding"), ["hex"]));
 
-        let event = self.signer.sign_event(builder).await?;
+        let Some(event) = self.sign_or_defer(builder).await? else {
+            return Ok(());
+        };
         let output = self.client.send_event(&event).await?;
+        self.audit_publish("key_package_published", &event, &output, None).await;
 
         println!("✓ Key package published!");
         println!("  Event ID: {}", output.id());
@@ -223,18 +793,52 @@ impl MarmotCli {
         Ok(())
     }
 
-    async fn fetch_key_package(&self, npub: &str) -> Result<Event> {
-        let pubkey = if npub.starts_with("npub") {
-            PublicKey::from_bech32(npub)?
-        } else {
-            PublicKey::from_hex(npub)?
-        };
-        println!("Fetching key package for {}...", &pubkey.to_bech32().unwrap()[..20]);
+    /// Resolve a user given as contact alias, npub, hex or NIP-05 identifier
+    async fn resolve_user(&self, user: &str) -> Result<nip05::Resolved> {
+        if nip05::is_identifier(user) {
+            let resolved = Nip05Resolver::new(&self.db_path, self.proxy)?.resolve(user).await?;
+            println!("✓ {} is {}", user, resolved.public_key.to_bech32()?);
+            return Ok(resolved);
+        }
+        let public_key = Contacts::load(&self.db_path)?.resolve(user)?;
+        Ok(nip05::Resolved { public_key, relays: Vec::new() })
+    }
+
+    async fn fetch_key_package(&self, user: &str) -> Result<Event> {
+        let resolved = self.resolve_user(user).await?;
+        let pubkey = resolved.public_key;
+        let contacts = Contacts::load(&self.db_path)?;
+        println!("Fetching key package for {}...", contacts.display(&pubkey));
 
+        let timeout = std::time::Duration::from_secs(10);
         let filter = Filter::new().kind(Kind::MlsKeyPackage).author(pubkey).limit(1);
-        let events = self.client
-            .fetch_events(filter, std::time::Duration::from_secs(10))
-            .await?;
+        let events = if resolved.relays.is_empty() {
+            self.client.fetch_events(filter, timeout).await?
+        } else {
+            // Also search the relays their NIP-05 domain advertises. They join the
+            // pool read-only, so nothing we publish goes to them, and are
+            // removed again once the key package has been fetched.
+            let mut urls: Vec<String> = self.relays.iter().map(|r| r.to_string()).collect();
+            let mut added = Vec::new();
+            for relay in &resolved.relays {
+                if self.client.add_read_relay(relay.as_str()).await? {
+                    added.push(relay.clone());
+                }
+                if let Err(e) = self.client.try_connect_relay(relay.as_str(), timeout).await {
+                    tracing::debug!("Advertised relay {} unreachable: {}", relay, e);
+                }
+                if !urls.contains(&relay.to_string()) {
+                    urls.push(relay.to_string());
+                }
+            }
+            let fetched = self.client.fetch_events_from(urls, filter, timeout).await;
+            for relay in &added {
+                if let Err(e) = self.client.remove_relay(relay.as_str()).await {
+                    tracing::debug!("Failed to remove advertised relay {}: {}", relay, e);
+                }
+            }
+            fetched?
+        };
 
         let event = events.first().cloned()
             .context("No key package found for this user. They need to run `publish-key-package` first.")?;
@@ -246,7 +850,11 @@ impl MarmotCli {
         let other_key_package_event = self.fetch_key_package(npub).await?;
         let other_pubkey = other_key_package_event.pubkey;
         let group_name = name.unwrap_or_else(|| {
-            format!("Chat with {}", &other_pubkey.to_bech32().unwrap()[..20])
+            let contacts = Contacts::load(&self.db_path).unwrap_or_default();
+            match contacts.alias_for(&other_pubkey) {
+                Some(alias) => format!("Chat with {}", alias),
+                None => format!("Chat with {}", &other_pubkey.to_bech32().unwrap()[..20]),
+            }
         });
         println!("Creating group '{}'...", group_name);
 
@@ -261,6 +869,7 @@ impl MarmotCli {
         for welcome_rumor in &result.welcome_rumors {
             let gift_wrap = self.signer.gift_wrap(&other_pubkey, welcome_rumor.clone()).await?;
             let send_result = self.client.send_event(&gift_wrap).await?;
+            self.audit_publish("welcome_published", &gift_wrap, &send_result, Some(&result.group.mls_group_id)).await;
             println!("✓ Welcome sent to {} relays", send_result.success.len());
         }
 
@@ -281,6 +890,9 @@ impl MarmotCli {
             println!("No chats found. Create one with: marmot-cli create-chat <npub>");
             return Ok(());
         }
+        let verifications = Verifications::load(&self.db_path)?;
+        let contacts = Contacts::load(&self.db_path)?;
+        let profiles = ProfileCache::load(&self.db_path);
         println!("=== Your Chats ({}) ===\n", groups.len());
         for group in groups {
             let mls_id = hex::encode(group.mls_group_id.as_slice());
@@ -288,11 +900,20 @@ impl MarmotCli {
             println!("   MLS ID: {} (use first 8+ chars with -g)", mls_id);
             println!("   Nostr ID: {}", hex::encode(&group.nostr_group_id));
             if let Ok(members) = self.mdk.get_members(&group.mls_group_id) {
+                let statuses = self.member_statuses(&group.mls_group_id, &verifications);
                 println!("   Members: {}", members.len());
                 for member in &members {
-                    let bech32 = member.to_bech32().unwrap_or_else(|_| member.to_string());
                     let is_me = *member == self.signer.public_key();
-                    println!("     {} {}", if is_me { "→" } else { " " }, &bech32[..20]);
+                    let status = if is_me {
+                        ""
+                    } else {
+                        match statuses.get(member).copied().unwrap_or(MemberStatus::Unverified) {
+                            MemberStatus::Verified => " ✓ verified",
+                            MemberStatus::Unverified => " (unverified)",
+                            MemberStatus::KeyChanged => " ⚠️  KEY CHANGED since verification",
+                        }
+                    };
+                    println!("     {} {}{}", if is_me { "→" } else { " " }, metadata::label(member, &contacts, &profiles), status);
                 }
             }
             if let Some(last) = &group.last_message_at {
@@ -303,7 +924,256 @@ impl MarmotCli {
         Ok(())
     }
 
+    /// Verification state of each member (empty if the MLS state can't be read)
+    fn member_statuses(&self, group_id: &GroupId, verifications: &Verifications) -> BTreeMap<PublicKey, MemberStatus> {
+        let group_hex = hex::encode(group_id.as_slice());
+        match verification::group_keys(&self.mdk, group_id) {
+            Ok(keys) => keys
+                .leaf_keys
+                .iter()
+                .map(|(member, leaf_key)| (*member, verifications.status(&group_hex, member, Some(leaf_key))))
+                .collect(),
+            Err(e) => {
+                tracing::debug!("Could not read member keys: {}", e);
+                BTreeMap::new()
+            }
+        }
+    }
+
+    /// After a commit: keep verifications of members who rotated their own key
+    fn follow_key_updates(&self, group_id: &GroupId, before: &verification::GroupKeys, verifications: &mut Verifications) {
+        let group_hex = hex::encode(group_id.as_slice());
+        let followed = match verification::group_keys(&self.mdk, group_id) {
+            Ok(after) => verifications.follow_key_updates(&group_hex, before, &after),
+            Err(e) => {
+                tracing::debug!("Could not read member keys: {}", e);
+                return;
+            }
+        };
+        if !followed.is_empty() {
+            tracing::info!("Followed key update of {} verified member(s)", followed.len());
+            if let Err(e) = verifications.save(&self.db_path) {
+                tracing::warn!("Could not save verifications: {}", e);
+            }
+        }
+    }
+
+    /// Combine failure counters with what the MLS state says about a group
+    fn group_verdict(&self, group: &mdk_storage_traits::groups::types::Group, stats: &health::GroupHealth) -> Verdict {
+        match health::mls_state(&self.mdk, &group.mls_group_id) {
+            Ok(mls) => stats.verdict(mls.pending_commit, mls.epoch != group.epoch),
+            Err(e) => {
+                tracing::debug!("Could not read MLS state: {}", e);
+                stats.verdict(false, false)
+            }
+        }
+    }
+
+    fn warn_unhealthy(name: &str, group_hex: &str, verdict: &Verdict) {
+        match verdict {
+            Verdict::Healthy => return,
+            Verdict::Degraded(reason) => eprintln!("⚠️  Group '{}' is degraded: {}", name, reason),
+            Verdict::Forked(reason) => eprintln!("❌ Group '{}' appears forked: {}", name, reason),
+        }
+        if let Some(recovery) = verdict.recovery() {
+            eprintln!("   {}", recovery);
+        }
+        eprintln!("   Details: marmot-cli group health -g {}", &group_hex[..16]);
+    }
+
+    /// `group health`: decryption failures, epoch state and recovery advice
+    fn group_health(&self, group: Option<&str>) -> Result<()> {
+        let mut groups = self.mdk.get_groups()?;
+        if let Some(partial) = group {
+            let group_id = self.resolve_group_id(partial)?;
+            groups.retain(|g| g.mls_group_id == group_id);
+        }
+        if groups.is_empty() {
+            println!("No chats found.");
+            return Ok(());
+        }
+
+        let health = HealthLog::load(&self.db_path);
+        println!("=== Group Health ({}) ===\n", groups.len());
+        for group in &groups {
+            let group_hex = hex::encode(group.mls_group_id.as_slice());
+            let stats = health.groups.get(&group_hex).cloned().unwrap_or_default();
+            let verdict = self.group_verdict(group, &stats);
+            let icon = match verdict {
+                Verdict::Healthy => "✓",
+                Verdict::Degraded(_) => "⚠️ ",
+                Verdict::Forked(_) => "❌",
+            };
+            println!("{} {} [epoch {}]", icon, group.name, group.epoch);
+            println!("   MLS ID: {}", &group_hex[..16]);
+            match health::mls_state(&self.mdk, &group.mls_group_id) {
+                Ok(mls) => {
+                    if mls.epoch != group.epoch {
+                        println!("   MLS epoch: {} (stored group record says {})", mls.epoch, group.epoch);
+                    }
+                    if mls.pending_commit {
+                        println!("   Pending commit: yes (created here, never merged)");
+                    }
+                }
+                Err(e) => println!("   MLS state: unreadable ({})", e),
+            }
+            println!(
+                "   Messages: {} processed, {} failed ({:.0}% of recent)",
+                stats.processed,
+                stats.failed,
+                stats.failure_rate() * 100.0
+            );
+            if stats.epoch_mismatches > 0 {
+                println!("   Epoch mismatches: {}", stats.epoch_mismatches);
+            }
+            if let Some(error) = &stats.last_error {
+                println!("   Last error: {} ({})", error, stats.last_error_at.as_deref().unwrap_or("?"));
+            }
+            if let Some(at) = &stats.last_success_at {
+                println!("   Last decrypted: {}", at);
+            }
+            if let Some(at) = &stats.last_key_rotation {
+                println!("   Last key rotation: {}", at);
+            }
+            match &verdict {
+                Verdict::Healthy => println!("   Status: healthy"),
+                Verdict::Degraded(reason) => println!("   Status: degraded — {}", reason),
+                Verdict::Forked(reason) => println!("   Status: FORKED — {}", reason),
+            }
+            if let Some(recovery) = verdict.recovery() {
+                println!("   → {}", recovery);
+            }
+            println!();
+        }
+        Ok(())
+    }
+
+    /// `verify`: show safety numbers and record out-of-band verification
+    async fn verify_member(&self, group: &str, member: Option<&str>, mark: bool, unmark: bool) -> Result<()> {
+        let group_id = self.resolve_group_id(group)?;
+        let group_hex = hex::encode(group_id.as_slice());
+        let group_name = self.mdk.get_groups()?
+            .into_iter()
+            .find(|g| g.mls_group_id == group_id)
+            .map(|g| g.name)
+            .unwrap_or_default();
+        let keys = verification::group_keys(&self.mdk, &group_id)?;
+        let me = self.signer.public_key();
+        let my_leaf = keys.leaf_keys.get(&me).context("We are not a member of this group")?;
+        let mut verifications = Verifications::load(&self.db_path)?;
+
+        println!("=== Verify '{}' ===\n", group_name);
+        println!("Epoch {} code (same for every member in this epoch):", keys.epoch);
+        println!("  {}", verification::format_digits(&verification::epoch_code(&keys.epoch_authenticator), 5).join(""));
+
+        let Some(member) = member else {
+            println!("\nMembers:");
+            for (pubkey, leaf_key) in keys.leaf_keys.iter().filter(|(pk, _)| **pk != me) {
+                let status = verifications.status(&group_hex, pubkey, Some(leaf_key));
+                println!("  {} {}", pubkey.to_bech32().unwrap_or_else(|_| pubkey.to_hex()), status.as_str());
+            }
+            println!("\nCompare with a member: marmot-cli verify -g {} <npub>", &group_hex[..16]);
+            return Ok(());
+        };
+
+        let member = Contacts::load(&self.db_path)?.resolve(member)?;
+        let leaf_key = keys.leaf_keys.get(&member).context("Not a member of this group")?;
+        let safety_number = verification::safety_number((&me, my_leaf), (&member, leaf_key));
+        let npub = member.to_bech32().unwrap_or_else(|_| member.to_hex());
+
+        println!("\nSafety number with {}:", npub);
+        for line in verification::format_digits(&safety_number, 4) {
+            println!("  {}", line);
+        }
+        println!("\nThey should see the same number (and epoch code) when verifying you.");
+
+        if unmark {
+            if verifications.unmark(&group_hex, &member) {
+                verifications.save(&self.db_path)?;
+                println!("✓ Verification removed");
+            } else {
+                println!("{} was not verified", npub);
+            }
+            return Ok(());
+        }
+        if mark {
+            verifications.mark(&group_hex, &member, leaf_key, &safety_number);
+            verifications.save(&self.db_path)?;
+            self.signer.audit(
+                "member_verified",
+                "safety number confirmed out-of-band",
+                serde_json::json!({ "group_id": group_hex, "member": member.to_hex(), "epoch": keys.epoch }),
+            ).await;
+            println!("✓ Marked {} as verified", npub);
+            return Ok(());
+        }
+
+        match verifications.status(&group_hex, &member, Some(leaf_key)) {
+            MemberStatus::Verified => println!("Status: ✓ verified"),
+            MemberStatus::Unverified => println!("Status: unverified — add --mark once the numbers match"),
+            MemberStatus::KeyChanged => {
+                println!("Status: ⚠️  key changed since you verified this member");
+                println!("   Compare the new number before trusting them again, then re-run with --mark.");
+            }
+        }
+        Ok(())
+    }
+
+    /// `history`: the last `limit` stored chat messages of a group, oldest first
+    fn history(&self, group: &str, limit: usize) -> Result<()> {
+        let group_id = self.resolve_group_id(group)?;
+        let contacts = Contacts::load(&self.db_path)?;
+        let profiles = ProfileCache::load(&self.db_path);
+        let blocklist = BlockList::load(&self.db_path)?;
+        let mut messages: Vec<_> = self.mdk.get_messages(&group_id)?
+            .into_iter()
+            .filter(|m| m.kind == Kind::Custom(9) && !blocklist.is_blocked(&m.pubkey))
+            .collect();
+        if messages.is_empty() {
+            println!("No stored messages in this group.");
+            return Ok(());
+        }
+        messages.sort_by_key(|m| m.created_at);
+        for message in &messages[messages.len().saturating_sub(limit)..] {
+            let sender = if message.pubkey == self.signer.public_key() {
+                "You".to_string()
+            } else {
+                metadata::label(&message.pubkey, &contacts, &profiles)
+            };
+            let when = chrono::DateTime::from_timestamp(message.created_at.as_secs() as i64, 0)
+                .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+            println!("[{}] {}: {}", when, sender, message.content);
+        }
+        Ok(())
+    }
+
+    /// Fetch kind-0 profiles of `members` missing from the cache or due for a refresh
+    async fn refresh_profiles(&self, members: &[PublicKey]) -> ProfileCache {
+        let mut profiles = ProfileCache::load(&self.db_path);
+        let now = chrono::Utc::now();
+        let stale = profiles.stale(members, now);
+        if stale.is_empty() {
+            return profiles;
+        }
+        let filter = Filter::new().kind(Kind::Metadata).authors(stale.clone());
+        match self.client.fetch_events(filter, std::time::Duration::from_secs(10)).await {
+            Ok(events) => {
+                let events: Vec<Event> = events.into_iter().collect();
+                profiles.update(&stale, &events, now);
+                if let Err(e) = profiles.save(&self.db_path) {
+                    tracing::warn!("Could not save profile cache: {}", e);
+                }
+            }
+            Err(e) => tracing::debug!("Profile fetch failed: {}", e),
+        }
+        profiles
+    }
+
     fn resolve_group_id(&self, partial: &str) -> Result<GroupId> {
+        if let Some(contact) = Contacts::load(&self.db_path)?.lookup(partial)? {
+            return self.direct_chat_with(partial, &contact);
+        }
         let groups = self.mdk.get_groups()?;
         let partial_lower = partial.to_lowercase();
         let matches: Vec<_> = groups.iter().filter(|g| {
@@ -324,16 +1194,297 @@ impl MarmotCli {
         }
     }
 
-    async fn send_message(&self, group_id_str: &str, message: &str) -> Result<()> {
+    /// The one-to-one chat (us and `contact` only) behind a contact alias
+    fn direct_chat_with(&self, alias: &str, contact: &PublicKey) -> Result<GroupId> {
+        let me = self.signer.public_key();
+        let chats: Vec<_> = self.mdk.get_groups()?
+            .into_iter()
+            .filter(|g| {
+                self.mdk.get_members(&g.mls_group_id).is_ok_and(|members| {
+                    members.len() == 2 && members.contains(contact) && members.contains(&me)
+                })
+            })
+            .collect();
+        match chats.len() {
+            0 => anyhow::bail!("No one-to-one chat with '{}'. Start one with: marmot-cli create-chat {}", alias, alias),
+            1 => Ok(chats[0].mls_group_id.clone()),
+            n => {
+                eprintln!("'{}' has {} one-to-one chats:", alias, n);
+                for g in &chats {
+                    eprintln!("  - {} ({})", g.name, hex::encode(g.mls_group_id.as_slice()));
+                }
+                anyhow::bail!("Use the group ID instead of the alias")
+            }
+        }
+    }
+
+    async fn send_message(&self, group_id_str: &str, message: &str, retention: &Retention) -> Result<()> {
         let mls_group_id = self.resolve_group_id(group_id_str)?;
-        let rumor = EventBuilder::new(Kind::Custom(9), message)
-            .build(self.signer.public_key());
-        let message_event = self.mdk.create_message(&mls_group_id, rumor)?;
-        let send_result = self.client.send_event(&message_event).await?;
+        let builder = EventBuilder::new(Kind::Custom(9), message);
+        let send_result = self.publish_group_message(&mls_group_id, builder, retention).await?;
         println!("✓ Message sent to {} relays", send_result.success.len());
         Ok(())
     }
 
+    /// Encrypt a rumor for the group (with its NIP-40 expiration, if any) and publish it
+    async fn publish_group_message(
+        &self,
+        mls_group_id: &GroupId,
+        mut builder: EventBuilder,
+        retention: &Retention,
+    ) -> Result<Output<EventId>> {
+        let retention = RetentionSettings::load(&self.db_path)?
+            .effective(&hex::encode(mls_group_id.as_slice()), retention)?;
+        if let Some(expiration) = retention.expiration(chrono::Utc::now()) {
+            builder = builder.tag(Tag::expiration(Timestamp::from(expiration as u64)));
+        }
+        let rumor = builder.build(self.signer.public_key());
+        let message_event = self.mdk.create_message(mls_group_id, rumor)?;
+        let send_result = self.client.send_event(&message_event).await?;
+        self.audit_publish("message_published", &message_event, &send_result, Some(mls_group_id)).await;
+        Ok(send_result)
+    }
+
+    /// `block` / `unblock`, optionally publishing the NIP-51 mute list
+    async fn set_blocked(&self, user: &str, block: bool, publish: bool) -> Result<()> {
+        let public_key = self.resolve_user(user).await?.public_key;
+        if public_key == self.signer.public_key() {
+            anyhow::bail!("You can't block yourself");
+        }
+        let shown = metadata::label(&public_key, &Contacts::load(&self.db_path)?, &ProfileCache::load(&self.db_path));
+        let mut blocklist = BlockList::load(&self.db_path)?;
+        if block {
+            if blocklist.block(&public_key) {
+                println!("🚫 Blocked {}", shown);
+            } else {
+                println!("{} was already blocked", shown);
+            }
+            // Their pending invites are declined rather than left in the list
+            for welcome in self.mdk.get_pending_welcomes(None)? {
+                if welcome.welcomer == public_key {
+                    self.mdk.decline_welcome(&welcome)?;
+                    println!("   Declined pending welcome to '{}'", welcome.group_name);
+                }
+            }
+        } else if blocklist.unblock(&public_key) {
+            println!("✓ Unblocked {}", shown);
+        } else {
+            println!("{} was not blocked", shown);
+        }
+        blocklist.save(&self.db_path)?;
+
+        if publish {
+            // Kind 10000 is replaceable: build on the list we last published
+            let filter = Filter::new().kind(Kind::MuteList).author(self.signer.public_key()).limit(1);
+            let existing = self.client
+                .fetch_events(filter, std::time::Duration::from_secs(10))
+                .await
+                .context("Failed to fetch the current mute list; not publishing over it")?
+                .into_iter()
+                .max_by_key(|event| event.created_at);
+            let unblocked = if block { Vec::new() } else { vec![public_key] };
+            let builder = blocklist.mute_list(existing.as_ref(), &unblocked)?;
+            let Some(event) = self.sign_or_defer(builder).await? else {
+                return Ok(());
+            };
+            let output = self.client.send_event(&event).await?;
+            self.audit_publish("mute_list_published", &event, &output, None).await;
+            println!(
+                "✓ Mute list ({} user(s)) published to {} relays",
+                event.tags.public_keys().count(),
+                output.success.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// `blocked`: list blocked users
+    fn list_blocked(&self) -> Result<()> {
+        let blocklist = BlockList::load(&self.db_path)?;
+        if blocklist.blocked.is_empty() {
+            println!("Nobody is blocked.");
+            return Ok(());
+        }
+        let contacts = Contacts::load(&self.db_path)?;
+        let profiles = ProfileCache::load(&self.db_path);
+        println!("=== Blocked ({}) ===", blocklist.blocked.len());
+        for (hex, user) in &blocklist.blocked {
+            let shown = PublicKey::from_hex(hex)
+                .map(|pk| metadata::label(&pk, &contacts, &profiles))
+                .unwrap_or_else(|_| user.npub.clone());
+            println!("  🚫 {} (since {})", shown, user.blocked_at);
+        }
+        Ok(())
+    }
+
+    /// `add-members`: commit the new members, then send each a welcome
+    async fn add_members(&self, group_id_str: &str, users: &[String]) -> Result<()> {
+        let mls_group_id = self.resolve_group_id(group_id_str)?;
+        let contacts = Contacts::load(&self.db_path)?;
+        let mut key_packages = Vec::new();
+        for user in users {
+            key_packages.push(self.fetch_key_package(user).await?);
+        }
+
+        let result = self.mdk.add_members(&mls_group_id, &key_packages)?;
+        let output = self.publish_commit(&mls_group_id, &result.evolution_event).await?;
+        self.mdk.merge_pending_commit(&mls_group_id)?;
+        self.audit_publish("members_added", &result.evolution_event, &output, Some(&mls_group_id)).await;
+
+        // One welcome per key package, in the order they were passed
+        let welcomes = result.welcome_rumors.unwrap_or_default();
+        for (key_package, welcome_rumor) in key_packages.iter().zip(welcomes) {
+            let gift_wrap = self.signer.gift_wrap(&key_package.pubkey, welcome_rumor).await?;
+            let send_result = self.client.send_event(&gift_wrap).await?;
+            self.audit_publish("welcome_published", &gift_wrap, &send_result, Some(&mls_group_id)).await;
+            println!("✓ Added {} (welcome sent to {} relays)", contacts.display(&key_package.pubkey), send_result.success.len());
+        }
+        Ok(())
+    }
+
+    /// Publish a commit MDK has pending for `group_id`; the caller merges it
+    ///
+    /// If no relay accepts it the pending commit is discarded, since nobody
+    /// else will see it: merging would fork the group, and keeping it would
+    /// block every later commit.
+    async fn publish_commit(&self, group_id: &GroupId, commit: &Event) -> Result<Output<EventId>> {
+        match self.client.send_event(commit).await {
+            Ok(output) if !output.success.is_empty() => Ok(output),
+            outcome => {
+                if let Err(e) = self.mdk.clear_pending_commit(group_id) {
+                    tracing::warn!("Could not discard the unpublished commit: {}", e);
+                }
+                match outcome {
+                    Err(e) => Err(e).context("Failed to publish the commit; discarded it"),
+                    Ok(_) => Err(anyhow::anyhow!("No relay accepted the commit; discarded it (merging would fork the group)")),
+                }
+            }
+        }
+    }
+
+    /// Publish an MLS self-update commit for one group and merge it
+    async fn rotate_group_key(&self, group_id: &GroupId) -> Result<()> {
+        let result = self.mdk.self_update(group_id)?;
+        let output = self.publish_commit(group_id, &result.evolution_event).await?;
+        self.mdk.merge_pending_commit(group_id)?;
+        self.audit_publish("key_rotated", &result.evolution_event, &output, Some(group_id)).await;
+
+        let mut health = HealthLog::load(&self.db_path);
+        health.group(&hex::encode(group_id.as_slice())).record_key_rotation();
+        health.save(&self.db_path)?;
+        Ok(())
+    }
+
+    /// `rotate-keys`: self-update in one group or all of them
+    async fn rotate_keys(&self, group: Option<&str>) -> Result<()> {
+        let groups = match group {
+            Some(partial) => {
+                let group_id = self.resolve_group_id(partial)?;
+                self.mdk.get_groups()?.into_iter().filter(|g| g.mls_group_id == group_id).collect()
+            }
+            None => self.mdk.get_groups()?,
+        };
+        let mut failed = 0;
+        for group in &groups {
+            match self.rotate_group_key(&group.mls_group_id).await {
+                Ok(()) => println!("✓ Rotated our leaf key in '{}'", group.name),
+                Err(e) => {
+                    failed += 1;
+                    eprintln!("❌ Key rotation failed in '{}': {}", group.name, e);
+                }
+            }
+        }
+        if failed > 0 {
+            anyhow::bail!("{} of {} group(s) could not be rotated", failed, groups.len());
+        }
+        Ok(())
+    }
+
+    /// `listen --rotate-every`: rotate in groups whose last rotation is older than `every`
+    async fn rotate_due_keys(&self, every: chrono::Duration) {
+        let Ok(groups) = self.mdk.get_groups() else { return };
+        // Decide first: checking starts the clock for new groups, and
+        // `rotate_group_key` records its own rotations
+        let mut health = HealthLog::load(&self.db_path);
+        let due: Vec<_> = groups
+            .into_iter()
+            .filter(|group| health.group(&hex::encode(group.mls_group_id.as_slice())).key_rotation_due(every))
+            .collect();
+        if let Err(e) = health.save(&self.db_path) {
+            tracing::warn!("Could not save group health: {}", e);
+        }
+        for group in due {
+            match self.rotate_group_key(&group.mls_group_id).await {
+                Ok(()) => println!("🔄 Rotated our leaf key in '{}'", group.name),
+                Err(e) => eprintln!("⚠️  Scheduled key rotation failed in '{}': {}", group.name, e),
+            }
+        }
+    }
+
+    /// `group retention`: show, set or clear a group's retention override
+    fn group_retention(
+        &self,
+        group: &str,
+        keep: Option<&str>,
+        expire_outgoing: bool,
+        clear: bool,
+        default: &Retention,
+    ) -> Result<()> {
+        let group_hex = hex::encode(self.resolve_group_id(group)?.as_slice());
+        let mut settings = RetentionSettings::load(&self.db_path)?;
+        if let Some(keep) = keep {
+            retention::parse_keep(keep)?;
+            settings.groups.insert(group_hex.clone(), GroupRetention { keep: keep.to_string(), expire_outgoing });
+            settings.save(&self.db_path)?;
+            println!("✓ Retention for {} set", &group_hex[..16]);
+        } else if clear {
+            if settings.groups.remove(&group_hex).is_some() {
+                settings.save(&self.db_path)?;
+            }
+            println!("✓ Retention for {} follows the profile default", &group_hex[..16]);
+        }
+
+        let effective = settings.effective(&group_hex, default)?;
+        let source = if settings.groups.contains_key(&group_hex) { "group setting" } else { "profile default" };
+        match effective.keep {
+            Some(keep) => println!("Messages are deleted after {} ({})", retention::format_keep(keep), source),
+            None => println!("Messages are kept forever ({})", source),
+        }
+        if effective.keep.is_some() {
+            let tagged = if effective.expire_outgoing { "with" } else { "without" };
+            println!("Outgoing messages are sent {} a NIP-40 expiration tag", tagged);
+        }
+        Ok(())
+    }
+
+    /// Delete stored messages older than each group's retention limit
+    async fn purge_expired_messages(&self, default: &Retention) -> Result<usize> {
+        let settings = RetentionSettings::load(&self.db_path)?;
+        let now = chrono::Utc::now();
+        let mut conn = None;
+        let mut purged = 0;
+        for group in self.mdk.get_groups()? {
+            let group_hex = hex::encode(group.mls_group_id.as_slice());
+            let Some(keep) = settings.effective(&group_hex, default)?.keep else { continue };
+            if conn.is_none() {
+                let opened = storage::open_connection(&self.db_path, self.db_key.as_ref())?;
+                opened.busy_timeout(std::time::Duration::from_secs(5))?;
+                conn = Some(opened);
+            }
+            let Some(conn) = conn.as_ref() else { continue };
+            let count = retention::purge_messages(conn, group.mls_group_id.as_slice(), (now - keep).timestamp())?;
+            if count > 0 {
+                purged += count;
+                self.signer.audit(
+                    "messages_purged",
+                    &format!("Deleted {} message(s) older than {}", count, retention::format_keep(keep)),
+                    serde_json::json!({ "group_id": group_hex, "count": count }),
+                ).await;
+            }
+        }
+        Ok(purged)
+    }
+
     async fn leave_chat(&self, group_id_str: &str) -> Result<()> {
         let mls_group_id = self.resolve_group_id(group_id_str)?;
         
@@ -346,6 +1497,7 @@ impl MarmotCli {
         // Create leave proposal and publish
         let leave_result = self.mdk.leave_group(&mls_group_id)?;
         let send_result = self.client.send_event(&leave_result.evolution_event).await?;
+        self.audit_publish("leave_published", &leave_result.evolution_event, &send_result, Some(&mls_group_id)).await;
         
         println!("✓ Left group '{}' (published to {} relays)", group_name, send_result.success.len());
         println!("  Note: The group will be removed from your list after other members process the leave.");
@@ -362,30 +1514,36 @@ impl MarmotCli {
             .kind(Kind::GiftWrap)
             .pubkey(self.signer.public_key())
             .limit(100);
-        let events = self.client
+        let fetched = self.client
             .fetch_events(filter, std::time::Duration::from_secs(10))
             .await?;
+        // Gift wraps we couldn't open while the bunker was offline come first
+        let mut events = self.offline_queue.as_ref()
+            .and_then(|q| q.lock().ok().map(|mut q| q.take_gift_wraps()))
+            .unwrap_or_default();
+        for event in fetched {
+            if !events.iter().any(|e| e.id == event.id) {
+                events.push(event);
+            }
+        }
 
+        let mut blocklist = BlockList::load(&self.db_path)?;
+        let now = chrono::Utc::now().timestamp();
         for event in events.iter() {
-            match self.signer.extract_rumor(event).await {
-                Ok(unwrapped) => {
-                    if unwrapped.rumor.kind == Kind::MlsWelcome {
-                        match self.mdk.process_welcome(&event.id, &unwrapped.rumor) {
-                            Ok(_) => {
-                                welcomes_found += 1;
-                                println!("📨 New welcome received (event: {})", event.id.to_hex());
-                            }
-                            Err(e) => { tracing::debug!("Welcome processing: {}", e); }
-                        }
-                    }
-                }
-                Err(e) => { tracing::debug!("Could not unwrap gift-wrap: {}", e); }
+            if self.process_gift_wrap(event, &mut blocklist, now).await {
+                welcomes_found += 1;
+                println!("📨 New welcome received (event: {})", event.id.to_hex());
             }
         }
 
+        blocklist.prune(now);
+        if let Err(e) = blocklist.save(&self.db_path) {
+            tracing::warn!("Could not save block list: {}", e);
+        }
+
         // Phase 2: Check for pending welcomes
         if let Ok(pending) = self.mdk.get_pending_welcomes(None) {
-            for welcome in &pending {
+            for welcome in pending.iter().filter(|w| !blocklist.is_blocked(&w.welcomer)) {
                 println!("⏳ Pending welcome: '{}' (event: {})", welcome.group_name, &welcome.id.to_hex()[..16]);
                 println!("   Run: marmot-cli accept-welcome {}", welcome.id.to_hex());
             }
@@ -393,7 +1551,19 @@ impl MarmotCli {
 
         // Phase 3: Fetch and process group messages
         let groups = self.mdk.get_groups()?;
+        let mut verifications = Verifications::load(&self.db_path)?;
+        let contacts = Contacts::load(&self.db_path)?;
+        let members: Vec<PublicKey> = groups
+            .iter()
+            .filter_map(|g| self.mdk.get_members(&g.mls_group_id).ok())
+            .flatten()
+            .collect();
+        let profiles = self.refresh_profiles(&members).await;
+        let mut health = HealthLog::load(&self.db_path);
         for group in &groups {
+            let mut statuses = self.member_statuses(&group.mls_group_id, &verifications);
+            let group_hex = hex::encode(group.mls_group_id.as_slice());
+            let failures_before = health.group(&group_hex).failed;
             let nostr_group_id = hex::encode(&group.nostr_group_id);
             let filter = Filter::new()
                 .kind(Kind::MlsGroupMessage)
@@ -404,59 +1574,332 @@ impl MarmotCli {
                 .await?;
 
             for event in events.iter() {
-                match self.mdk.process_message(event) {
+                match self.process_group_message(event, Some(&group.mls_group_id), &mut health, &mut verifications) {
                     Ok(result) => {
                         match result {
+                            MessageProcessingResult::ApplicationMessage(msg) if blocklist.is_blocked(&msg.pubkey) => {
+                                tracing::debug!("Hid message from blocked {}", msg.pubkey);
+                            }
                             MessageProcessingResult::ApplicationMessage(msg) => {
                                 messages_found += 1;
                                 let sender = msg.pubkey.to_bech32().unwrap_or_else(|_| "unknown".to_string());
+                                let sender_alias = contacts.alias_for(&msg.pubkey).map(str::to_string);
+                                let sender_name = profiles.name(&msg.pubkey);
+                                let shown = metadata::label(&msg.pubkey, &contacts, &profiles);
                                 let is_me = msg.pubkey == self.signer.public_key();
-                                let prefix = if is_me { "→ You".to_string() } else { sender.clone() };
+                                let status = statuses.get(&msg.pubkey).copied().unwrap_or(MemberStatus::Unverified);
+                                let prefix = match (is_me, status) {
+                                    (true, _) => "→ You".to_string(),
+                                    (false, MemberStatus::Verified) => format!("{} ✓", shown),
+                                    (false, MemberStatus::Unverified) => format!("{} (unverified)", shown),
+                                    (false, MemberStatus::KeyChanged) => format!("{} ⚠️  KEY CHANGED", shown),
+                                };
                                 println!("[{}] {}: {}", group.name, prefix, msg.content);
                                 payloads.push(MessagePayload {
+                                    account: self.signer.public_key().to_bech32().unwrap_or_default(),
                                     message_id: event.id.to_hex(),
                                     group_id: hex::encode(group.mls_group_id.as_slice()),
                                     group_name: group.name.clone(),
                                     sender, sender_hex: msg.pubkey.to_hex(),
+                                    sender_alias,
+                                    sender_name,
                                     content: msg.content.clone(),
                                     timestamp: event.created_at.as_secs(),
                                     is_me,
+                                    sender_verification: status.as_str().to_string(),
                                 });
                             }
                             MessageProcessingResult::Commit { .. } => {
                                 tracing::debug!("Processed commit for group {}", group.name);
+                                statuses = self.member_statuses(&group.mls_group_id, &verifications);
                             }
                             _ => {}
                         }
                     }
-                    Err(e) => { tracing::debug!("Message processing: {}", e); }
+                    Err(_) => {}
+                }
+            }
+
+            // Only speak up when this poll brought new failures
+            let stats = health.group(&group_hex);
+            if stats.failed > failures_before {
+                let verdict = self.group_verdict(group, stats);
+                if !verdict.is_healthy() {
+                    Self::warn_unhealthy(&group.name, &group_hex, &verdict);
+                }
+            }
+        }
+        if let Err(e) = health.save(&self.db_path) {
+            tracing::warn!("Could not save group health: {}", e);
+        }
+
+        Ok((welcomes_found, messages_found, payloads))
+    }
+
+    /// Process a group message: record the outcome in the group's health (when
+    /// the group is known) and, for a commit, follow key updates of verified
+    /// members. The caller saves `health`.
+    fn process_group_message(
+        &self,
+        event: &Event,
+        group_id: Option<&GroupId>,
+        health: &mut HealthLog,
+        verifications: &mut Verifications,
+    ) -> Result<MessageProcessingResult, mdk_core::Error> {
+        let group_hex = group_id.map(|id| hex::encode(id.as_slice()));
+        // Member keys before a possible commit, only needed if anyone here is verified
+        let before = group_id
+            .filter(|_| group_hex.as_ref().is_some_and(|hex| verifications.groups.contains_key(hex)))
+            .and_then(|id| verification::group_keys(&self.mdk, id).ok());
+        let result = self.mdk.process_message(event);
+        match (&result, group_hex.as_deref()) {
+            (Ok(processed), Some(group_hex)) => {
+                health.group(group_hex).record_success(&event.id.to_hex());
+                if let (MessageProcessingResult::Commit { .. }, Some(before), Some(group_id)) =
+                    (processed, &before, group_id)
+                {
+                    self.follow_key_updates(group_id, before, verifications);
+                }
+            }
+            (Err(e), group_hex) => {
+                tracing::debug!("Message processing: {}", e);
+                if let (Some(kind), Some(group_hex)) = (health::classify(e), group_hex) {
+                    health.group(group_hex).record_failure(&event.id.to_hex(), kind, &e.to_string());
+                }
+            }
+            (Ok(_), None) => {}
+        }
+        result
+    }
+
+    /// Store the welcome in a gift wrap unless its sender is blocked or over the
+    /// rate cap; true if a new welcome was stored
+    async fn process_gift_wrap(&self, event: &Event, blocklist: &mut BlockList, now: i64) -> bool {
+        let unwrapped = match self.signer.extract_rumor(event).await {
+            Ok(unwrapped) => unwrapped,
+            Err(e) => {
+                if let Some(mut queue) = self.offline_queue() {
+                    queue.defer_gift_wrap(event);
+                }
+                tracing::debug!("Could not unwrap gift-wrap: {}", e);
+                return false;
+            }
+        };
+        if unwrapped.rumor.kind != Kind::MlsWelcome {
+            return false;
+        }
+        if blocklist.is_blocked(&unwrapped.sender) {
+            tracing::debug!("Dropped welcome from blocked {}", unwrapped.sender);
+            return false;
+        }
+        if !blocklist.welcome_allowed(&unwrapped.sender, now, self.welcomes_per_hour) {
+            tracing::debug!("Dropped welcome from {} over the rate cap", unwrapped.sender);
+            return false;
+        }
+        match self.mdk.process_welcome(&event.id, &unwrapped.rumor) {
+            Ok(_) => {
+                blocklist.record_welcome(&unwrapped.sender, now);
+                true
+            }
+            Err(e) => {
+                tracing::debug!("Welcome processing: {}", e);
+                false
+            }
+        }
+    }
+
+    async fn relays_status(&self) -> Result<()> {
+        let mut group_relays = Vec::new();
+        for group in self.mdk.get_groups()? {
+            if let Ok(relays) = self.mdk.get_relays(&group.mls_group_id) {
+                group_relays.push((group.name.clone(), relays.into_iter().collect()));
+            }
+        }
+        let bunker_relays = BunkerConfig::load(&self.db_path)?
+            .map(|c| c.relays)
+            .unwrap_or_default();
+        let all = relays::collect_relay_sources(&self.relays, &group_relays, &bunker_relays);
+
+        println!("=== Relay Status ({} relays) ===\n", all.len());
+        for (url, sources) in all {
+            let probe = relays::probe_relay(&self.client, url, sources, self.signer.public_key(), self.proxy).await;
+            let icon = if probe.connected { "✓" } else { "✗" };
+            let used_by: Vec<String> = probe.sources.iter().map(|s| s.to_string()).collect();
+            println!("{} {} [{}]", icon, probe.url, probe.status);
+            println!("   Used by: {}", used_by.join(", "));
+            if let Some(latency) = probe.connect_latency {
+                println!("   Connect: {} ms", latency.as_millis());
+            }
+            if let Some(latency) = probe.query_latency {
+                println!("   Round-trip: {} ms", latency.as_millis());
+            }
+            if let Some(info) = &probe.info {
+                println!(
+                    "   NIP-11: {} ({} {})",
+                    info.name.as_deref().unwrap_or("unnamed"),
+                    info.software.as_deref().unwrap_or("unknown software"),
+                    info.version.as_deref().unwrap_or(""),
+                );
+                if !info.supported_nips.is_empty() {
+                    let nips: Vec<String> = info.supported_nips.iter().map(|n| n.to_string()).collect();
+                    println!("   NIPs: {}", nips.join(", "));
+                }
+                if let Some(limits) = &info.limitation {
+                    if limits.auth_required == Some(true) {
+                        println!("   ⚠️  Requires NIP-42 AUTH");
+                    }
+                    if limits.payment_required == Some(true) {
+                        println!("   ⚠️  Requires payment");
+                    }
+                    if limits.restricted_writes == Some(true) {
+                        println!("   ⚠️  Restricted writes (may reject Marmot events)");
+                    }
+                }
+                for warning in info.policy_warnings() {
+                    println!("   ⚠️  {}", warning);
                 }
+            } else {
+                println!("   NIP-11: unavailable");
             }
+            if probe.connected {
+                let kinds: Vec<String> = relays::MARMOT_KINDS.iter().map(|k| {
+                    let mark = if probe.kinds_seen.contains(k) { "✓" } else { "?" };
+                    format!("{} {}", k, mark)
+                }).collect();
+                println!("   Stored Marmot events: {}", kinds.join("  "));
+            }
+            match probe.has_key_package {
+                Some(true) => println!("   Key package: ✓ present"),
+                Some(false) => println!("   Key package: ✗ missing (run publish-key-package)"),
+                None => {}
+            }
+            if let Some(err) = &probe.error {
+                println!("   Error: {}", err);
+            }
+            println!();
         }
+        println!("Kinds marked ? have no stored events on that relay. Whether a relay accepts new");
+        println!("events can't be known without publishing; warnings above come from its NIP-11 policy.");
+        Ok(())
+    }
 
-        Ok((welcomes_found, messages_found, payloads))
+    /// Accept pending welcomes allowed by the profile's auto-accept policy
+    async fn auto_accept_welcomes(&self, profile: &Profile) -> Result<usize> {
+        let mut accepted = 0;
+        let blocklist = BlockList::load(&self.db_path)?;
+        for welcome in self.mdk.get_pending_welcomes(None)? {
+            if profile.should_auto_accept(&welcome.welcomer) && !blocklist.is_blocked(&welcome.welcomer) {
+                self.mdk.accept_welcome(&welcome)?;
+                self.audit_welcome_accepted(&welcome, true).await;
+                println!("✓ Auto-accepted welcome to '{}'", welcome.group_name);
+                accepted += 1;
+            }
+        }
+        Ok(accepted)
+    }
+
+    async fn audit_welcome_accepted(&self, welcome: &mdk_storage_traits::welcomes::types::Welcome, automatic: bool) {
+        self.signer.audit(
+            "welcome_accepted",
+            &format!("joined '{}'", welcome.group_name),
+            serde_json::json!({
+                "event_id": welcome.id.to_hex(),
+                "group_id": hex::encode(welcome.mls_group_id.as_slice()),
+                "welcomer": welcome.welcomer.to_hex(),
+                "automatic": automatic,
+            }),
+        ).await;
     }
 
-    fn invoke_callback(script: &str, payload: &MessagePayload) -> Result<i32> {
+    async fn invoke_callback(script: &str, payload: &MessagePayload) -> Result<i32> {
+        use tokio::io::AsyncWriteExt;
+
         let json = serde_json::to_string(payload)?;
-        let mut child = Command::new("sh")
+        let mut child = tokio::process::Command::new("sh")
             .arg("-c").arg(script)
             .stdin(Stdio::piped())
             .spawn().context("Failed to spawn callback process")?;
         if let Some(mut stdin) = child.stdin.take() {
-            stdin.write_all(json.as_bytes())?;
+            stdin.write_all(json.as_bytes()).await?;
         }
-        let status = child.wait()?;
+        let status = child.wait().await?;
         Ok(status.code().unwrap_or(-1))
     }
 
+    /// `listen` for this account: poll every `options.interval` seconds, forever
+    async fn listen(&self, options: &ListenOptions) {
+        loop {
+            self.listen_poll(options).await;
+            tokio::time::sleep(tokio::time::Duration::from_secs(options.interval)).await;
+        }
+    }
+
+    /// One `listen` round; bunker or relay trouble is reported and retried on the next one
+    async fn listen_poll(&self, options: &ListenOptions) {
+        match self.flush_offline_queue().await {
+            Ok(0) => {}
+            Ok(n) => println!("✓ Published {} queued event(s)", n),
+            Err(e) => eprintln!("⚠️  Publishing queued events failed: {}", e),
+        }
+        let (w, m, payloads) = match self.receive_messages().await {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("⚠️  Receive failed: {} (retrying in {}s)", e, options.interval);
+                return;
+            }
+        };
+        if let Err(e) = self.auto_accept_welcomes(&options.profile).await {
+            eprintln!("⚠️  Auto-accept failed: {}", e);
+        }
+        if let Some(every) = options.rotate_every {
+            self.rotate_due_keys(every).await;
+        }
+        match self.purge_expired_messages(&options.retention).await {
+            Ok(0) => {}
+            Ok(n) => println!("🗑️  Deleted {} message(s) past their retention limit", n),
+            Err(e) => eprintln!("⚠️  Message purge failed: {}", e),
+        }
+        if let Some(ref script) = options.on_message {
+            for payload in &payloads {
+                if payload.is_me { continue; }
+                let outcome = Self::invoke_callback(script, payload).await;
+                let mut context = serde_json::json!({
+                    "message_id": payload.message_id,
+                    "group_id": payload.group_id,
+                });
+                match &outcome {
+                    Ok(0) => { tracing::debug!("Callback succeeded for message {}", payload.message_id); }
+                    Ok(code) => { eprintln!("⚠️ Callback exited with code {} for message {}", code, &payload.message_id[..16]); }
+                    Err(e) => { eprintln!("❌ Callback failed for message {}: {}", &payload.message_id[..16], e); }
+                }
+                match outcome {
+                    Ok(code) => context["exit_code"] = code.into(),
+                    Err(e) => context["error"] = e.to_string().into(),
+                }
+                self.signer.audit("callback", "on_message callback ran", context).await;
+            }
+        }
+        if w > 0 || m > 0 {
+            if options.label_accounts {
+                let npub = self.signer.public_key().to_bech32().unwrap_or_default();
+                println!("--- [{}] {} welcome(s), {} message(s) ---", &npub[..20], w, m);
+            } else {
+                println!("--- {} welcome(s), {} message(s) ---", w, m);
+            }
+        }
+    }
+
     async fn accept_welcome(&self, event_id_str: &str) -> Result<()> {
         let event_id = EventId::from_hex(event_id_str)
             .or_else(|_| EventId::from_bech32(event_id_str))
             .context("Invalid event ID")?;
         let welcome = self.mdk.get_welcome(&event_id)?
             .context("Welcome not found. Run `receive` first to fetch pending welcomes.")?;
+        if BlockList::load(&self.db_path)?.is_blocked(&welcome.welcomer) {
+            anyhow::bail!("This welcome is from a blocked user. Run `unblock` first to accept it.");
+        }
         self.mdk.accept_welcome(&welcome)?;
+        self.audit_welcome_accepted(&welcome, false).await;
         println!("✓ Welcome accepted! You've joined the group.");
         let groups = self.mdk.get_groups()?;
         if let Some(latest) = groups.last() {
@@ -468,7 +1911,13 @@ impl MarmotCli {
 }
 
 /// Migrate from nsec to bunker mode (standalone, doesn't need full MarmotCli)
-async fn migrate_to_bunker(db_path: &PathBuf, bunker_uri: &str, current_nsec: Option<&str>) -> Result<()> {
+async fn migrate_to_bunker(
+    db_path: &Path,
+    bunker_uri: &str,
+    current_nsec: Option<&str>,
+    proxy: Option<SocketAddr>,
+    bunker_timeout: std::time::Duration,
+) -> Result<()> {
     // Step 1: Parse the bunker URI
     println!("🔐 Migrating to NIP-46 bunker signing...\n");
     let mut config = BunkerConfig::from_bunker_uri(bunker_uri)?;
@@ -485,11 +1934,7 @@ async fn migrate_to_bunker(db_path: &PathBuf, bunker_uri: &str, current_nsec: Op
 
     // Step 3: Connect to bunker and get user pubkey
     println!("   Connecting to bunker...");
-    let uri = config.to_nostr_connect_uri()?;
-    let client_keys = config.client_keys()?;
-    let connect = nostr_connect::prelude::NostrConnect::new(
-        uri, client_keys, std::time::Duration::from_secs(30), None,
-    ).map_err(|e| anyhow::anyhow!("Failed to create NIP-46 client: {}", e))?;
+    let connect = connect_bunker(&mut config, proxy, bunker_timeout, &AuthPolicy::default()).await?;
 
     let bunker_pubkey = connect.get_public_key().await
         .map_err(|e| anyhow::anyhow!("Failed to connect to bunker: {}", e))?;
@@ -538,7 +1983,60 @@ async fn migrate_to_bunker(db_path: &PathBuf, bunker_uri: &str, current_nsec: Op
     Ok(())
 }
 
-fn show_signer_status(db_path: &PathBuf, nsec: Option<&str>, bunker_uri: Option<&str>) -> Result<()> {
+/// Client-initiated NIP-46 pairing (`init --nostrconnect`)
+async fn init_nostrconnect(
+    db_path: &Path,
+    pairing_relays: &str,
+    proxy: Option<SocketAddr>,
+    bunker_timeout: std::time::Duration,
+) -> Result<()> {
+    if BunkerConfig::load(db_path)?.is_some() {
+        anyhow::bail!(
+            "A bunker configuration already exists.\n\
+             Delete it first with: rm {}",
+            BunkerConfig::config_path(db_path).display()
+        );
+    }
+
+    let relays: Vec<String> = pairing_relays.split(',').map(|s| s.trim().to_string()).collect();
+    let pairing = NostrConnectPairing::new(&relays)?;
+    println!("🔐 Scan this with your signer app (Amber, nsec.app, ...):\n");
+    match pairing.qr_code() {
+        Ok(qr) => println!("{}", qr),
+        Err(e) => eprintln!("⚠️  Could not render QR code: {}", e),
+    }
+    println!("Or paste this URI into the signer:\n\n{}\n", pairing.uri());
+    println!("Waiting for the signer to connect (up to {}s)...", nip46::pairing::PAIRING_TIMEOUT.as_secs());
+
+    let mut config = pairing.wait(proxy, nip46::pairing::PAIRING_TIMEOUT).await?;
+    println!("   ✓ Signer connected: {}", config.remote_signer_pubkey);
+
+    // The user key may differ from the signer's transport key
+    let connect = open_nostr_connect(&config, proxy, bunker_timeout)?;
+    let pubkey = connect.get_public_key().await
+        .map_err(|e| anyhow::anyhow!("Failed to get public key from signer: {}", e))?;
+    connect.shutdown().await;
+    config.update_connected(Some(pubkey));
+
+    if let Some(parent) = db_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    config.save(db_path)?;
+
+    println!("✓ Bunker configured!");
+    println!("  npub: {}", pubkey.to_bech32().unwrap_or_default());
+    println!("  hex:  {}", pubkey);
+    println!("  Config: {}", BunkerConfig::config_path(db_path).display());
+    println!("\nNext: marmot-cli publish-key-package");
+    Ok(())
+}
+
+fn show_signer_status(
+    db_path: &PathBuf,
+    nsec: Option<&str>,
+    bunker_uri: Option<&str>,
+    show_permissions: bool,
+) -> Result<()> {
     println!("=== Marmot CLI Signer Status ===\n");
 
     // Check for bunker config
@@ -554,16 +2052,48 @@ fn show_signer_status(db_path: &PathBuf, nsec: Option<&str>, bunker_uri: Option<
             println!("  Last connected: {}", last);
         }
         println!("  Config file: {}", BunkerConfig::config_path(db_path).display());
+        if show_permissions {
+            print_requested_permissions(&config.requested_permissions);
+        }
+    } else if bunker_uri.is_none() && nsec.is_none() && NcryptsecConfig::config_path(db_path).exists() {
+        let stored = NcryptsecConfig::load(db_path)?.context("ncryptsec config disappeared")?;
+        println!("Mode: 🔐 Encrypted key (NIP-49 ncryptsec, stored)");
+        println!("  User pubkey: {}...", &stored.pubkey[..16]);
+        println!("  Created: {}", stored.created_at);
+        println!("  Config file: {}", NcryptsecConfig::config_path(db_path).display());
+        println!("  Passphrase: prompt, --passphrase-fd or MARMOT_KEY_PASSPHRASE");
     } else if bunker_uri.is_some() {
         println!("Mode: 🔐 NIP-46 Bunker (from CLI/env, not yet stored)");
     } else if nsec.is_some() {
         println!("Mode: 🔑 Direct nsec");
         println!("  ⚠️  Consider migrating to bunker mode for production use:");
         println!("     marmot-cli migrate-to-bunker --bunker \"bunker://...\"");
+        println!("     or store it encrypted: marmot-cli init --nsec \"nsec1...\" --encrypt-key");
     } else {
         println!("Mode: ❌ No credentials configured");
     }
 
+    let policy = SigningPolicy::load(db_path)?;
+    let policy_path = SigningPolicy::config_path(db_path);
+    if policy_path.exists() {
+        println!("\nSigning policy: {}", policy_path.display());
+    } else {
+        println!("\nSigning policy: built-in default (no {})", policy_path.display());
+    }
+    let kinds: Vec<String> = policy.kinds().iter().map(|k| k.to_string()).collect();
+    println!("  Allowed kinds: {}", kinds.join(", "));
+    match policy.max_events_per_minute {
+        Some(max) => println!("  Rate limit: {} events/minute", max),
+        None => println!("  Rate limit: none"),
+    }
+    if !policy.forbidden_patterns.is_empty() {
+        println!("  Forbidden patterns: {}", policy.forbidden_patterns.len());
+    }
+    match &policy.allowed_recipients {
+        Some(recipients) => println!("  Gift-wrap recipients: {} allowed", recipients.len()),
+        None => println!("  Gift-wrap recipients: anyone"),
+    }
+
     // Check audit log
     let audit_path = db_path.with_extension("audit.jsonl");
     if audit_path.exists() {
@@ -571,7 +2101,569 @@ fn show_signer_status(db_path: &PathBuf, nsec: Option<&str>, bunker_uri: Option<
             println!("\nAudit log: {} ({} bytes)", audit_path.display(), metadata.len());
         }
     }
+    let archived = nip46::audit::archives(&audit_path).len();
+    if archived > 0 {
+        println!("Audit archives: {} (see `audit show --all`)", archived);
+    }
+
+    Ok(())
+}
+
+fn print_requested_permissions(requested: &[String]) {
+    if requested.is_empty() {
+        println!("\nPermissions: none requested (connected before scoped permissions)");
+        println!("  Re-pair to lock the bunker down: marmot-cli init --nostrconnect");
+        return;
+    }
+    println!("\nPermissions requested from bunker:");
+    for permission in requested {
+        match nip46::permissions::describe(permission) {
+            "" => println!("  - {}", permission),
+            purpose => println!("  - {:<18} {}", permission, purpose),
+        }
+    }
+    println!("  Grant only these in your bunker; everything else can be denied.");
+}
+
+/// Our identity as known locally (no network): direct key, cached bunker pubkey
+/// or the pubkey recorded next to a stored ncryptsec (no passphrase needed)
+fn local_identity(nsec: Option<&str>, bunker_uri: Option<&str>, db_path: &Path) -> Option<PublicKey> {
+    if nsec.is_none() && bunker_uri.is_none() && BunkerConfig::load(db_path).ok()?.is_none() {
+        if let Some(stored) = NcryptsecConfig::load(db_path).ok()? {
+            return stored.public_key();
+        }
+    }
+    match SigningMode::resolve(nsec, bunker_uri, db_path, &PassphraseSource::Prompt).ok()? {
+        SigningMode::DirectKey(keys) | SigningMode::EncryptedKey(keys) => Some(keys.public_key()),
+        SigningMode::Bunker(config) => config.cached_user_pubkey().or_else(|| {
+            BunkerConfig::load(db_path).ok().flatten()?.cached_user_pubkey()
+        }),
+    }
+}
+
+fn read_passphrase(env_var: Option<&str>, confirm: bool) -> Result<String> {
+    if let Some(var) = env_var {
+        return std::env::var(var).with_context(|| format!("Environment variable {} is not set", var));
+    }
+    let passphrase = rpassword::prompt_password("Backup passphrase: ")?;
+    if confirm && rpassword::prompt_password("Confirm passphrase: ")? != passphrase {
+        anyhow::bail!("Passphrases do not match");
+    }
+    if passphrase.is_empty() {
+        anyhow::bail!("Backup passphrase must not be empty");
+    }
+    Ok(passphrase)
+}
+
+/// Passphrase for a new ncryptsec (confirmed when typed interactively)
+fn new_key_passphrase(source: &PassphraseSource) -> Result<String> {
+    let passphrase = source.read("New ncryptsec passphrase: ")?;
+    if matches!(source, PassphraseSource::Prompt)
+        && rpassword::prompt_password("Confirm passphrase: ")? != passphrase
+    {
+        anyhow::bail!("Passphrases do not match");
+    }
+    if passphrase.is_empty() {
+        anyhow::bail!("ncryptsec passphrase must not be empty");
+    }
+    Ok(passphrase)
+}
+
+/// Store a NIP-49 encrypted key next to the database (`init --encrypt-key` / `--ncryptsec`)
+fn init_encrypted_key(
+    db_path: &Path,
+    nsec: Option<&str>,
+    ncryptsec: Option<&str>,
+    passphrase: &PassphraseSource,
+) -> Result<()> {
+    if NcryptsecConfig::config_path(db_path).exists() {
+        anyhow::bail!(
+            "An encrypted key is already stored.\n\
+             Delete it first with: rm {}",
+            NcryptsecConfig::config_path(db_path).display()
+        );
+    }
+    println!("🔐 Initializing with a NIP-49 encrypted key...");
+    let config = match (ncryptsec, nsec) {
+        (Some(ncryptsec), _) => {
+            NcryptsecConfig::import(ncryptsec, &passphrase.read("ncryptsec passphrase: ")?)?
+        }
+        (None, nsec) => {
+            let keys = match nsec {
+                Some(nsec) if nsec.starts_with("nsec") => Keys::parse(nsec)?,
+                Some(hex) => Keys::new(SecretKey::from_hex(hex)?),
+                None => {
+                    println!("🔑 Generated a new identity");
+                    Keys::generate()
+                }
+            };
+            NcryptsecConfig::encrypt(&keys, &new_key_passphrase(passphrase)?)?
+        }
+    };
+
+    if let Some(parent) = db_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    config.save(db_path)?;
+
+    let pubkey = config.public_key().context("Invalid public key in ncryptsec config")?;
+    println!("✓ Encrypted key stored!");
+    println!("  npub: {}", pubkey.to_bech32().unwrap_or_default());
+    println!("  hex:  {}", pubkey);
+    println!("  Config: {}", NcryptsecConfig::config_path(db_path).display());
+    println!("\nThe passphrase is asked for at startup; for unattended use pass --passphrase-fd <FD>");
+    println!("or set MARMOT_KEY_PASSPHRASE. You no longer need --nsec / NOSTR_NSEC.");
+    println!("\nNext: marmot-cli publish-key-package");
+    Ok(())
+}
+
+fn backup_state(
+    db_path: &Path,
+    db_key: Option<[u8; 32]>,
+    identity: Option<PublicKey>,
+    out: &Path,
+    passphrase_env: Option<&str>,
+) -> Result<()> {
+    if out.exists() {
+        anyhow::bail!("{} already exists — refusing to overwrite", out.display());
+    }
+    let backup = Backup::capture(db_path, db_key.as_ref(), identity)?;
+    let passphrase = read_passphrase(passphrase_env, true)?;
+    storage::write_with_mode(out, &backup.seal(&passphrase)?, storage::PRIVATE_FILE_MODE, true)?;
+
+    println!("✓ Backup written to {}", out.display());
+    println!("  Files: {}", backup.file_names().join(", "));
+    match identity {
+        Some(pk) => println!("  Identity: {}", pk.to_bech32().unwrap_or_default()),
+        None => println!("  ⚠️  Identity unknown (no credentials) — restore can't verify it"),
+    }
+    if let Some(version) = backup.manifest.schema_version {
+        println!("  Schema version: {}", version);
+    }
+    if backup.manifest.db_encrypted {
+        println!("  ⚠️  The database is encrypted; keep its --db-key source, it is not in the backup");
+    }
+    Ok(())
+}
+
+fn restore_state(
+    db_path: &Path,
+    identity: Option<PublicKey>,
+    file: &Path,
+    passphrase_env: Option<&str>,
+    force: bool,
+) -> Result<()> {
+    let data = std::fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    let passphrase = read_passphrase(passphrase_env, false)?;
+    let backup = Backup::open(&data, &passphrase)?;
+
+    println!("Backup from {} (marmot-cli {})", backup.manifest.created_at, backup.manifest.marmot_cli_version);
+    println!("  Files: {}", backup.file_names().join(", "));
+
+    let mut problems = Vec::new();
+    match (identity, backup.user_pubkey()) {
+        (Some(ours), Some(theirs)) if ours != theirs => problems.push(format!(
+            "identity mismatch: backup belongs to {}, current identity is {}",
+            theirs.to_bech32().unwrap_or_default(),
+            ours.to_bech32().unwrap_or_default()
+        )),
+        (_, None) => problems.push("backup does not record an identity".to_string()),
+        (None, Some(_)) if db_path.exists() => problems.push(
+            "no local identity configured, so the backup can't be checked against the current state".to_string(),
+        ),
+        (None, Some(theirs)) => println!(
+            "  ⚠️  Identity not checked (no local identity); backup belongs to {}",
+            theirs.to_bech32().unwrap_or_default()
+        ),
+        _ => {}
+    }
+    let expected = storage::expected_schema_version(db_path)?;
+    if backup.manifest.schema_version != expected {
+        problems.push(format!(
+            "schema version mismatch: backup has {:?}, this binary expects {:?}",
+            backup.manifest.schema_version, expected
+        ));
+    }
+
+    if !problems.is_empty() {
+        for problem in &problems {
+            eprintln!("⚠️  {}", problem);
+        }
+        if !force {
+            anyhow::bail!("Refusing to restore. Re-run with --force to override.");
+        }
+    }
+
+    let moved = backup.restore(db_path)?;
+    println!("✓ Restored into {}", db_path.display());
+    for path in moved {
+        println!("  Previous file kept at {}", path.display());
+    }
+    if backup.manifest.db_encrypted {
+        println!("  The restored database is encrypted — use the same --db-key as when it was backed up");
+    }
+    Ok(())
+}
+
+fn verify_audit_log(db_path: &Path, identity: Option<PublicKey>, allow_missing_archives: bool) -> Result<()> {
+    let log_path = db_path.with_extension("audit.jsonl");
+    println!("=== Audit Log Verification ===\n");
+    println!("Log: {}", log_path.display());
+    if !log_path.exists() && nip46::audit::archives(&log_path).is_empty() {
+        println!("No audit log yet.");
+        return Ok(());
+    }
+
+    let report = nip46::audit::verify(&log_path, allow_missing_archives)?;
+    if report.archives > 0 {
+        println!("Archives:        {}", report.archives);
+    }
+    for name in &report.missing_archives {
+        println!("⚠️  Archive {} was deleted; its entries can't be checked", name);
+    }
+    if let Some(seq) = report.anchored_at {
+        println!("Chain start:     entry {} (older archives were removed)", seq);
+    }
+    println!("Chained entries: {}", report.entries);
+    if report.legacy > 0 {
+        println!("Legacy entries:  {} (written before hash chaining, not verifiable)", report.legacy);
+    }
+    println!("Signed entries:  {}", report.signed);
+
+    // Signatures only mean something if they come from keys we expect
+    let client_key = BunkerConfig::load(db_path)?
+        .and_then(|c| c.client_keys().ok())
+        .map(|k| k.public_key().to_hex());
+    let mut unknown_signer = false;
+    for signer in &report.signers {
+        let role = if identity.map(|pk| pk.to_hex()).as_deref() == Some(signer.as_str()) {
+            "user key"
+        } else if client_key.as_deref() == Some(signer.as_str()) {
+            "bunker client key"
+        } else {
+            unknown_signer = true;
+            "⚠️  unknown key"
+        };
+        println!("  signer {}… ({})", &signer[..16], role);
+    }
+
+    match report.problem {
+        None if unknown_signer => {
+            println!("\n⚠️  Chain intact, but some entries were signed by a key this installation doesn't use");
+            println!("   (e.g. an earlier bunker pairing). Review them before trusting the log.");
+            Ok(())
+        }
+        None => {
+            println!("\n✓ Audit log intact");
+            Ok(())
+        }
+        Some(problem) if problem.line == 0 => anyhow::bail!("Audit log verification failed: {}", problem.reason),
+        Some(problem) => anyhow::bail!(
+            "Audit log verification failed at {} line {}: {}",
+            problem.file.display(),
+            problem.line,
+            problem.reason
+        ),
+    }
+}
+
+/// `audit show`: print entries matching `filter`
+fn show_audit_log(db_path: &Path, filter: &AuditFilter, include_archives: bool, json: bool) -> Result<()> {
+    let log_path = db_path.with_extension("audit.jsonl");
+    if !log_path.exists() && nip46::audit::archives(&log_path).is_empty() {
+        println!("No audit log yet.");
+        return Ok(());
+    }
+
+    let entries = nip46::audit::read_entries(&log_path, include_archives)?;
+    let matching: Vec<_> = entries.iter().filter(|e| filter.matches(e)).collect();
+    if json {
+        for entry in matching {
+            println!("{}", serde_json::to_string(entry)?);
+        }
+        return Ok(());
+    }
+
+    for entry in &matching {
+        let when = chrono::DateTime::parse_from_rfc3339(&entry.timestamp)
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|_| entry.timestamp.clone());
+        let seq = entry.seq.map(|s| format!("#{}", s)).unwrap_or_default();
+        println!("{} {:>6}  {:<24} {}", when, seq, entry.operation, entry.details);
+        for (key, value) in &entry.context {
+            let value = match value {
+                serde_json::Value::String(s) if s.len() == 64 => format!("{}…", &s[..16]),
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            println!("{:>29}  {}: {}", "", key, value);
+        }
+    }
+    println!("\n{} of {} entries", matching.len(), entries.len());
+    Ok(())
+}
+
+fn check_database(db_path: &Path, db_key: Option<&DbKeySource>) -> Result<()> {
+    println!("=== Database Check ===\n");
+    println!("Path:      {}", db_path.display());
+    if !db_path.exists() {
+        println!("Status:    ❌ does not exist (it will be created on first use)");
+        return Ok(());
+    }
+    let encrypted = !storage::is_plaintext_sqlite(db_path)?;
+    println!("Encrypted: {}", if encrypted { "yes" } else { "no" });
+    if encrypted && db_key.is_none() {
+        anyhow::bail!("Database is encrypted — pass --db-key to check it");
+    }
+    let key = db_key.map(|source| source.load_key(db_path, false)).transpose()?;
+    let conn = storage::open_connection(db_path, key.as_ref())?;
+
+    let applied = storage::migrations(&conn)?;
+    let expected = storage::expected_migrations(db_path)?;
+    let version = |m: &[storage::Migration]| {
+        m.last().map(|(v, _)| format!("V{}", v)).unwrap_or_else(|| "none".to_string())
+    };
+    println!("Schema:    {} (this build expects {})", version(&applied), version(&expected));
+    let compat = storage::schema_compat(&applied, &expected);
+    match &compat {
+        storage::SchemaCompat::Fresh => println!("           ✓ fresh database"),
+        storage::SchemaCompat::Current => println!("           ✓ up to date"),
+        storage::SchemaCompat::Upgradable { from, to } => {
+            println!("           ⬆️  will be upgraded from V{} to V{} on next use", from, to);
+        }
+        storage::SchemaCompat::Incompatible(reason) => println!("           ❌ incompatible: {}", reason),
+    }
+
+    let integrity = storage::integrity_check(&conn)?;
+    if integrity == ["ok"] {
+        println!("Integrity: ✓ ok");
+    } else {
+        println!("Integrity: ❌ {} problem(s)", integrity.len());
+        for problem in integrity.iter().take(10) {
+            println!("           - {}", problem);
+        }
+    }
+    drop(conn);
+
+    // Only hand the database to MDK once it is fully migrated: opening a fresh or
+    // upgradable one would run the migrations this command only reports
+    if compat == storage::SchemaCompat::Current {
+        let mdk = MDK::new(storage::open_storage(db_path, key)?);
+        println!("Groups:    {}", mdk.get_groups()?.len());
+        println!("Pending welcomes: {}", mdk.get_pending_welcomes(None)?.len());
+    }
+    Ok(())
+}
+
+fn encrypt_database(db_path: &Path, source: &DbKeySource, keep_plaintext: bool) -> Result<()> {
+    println!("🔒 Encrypting {}...", db_path.display());
+    if !storage::is_plaintext_sqlite(db_path)? {
+        anyhow::bail!("Database is already encrypted");
+    }
+    let key = source.load_key(db_path, true)?;
+    let backup = storage::encrypt_in_place(db_path, &key, keep_plaintext)?;
+    println!("✓ Database encrypted");
+    if let Some(backup) = backup {
+        println!("  ⚠️  Plaintext copy kept at {} — delete it once you've verified the encrypted DB", backup.display());
+    }
+    println!("\nPass the same key source from now on, e.g.:");
+    println!("  marmot-cli --db-key {} list-chats", match source {
+        DbKeySource::Keyring => "keyring".to_string(),
+        DbKeySource::Prompt => "prompt".to_string(),
+        DbKeySource::File(path) => format!("file:{}", path.display()),
+        DbKeySource::Env(var) => format!("env:{}", var),
+    });
+    println!("  (or: marmot-cli config set db_key <source>)");
+    Ok(())
+}
+
+async fn run_accounts_command(
+    command: &AccountsCommand,
+    accounts: &Accounts,
+    passphrase: &PassphraseSource,
+    proxy: Option<SocketAddr>,
+    bunker_timeout: std::time::Duration,
+) -> Result<()> {
+    match command {
+        AccountsCommand::Add { nsec, bunker, encrypt_key, label } => {
+            let record = match (nsec, bunker) {
+                (Some(_), Some(_)) => anyhow::bail!("Use either --nsec or --bunker, not both"),
+                (None, Some(uri)) => {
+                    let mut config = BunkerConfig::from_bunker_uri(uri)?;
+                    println!("🔐 Connecting to bunker...");
+                    let connect = connect_bunker(&mut config, proxy, bunker_timeout, &AuthPolicy::default()).await?;
+                    let pubkey = connect.get_public_key().await
+                        .map_err(|e| anyhow::anyhow!("Failed to connect to bunker: {}", e))?;
+                    connect.shutdown().await;
+                    config.update_connected(Some(pubkey));
+
+                    let record = AccountRecord::new(&pubkey, label.clone(), AccountSigner::Bunker);
+                    accounts.add(&record)?;
+                    config.save(&accounts.db_path(&record))?;
+                    record
+                }
+                (nsec, None) => {
+                    let keys = match nsec {
+                        Some(nsec) if nsec.starts_with("nsec") => Keys::parse(nsec)?,
+                        Some(hex) => Keys::new(SecretKey::from_hex(hex)?),
+                        None => Keys::generate(),
+                    };
+                    // An encrypted account keeps its ncryptsec next to its database, not in account.json
+                    let encrypted = encrypt_key
+                        .then(|| NcryptsecConfig::encrypt(&keys, &new_key_passphrase(passphrase)?))
+                        .transpose()?;
+                    let signer = match encrypted {
+                        Some(_) => AccountSigner::Ncryptsec,
+                        None => AccountSigner::Nsec { nsec: keys.secret_key().to_bech32()? },
+                    };
+                    let record = AccountRecord::new(&keys.public_key(), label.clone(), signer);
+                    accounts.add(&record)?;
+                    if let Some(config) = encrypted {
+                        config.save(&accounts.db_path(&record))?;
+                    }
+                    if nsec.is_none() {
+                        println!("🔑 Generated a new identity");
+                    }
+                    record
+                }
+            };
+
+            if accounts.active()?.is_none() {
+                accounts.set_active(&record)?;
+            }
+            println!("✓ Account added: {}", record.display_name());
+            println!("  npub: {}", record.npub);
+            println!("  db:   {}", accounts.db_path(&record).display());
+            println!("\nNext: marmot-cli --account {} publish-key-package", record.label.as_deref().unwrap_or(&record.npub));
+        }
+        AccountsCommand::List => {
+            let all = accounts.list()?;
+            if all.is_empty() {
+                println!("No accounts. Add one with: marmot-cli accounts add --label <name>");
+                return Ok(());
+            }
+            let active = accounts.active()?.map(|a| a.npub);
+            println!("=== Accounts ({}) ===\n", all.len());
+            for record in &all {
+                let marker = if active.as_deref() == Some(record.npub.as_str()) { "→" } else { " " };
+                let signer = match record.signer {
+                    AccountSigner::Nsec { .. } => "nsec",
+                    AccountSigner::Ncryptsec => "ncryptsec",
+                    AccountSigner::Bunker => "bunker",
+                };
+                println!("{} {} ({})", marker, record.display_name(), signer);
+                println!("    {}", record.npub);
+            }
+        }
+        AccountsCommand::Use { account } => {
+            let record = accounts.find(account)?;
+            accounts.set_active(&record)?;
+            println!("✓ Active account: {}", record.display_name());
+        }
+        AccountsCommand::Remove { account, force } => {
+            let record = accounts.find(account)?;
+            if !force {
+                println!("Remove account {}? Its database, keys and audit log will be deleted.", record.display_name());
+                println!("Consider `marmot-cli --account {} backup <file>` first.", record.npub);
+                print!("Type 'yes' to confirm: ");
+                std::io::stdout().flush()?;
+                let mut input = String::new();
+                std::io::stdin().read_line(&mut input)?;
+                if input.trim().to_lowercase() != "yes" {
+                    println!("Cancelled.");
+                    return Ok(());
+                }
+            }
+            accounts.remove(&record)?;
+            println!("✓ Removed account {}", record.display_name());
+        }
+    }
+    Ok(())
+}
+
+async fn run_contacts_command(command: &ContactsCommand, db_path: &Path, proxy: Option<SocketAddr>) -> Result<()> {
+    let mut contacts = Contacts::load(db_path)?;
+    match command {
+        ContactsCommand::Add { alias, npub } => {
+            let (public_key, nip05) = if nip05::is_identifier(npub) {
+                let resolved = Nip05Resolver::new(db_path, proxy)?.resolve(npub).await?;
+                (resolved.public_key, Some(npub.to_lowercase()))
+            } else {
+                (PublicKey::parse(npub).context("Invalid npub or hex public key")?, None)
+            };
+            contacts.add(alias, &public_key)?;
+            if let Some(contact) = contacts.contacts.get_mut(alias) {
+                contact.nip05 = nip05;
+            }
+            contacts.save(db_path)?;
+            println!("✓ {} → {}", alias, public_key.to_bech32()?);
+        }
+        ContactsCommand::List => {
+            if contacts.contacts.is_empty() {
+                println!("No contacts. Add one with: marmot-cli contacts add <alias> <npub>");
+                return Ok(());
+            }
+            println!("=== Contacts ({}) ===", contacts.contacts.len());
+            for (alias, contact) in &contacts.contacts {
+                match &contact.nip05 {
+                    Some(nip05) => println!("  {:<16} {} ({})", alias, contact.npub, nip05),
+                    None => println!("  {:<16} {}", alias, contact.npub),
+                }
+            }
+        }
+        ContactsCommand::Remove { alias } => {
+            if !contacts.remove(alias) {
+                anyhow::bail!("No contact named '{}'", alias);
+            }
+            contacts.save(db_path)?;
+            println!("✓ Removed {}", alias);
+        }
+    }
+    Ok(())
+}
 
+fn run_config_command(
+    command: &ConfigCommand,
+    mut config_file: ConfigFile,
+    config_path: &Path,
+    requested: Option<&str>,
+) -> Result<()> {
+    match command {
+        ConfigCommand::Show => {
+            println!("# {}", config_path.display());
+            if let Some(name) = config_file.selected_name(requested) {
+                println!("# active profile: {}", name);
+            }
+            let mut shown = config_file.clone();
+            for profile in shown.profiles.values_mut() {
+                *profile = profile.redacted();
+            }
+            print!("{}", toml::to_string_pretty(&shown)?);
+        }
+        ConfigCommand::Get { key } => {
+            let value = if key == "default_profile" {
+                config_file.default_profile.clone()
+            } else {
+                config_file.profile(requested)?.get(key)?
+            };
+            match value {
+                Some(value) => println!("{}", value),
+                None => anyhow::bail!("'{}' is not set", key),
+            }
+        }
+        ConfigCommand::Set { key, value } => {
+            if key == "default_profile" {
+                config_file.default_profile = (!value.is_empty()).then(|| value.clone());
+            } else {
+                let name = config_file.selected_name(requested)
+                    .unwrap_or(config::FALLBACK_PROFILE)
+                    .to_string();
+                config_file.profiles.entry(name.clone()).or_default().set(key, value)?;
+                println!("✓ [{}] {} updated", name, key);
+            }
+            config_file.save(config_path)?;
+        }
+    }
     Ok(())
 }
 
@@ -579,10 +2671,23 @@ fn show_signer_status(db_path: &PathBuf, nsec: Option<&str>, bunker_uri: Option<
 async fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    let config_path = config::expand_home(&cli.config);
+    let config_file = ConfigFile::load(&config_path)?;
+    if let Commands::Config { command } = &cli.command {
+        return run_config_command(command, config_file, &config_path, cli.profile.as_deref());
+    }
+    let profile = config_file.profile(cli.profile.as_deref())?;
+
+    let quiet = cli.quiet.or(profile.quiet).unwrap_or(false);
     use tracing_subscriber::EnvFilter;
-    let default_filter = if cli.quiet { "warn,nostr_relay_pool=off" } else { "info" };
-    let filter = EnvFilter::try_from_default_env()
-        .unwrap_or_else(|_| EnvFilter::new(default_filter));
+    // Log lines would tear through the TUI's screen
+    let filter = match (&cli.command, quiet) {
+        // Even with RUST_LOG set
+        (Commands::Tui, _) => EnvFilter::new("off"),
+        (_, true) => EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| EnvFilter::new("warn,nostr_relay_pool=off")),
+        (_, false) => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
     let subscriber = tracing_subscriber::FmtSubscriber::builder()
         .with_env_filter(filter)
         .with_target(true)
@@ -590,31 +2695,116 @@ async fn main() -> Result<()> {
         .finish();
     tracing::subscriber::set_global_default(subscriber)?;
 
-    let db_path = PathBuf::from(cli.db.replace("~", &std::env::var("HOME").unwrap_or_default()));
-    let relay_urls: Vec<String> = cli.relays.split(',').map(|s| s.trim().to_string()).collect();
+    // CLI flags and env vars override the profile, which overrides built-in defaults
+    let relay_urls: Vec<String> = match (&cli.relays, &profile.relays) {
+        (Some(relays), _) => relays.split(',').map(|s| s.trim().to_string()).collect(),
+        (None, Some(relays)) => relays.clone(),
+        (None, None) => DEFAULT_RELAYS.split(',').map(str::to_string).collect(),
+    };
+    let proxy = cli.proxy.as_deref().or(profile.proxy.as_deref())
+        .map(relays::parse_proxy).transpose()?;
+    let db_key: Option<DbKeySource> = cli.db_key.as_deref().or(profile.db_key.as_deref())
+        .map(str::parse).transpose()?;
+    let bunker_timeout = cli.bunker_timeout.or(profile.bunker_timeout)
+        .map(std::time::Duration::from_secs)
+        .unwrap_or(nip46::DEFAULT_BUNKER_TIMEOUT);
+
+    // An fd can only be read once, so take the passphrase from it up front
+    let passphrase = match (cli.passphrase_fd, std::env::var("MARMOT_KEY_PASSPHRASE")) {
+        (Some(fd), _) => PassphraseSource::Value(PassphraseSource::Fd(fd).read("")?),
+        (None, Ok(value)) => PassphraseSource::Value(value),
+        (None, Err(_)) => PassphraseSource::Prompt,
+    };
+
+    let accounts = Accounts::new(config::expand_home(accounts::DEFAULT_ACCOUNTS_DIR));
+    if let Commands::Accounts { command } = &cli.command {
+        return run_accounts_command(command, &accounts, &passphrase, proxy, bunker_timeout).await;
+    }
+
+    // An explicit --account wins; the active account applies unless a db path was given
+    let account = match cli.account.as_deref().or(profile.account.as_deref()) {
+        Some(selector) => Some(accounts.find(selector)?),
+        None if cli.db.is_none() && profile.db.is_none() => accounts.active()?,
+        None => None,
+    };
+    let db_path = match &account {
+        Some(record) => accounts.db_path(record),
+        None => config::expand_home(cli.db.as_deref().or(profile.db.as_deref()).unwrap_or(DEFAULT_DB)),
+    };
+    let (nsec, bunker) = match account.as_ref().map(|a| &a.signer) {
+        Some(AccountSigner::Nsec { nsec }) => (Some(nsec.clone()), None),
+        // Bunker and ncryptsec accounts use the sidecar stored in the account directory
+        Some(AccountSigner::Bunker | AccountSigner::Ncryptsec) => (None, None),
+        None => (
+            cli.nsec.clone().or(profile.nsec.clone()),
+            cli.bunker.clone().or(profile.bunker.clone()),
+        ),
+    };
 
     // Handle commands that don't need full MarmotCli initialization
     match &cli.command {
         Commands::MigrateToBunker { bunker } => {
-            return migrate_to_bunker(&db_path, bunker, cli.nsec.as_deref()).await;
+            return migrate_to_bunker(&db_path, bunker, nsec.as_deref(), proxy, bunker_timeout).await;
+        }
+        Commands::Audit { command: AuditCommand::Verify { allow_missing_archives } } => {
+            let identity = local_identity(nsec.as_deref(), bunker.as_deref(), &db_path);
+            return verify_audit_log(&db_path, identity, *allow_missing_archives);
+        }
+        Commands::Audit { command: AuditCommand::Show { since, operation, kind, all, json } } => {
+            let filter = AuditFilter {
+                since: since.as_deref().map(|s| nip46::audit::parse_since(s, chrono::Utc::now())).transpose()?,
+                operation,
+                kind,
+            };
+            return show_audit_log(&db_path, &filter, all, json);
+        }
+        Commands::Db { command: DbCommand::Check } => {
+            return check_database(&db_path, db_key.as_ref());
         }
-        Commands::SignerStatus => {
-            return show_signer_status(&db_path, cli.nsec.as_deref(), cli.bunker.as_deref());
+        Commands::Db { command: DbCommand::Encrypt { keep_plaintext } } => {
+            let source = db_key.context(
+                "Choose where the key lives with --db-key (keyring, prompt, file:<path> or env:<VAR>)",
+            )?;
+            return encrypt_database(&db_path, &source, *keep_plaintext);
+        }
+        Commands::Backup { file, passphrase_env } => {
+            let key = db_key.as_ref().map(|source| source.load_key(&db_path, false)).transpose()?;
+            let identity = local_identity(nsec.as_deref(), bunker.as_deref(), &db_path);
+            return backup_state(&db_path, key, identity, file, passphrase_env.as_deref());
+        }
+        Commands::Restore { file, passphrase_env, force } => {
+            let identity = local_identity(nsec.as_deref(), bunker.as_deref(), &db_path);
+            return restore_state(&db_path, identity, file, passphrase_env.as_deref(), *force);
+        }
+        Commands::SignerStatus { permissions } => {
+            return show_signer_status(&db_path, nsec.as_deref(), bunker.as_deref(), *permissions);
+        }
+        Commands::Contacts { command } => {
+            return run_contacts_command(command, &db_path, proxy).await;
         }
         _ => {}
     }
 
+    // For Init with an encrypted key, store the ncryptsec and stop (no network needed)
+    if let Commands::Init { nsec: init_nsec, ncryptsec, encrypt_key, .. } = &cli.command {
+        if *encrypt_key || ncryptsec.is_some() {
+            let nsec = init_nsec.as_deref().or(nsec.as_deref());
+            return init_encrypted_key(&db_path, nsec, ncryptsec.as_deref(), &passphrase);
+        }
+    }
+
+    // For Init with nostrconnect://, wait for the signer app to pair
+    if let Commands::Init { nostrconnect: Some(pairing_relays), .. } = &cli.command {
+        return init_nostrconnect(&db_path, pairing_relays, proxy, bunker_timeout).await;
+    }
+
     // For Init with bunker, handle specially
-    if let Commands::Init { nsec: _init_nsec, bunker: Some(bunker_uri) } = &cli.command {
+    if let Commands::Init { bunker: Some(bunker_uri), .. } = &cli.command {
         // Store bunker config and show identity
         let mut config = BunkerConfig::from_bunker_uri(bunker_uri)?;
         println!("🔐 Initializing with NIP-46 bunker...");
 
-        let uri = config.to_nostr_connect_uri()?;
-        let client_keys = config.client_keys()?;
-        let connect = nostr_connect::prelude::NostrConnect::new(
-            uri, client_keys, std::time::Duration::from_secs(30), None,
-        ).map_err(|e| anyhow::anyhow!("Failed to create NIP-46 client: {}", e))?;
+        let connect = connect_bunker(&mut config, proxy, bunker_timeout, &AuthPolicy::default()).await?;
 
         let pubkey = connect.get_public_key().await
             .map_err(|e| anyhow::anyhow!("Failed to connect to bunker: {}", e))?;
@@ -638,10 +2828,27 @@ async fn main() -> Result<()> {
     // Merge init nsec with global nsec
     let effective_nsec = match &cli.command {
         Commands::Init { nsec: Some(init_nsec), .. } => Some(init_nsec.clone()),
-        _ => cli.nsec,
+        _ => nsec,
     };
 
-    let marmot = MarmotCli::new(db_path, effective_nsec, cli.bunker, relay_urls).await?;
+    let connect = cli.command.needs_network().then(|| ConnectOptions {
+        quorum: cli.connect_quorum.or(profile.connect_quorum).unwrap_or(1),
+        timeout: std::time::Duration::from_secs(
+            cli.connect_timeout.or(profile.connect_timeout).unwrap_or(10),
+        ),
+    });
+
+    let auth = match (&cli.auth_relays, &profile.auth_relays) {
+        (Some(spec), _) => AuthPolicy::parse(spec),
+        (None, Some(list)) => AuthPolicy::parse(&list.join(",")),
+        (None, None) => AuthPolicy::default(),
+    };
+    let network = NetworkOptions { relay_urls, auth, proxy, bunker_timeout, connect };
+    let marmot = MarmotCli::new(db_path, db_key.clone(), effective_nsec, bunker, &passphrase, network.clone()).await?
+        .with_welcome_cap(profile.max_welcomes_per_hour);
+    let audit_rotation = RotationPolicy::from_profile(profile.audit_max_size, profile.audit_max_age);
+    marmot.signer.set_audit_rotation(audit_rotation).await;
+    let retention = Retention::from_profile(profile.message_retention.as_deref(), profile.message_expiration)?;
 
     match cli.command {
         Commands::Init { .. } => {
@@ -657,11 +2864,17 @@ async fn main() -> Result<()> {
         Commands::CreateChat { npub, name } => {
             marmot.create_chat(&npub, name).await?;
         }
+        Commands::Verify { group, member, mark, unmark } => {
+            marmot.verify_member(&group, member.as_deref(), mark, unmark).await?;
+        }
         Commands::ListChats => {
             marmot.list_chats()?;
         }
+        Commands::History { group, limit } => {
+            marmot.history(&group, limit)?;
+        }
         Commands::Send { group, message } => {
-            marmot.send_message(&group, &message).await?;
+            marmot.send_message(&group, &message, &retention).await?;
         }
         Commands::Receive => {
             println!("Checking for new messages...");
@@ -671,38 +2884,95 @@ async fn main() -> Result<()> {
             } else {
                 println!("\n--- {} welcome(s), {} message(s) ---", welcomes, messages);
             }
+            match marmot.purge_expired_messages(&retention).await {
+                Ok(0) => {}
+                Ok(n) => println!("🗑️  Deleted {} message(s) past their retention limit", n),
+                Err(e) => eprintln!("⚠️  Message purge failed: {}", e),
+            }
         }
         Commands::AcceptWelcome { event_id } => {
             marmot.accept_welcome(&event_id).await?;
         }
-        Commands::Listen { interval, on_message } => {
+        Commands::Listen { interval, on_message, all_accounts, rotate_every } => {
+            let on_message = on_message.or(profile.on_message.clone());
+            let rotate_every = rotate_every.as_deref().map(config::parse_duration).transpose()
+                .context("Invalid --rotate-every")?;
+            let own_npub = marmot.signer.public_key().to_bech32().unwrap_or_default();
+            let mut listeners = vec![marmot];
+            if all_accounts {
+                for record in accounts.list()? {
+                    if record.npub == own_npub {
+                        continue;
+                    }
+                    let nsec = match &record.signer {
+                        AccountSigner::Nsec { nsec } => Some(nsec.clone()),
+                        AccountSigner::Bunker | AccountSigner::Ncryptsec => None,
+                    };
+                    // Each account gets its own client: a shared pool would sign NIP-42 AUTH
+                    // as the first account and let relays link the identities
+                    let db_path = accounts.db_path(&record);
+                    let opened = MarmotCli::new(db_path, db_key.clone(), nsec, None, &passphrase, network.clone()).await;
+                    match opened {
+                        Ok(other) => listeners.push(other.with_welcome_cap(profile.max_welcomes_per_hour)),
+                        Err(e) => eprintln!("⚠️  Skipping account {}: {}", record.display_name(), e),
+                    }
+                }
+                println!("Serving {} account(s) concurrently, each with its own relay connections", listeners.len());
+            }
+            for marmot in &mut listeners {
+                marmot.enable_offline_queue();
+                marmot.signer.set_audit_rotation(audit_rotation).await;
+            }
             if let Some(ref script) = on_message {
                 println!("Listening for messages with callback (Ctrl+C to stop, poll every {}s)...", interval);
                 println!("Callback: {}", script);
             } else {
                 println!("Listening for messages (Ctrl+C to stop, poll every {}s)...", interval);
             }
-            loop {
-                let (w, m, payloads) = marmot.receive_messages().await?;
-                if let Some(ref script) = on_message {
-                    for payload in &payloads {
-                        if payload.is_me { continue; }
-                        match MarmotCli::invoke_callback(script, payload) {
-                            Ok(0) => { tracing::debug!("Callback succeeded for message {}", payload.message_id); }
-                            Ok(code) => { eprintln!("⚠️ Callback exited with code {} for message {}", code, &payload.message_id[..16]); }
-                            Err(e) => { eprintln!("❌ Callback failed for message {}: {}", &payload.message_id[..16], e); }
-                        }
+            let options = std::rc::Rc::new(ListenOptions {
+                interval,
+                on_message,
+                rotate_every,
+                retention,
+                profile: profile.clone(),
+                label_accounts: listeners.len() > 1,
+            });
+            // One task per account, so a slow relay round for one doesn't hold up the others
+            let local = tokio::task::LocalSet::new();
+            local
+                .run_until(async move {
+                    let tasks: Vec<_> = listeners
+                        .into_iter()
+                        .map(|marmot| {
+                            let options = options.clone();
+                            tokio::task::spawn_local(async move { marmot.listen(&options).await })
+                        })
+                        .collect();
+                    for task in tasks {
+                        task.await.context("Listener task failed")?;
                     }
-                }
-                if w > 0 || m > 0 {
-                    println!("--- {} welcome(s), {} message(s) ---", w, m);
-                }
-                tokio::time::sleep(tokio::time::Duration::from_secs(interval)).await;
-            }
+                    anyhow::Ok(())
+                })
+                .await?;
+        }
+        Commands::Tui => {
+            tui::run(&marmot, &retention).await?;
         }
         Commands::FetchKeyPackage { npub } => {
             marmot.fetch_key_package(&npub).await?;
         }
+        Commands::Block { user, publish } => {
+            marmot.set_blocked(&user, true, publish).await?;
+        }
+        Commands::Unblock { user, publish } => {
+            marmot.set_blocked(&user, false, publish).await?;
+        }
+        Commands::Blocked => {
+            marmot.list_blocked()?;
+        }
+        Commands::AddMembers { group, members } => {
+            marmot.add_members(&group, &members).await?;
+        }
         Commands::LeaveChat { group, force } => {
             let mls_group_id = marmot.resolve_group_id(&group)?;
             let groups = marmot.mdk.get_groups()?;
@@ -722,7 +2992,26 @@ async fn main() -> Result<()> {
             }
             marmot.leave_chat(&group).await?;
         }
-        Commands::MigrateToBunker { .. } | Commands::SignerStatus => {
+        Commands::Relays { command: RelaysCommand::Status } => {
+            marmot.relays_status().await?;
+        }
+        Commands::RotateKeys { group, all: _ } => {
+            marmot.rotate_keys(group.as_deref()).await?;
+        }
+        Commands::Group { command: GroupCommand::Health { group } } => {
+            marmot.group_health(group.as_deref())?;
+        }
+        Commands::Group { command: GroupCommand::Retention { group, keep, expire_outgoing, clear } } => {
+            marmot.group_retention(&group, keep.as_deref(), expire_outgoing, clear, &retention)?;
+        }
+        Commands::MigrateToBunker { .. }
+        | Commands::SignerStatus { .. }
+        | Commands::Config { .. }
+        | Commands::Db { .. }
+        | Commands::Audit { .. }
+        | Commands::Accounts { .. }
+        | Commands::Backup { .. }
+        | Commands::Restore { .. } => {
             unreachable!("Handled above");
         }
     }
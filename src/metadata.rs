@@ -0,0 +1,202 @@
+//! Display names from kind-0 profile metadata
+//!
+//! `receive` fetches the kind-0 events of group members whose cached entry is
+//! missing or older than [`REFRESH_HOURS`] and stores name, display name,
+//! NIP-05 and picture in `marmot.metadata.json`. `list-chats`, `receive` and
+//! `history` then show names instead of npubs, offline from the cache.
+//!
+//! Profile names are chosen by their owners, so anyone can call themselves
+//! "Alice". A contact alias (set by us) always wins, and a profile name is
+//! shown with a short npub prefix so it can't pass for one.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use nostr::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::contacts::Contacts;
+
+/// Cached profiles older than this are fetched again
+pub const REFRESH_HOURS: i64 = 24;
+
+/// npub characters shown next to a profile name
+const NPUB_PREFIX_LEN: usize = 16;
+
+/// The parts of a kind-0 profile we keep
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemberProfile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nip05: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub picture: Option<String>,
+    /// When we last asked relays (also set when no profile was found)
+    pub fetched_at: String,
+}
+
+impl MemberProfile {
+    pub fn from_metadata(metadata: &Metadata, fetched_at: &str) -> Self {
+        Self {
+            name: metadata.name.clone(),
+            display_name: metadata.display_name.clone(),
+            nip05: metadata.nip05.clone(),
+            picture: metadata.picture.clone(),
+            fetched_at: fetched_at.to_string(),
+        }
+    }
+
+    /// `display_name`, falling back to `name` (control characters removed)
+    pub fn best_name(&self) -> Option<String> {
+        [&self.display_name, &self.name]
+            .into_iter()
+            .flatten()
+            .map(|n| n.chars().filter(|c| !c.is_control()).collect::<String>().trim().to_string())
+            .find(|n| !n.is_empty())
+    }
+
+    fn is_fresh(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        chrono::DateTime::parse_from_rfc3339(&self.fetched_at)
+            .is_ok_and(|at| now - at.with_timezone(&chrono::Utc) < chrono::Duration::hours(REFRESH_HOURS))
+    }
+}
+
+/// Contents of `marmot.metadata.json`, keyed by public key (hex)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileCache {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, MemberProfile>,
+}
+
+impl ProfileCache {
+    pub fn config_path(db_path: &Path) -> PathBuf {
+        db_path.with_extension("metadata.json")
+    }
+
+    /// Load the cache (missing or unreadable file = empty)
+    pub fn load(db_path: &Path) -> Self {
+        std::fs::read_to_string(Self::config_path(db_path))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    /// Save atomically with owner-only permissions
+    pub fn save(&self, db_path: &Path) -> Result<()> {
+        crate::storage::save_json_atomic(&Self::config_path(db_path), self, crate::storage::PRIVATE_FILE_MODE)
+    }
+
+    pub fn get(&self, public_key: &PublicKey) -> Option<&MemberProfile> {
+        self.profiles.get(&public_key.to_hex())
+    }
+
+    /// Keys among `members` with no profile or one due for a refresh
+    pub fn stale(&self, members: &[PublicKey], now: chrono::DateTime<chrono::Utc>) -> Vec<PublicKey> {
+        let mut stale: Vec<PublicKey> = members
+            .iter()
+            .filter(|pk| !self.get(pk).is_some_and(|p| p.is_fresh(now)))
+            .copied()
+            .collect();
+        stale.sort();
+        stale.dedup();
+        stale
+    }
+
+    /// Store the newest kind-0 event per author; `requested` without one are
+    /// recorded as fetched so they aren't asked for again until the refresh
+    pub fn update(&mut self, requested: &[PublicKey], events: &[Event], now: chrono::DateTime<chrono::Utc>) {
+        let fetched_at = now.to_rfc3339();
+        let mut newest: BTreeMap<PublicKey, &Event> = BTreeMap::new();
+        for event in events.iter().filter(|e| e.kind == Kind::Metadata) {
+            if newest.get(&event.pubkey).is_none_or(|seen| event.created_at > seen.created_at) {
+                newest.insert(event.pubkey, event);
+            }
+        }
+        for public_key in requested {
+            let profile = newest
+                .get(public_key)
+                .and_then(|event| Metadata::from_json(&event.content).ok())
+                .map(|metadata| MemberProfile::from_metadata(&metadata, &fetched_at));
+            let entry = self.profiles.entry(public_key.to_hex()).or_default();
+            match profile {
+                Some(profile) => *entry = profile,
+                // Keep what we had; just don't ask again yet
+                None => entry.fetched_at = fetched_at.clone(),
+            }
+        }
+    }
+
+    /// Kind-0 name, if the member has one
+    pub fn name(&self, public_key: &PublicKey) -> Option<String> {
+        self.get(public_key).and_then(MemberProfile::best_name)
+    }
+}
+
+/// How to show a member: contact alias, else `Name (npub1…)`, else the npub
+pub fn label(public_key: &PublicKey, contacts: &Contacts, profiles: &ProfileCache) -> String {
+    if let Some(alias) = contacts.alias_for(public_key) {
+        return alias.to_string();
+    }
+    let npub = public_key.to_bech32().unwrap_or_else(|_| public_key.to_hex());
+    match profiles.name(public_key) {
+        Some(name) => format!("{} ({}…)", name, &npub[..NPUB_PREFIX_LEN.min(npub.len())]),
+        None => npub,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata_event(keys: &Keys, content: &str, at: u64) -> Event {
+        EventBuilder::new(Kind::Metadata, content)
+            .custom_created_at(Timestamp::from(at))
+            .sign_with_keys(keys)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_update_keeps_newest_and_marks_missing() {
+        let alice = Keys::generate();
+        let bob = Keys::generate().public_key();
+        let now = chrono::Utc::now();
+        let mut cache = ProfileCache::default();
+        assert_eq!(cache.stale(&[alice.public_key(), bob, bob], now).len(), 2);
+
+        let events = vec![
+            metadata_event(&alice, r#"{"name":"old"}"#, 100),
+            metadata_event(&alice, r#"{"name":"alice","display_name":"Alice\n","nip05":"alice@example.com"}"#, 200),
+        ];
+        cache.update(&[alice.public_key(), bob], &events, now);
+
+        assert_eq!(cache.name(&alice.public_key()).as_deref(), Some("Alice"));
+        assert_eq!(cache.get(&alice.public_key()).unwrap().nip05.as_deref(), Some("alice@example.com"));
+        assert_eq!(cache.name(&bob), None);
+        assert!(cache.stale(&[alice.public_key(), bob], now).is_empty());
+
+        let later = now + chrono::Duration::hours(REFRESH_HOURS + 1);
+        assert_eq!(cache.stale(&[alice.public_key()], later).len(), 1);
+    }
+
+    #[test]
+    fn test_label_prefers_alias_and_marks_profile_names() {
+        let alice = Keys::generate();
+        let now = chrono::Utc::now();
+        let mut cache = ProfileCache::default();
+        cache.update(&[alice.public_key()], &[metadata_event(&alice, r#"{"name":"Alice"}"#, 1)], now);
+
+        let mut contacts = Contacts::default();
+        let shown = label(&alice.public_key(), &contacts, &cache);
+        assert!(shown.starts_with("Alice (npub1"));
+
+        contacts.add("ally", &alice.public_key()).unwrap();
+        assert_eq!(label(&alice.public_key(), &contacts, &cache), "ally");
+
+        let stranger = Keys::generate().public_key();
+        assert_eq!(label(&stranger, &contacts, &cache), stranger.to_bech32().unwrap());
+    }
+}
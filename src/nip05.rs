@@ -0,0 +1,292 @@
+//! NIP-05 identifiers (`alice@example.com`)
+//!
+//! Resolves an identifier through `https://<domain>/.well-known/nostr.json`
+//! to a public key plus the relays the domain advertises for it, which
+//! `fetch-key-package` and `create-chat` then search for the key package.
+//! Results are cached in `marmot.nip05.json` next to the database for
+//! [`CACHE_TTL_HOURS`], so repeated commands don't hit the domain every time.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use nostr::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How long a resolution is trusted before asking the domain again
+pub const CACHE_TTL_HOURS: i64 = 24;
+
+/// Whether `input` looks like a NIP-05 identifier rather than a key or alias
+pub fn is_identifier(input: &str) -> bool {
+    input.contains('@')
+}
+
+/// A parsed `name@domain`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nip05Address {
+    pub name: String,
+    pub domain: String,
+}
+
+impl Nip05Address {
+    pub fn parse(input: &str) -> Result<Self> {
+        let (name, domain) = input
+            .trim()
+            .split_once('@')
+            .with_context(|| format!("'{}' is not a NIP-05 identifier (name@domain)", input))?;
+        let name = name.to_lowercase();
+        let domain = domain.to_lowercase();
+        let valid_name = !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+        if !valid_name || !domain.contains('.') || domain.contains(['/', '?', '#', '@']) {
+            anyhow::bail!("'{}' is not a valid NIP-05 identifier", input);
+        }
+        Ok(Self { name, domain })
+    }
+
+    /// Well-known URL for this identifier
+    fn url(&self, scheme: &str) -> String {
+        format!("{}://{}/.well-known/nostr.json?name={}", scheme, self.domain, self.name)
+    }
+}
+
+impl std::fmt::Display for Nip05Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.name, self.domain)
+    }
+}
+
+/// `/.well-known/nostr.json` contents
+#[derive(Debug, Deserialize)]
+struct Nip05Document {
+    #[serde(default)]
+    names: BTreeMap<String, String>,
+    #[serde(default)]
+    relays: BTreeMap<String, Vec<String>>,
+}
+
+/// A resolved identifier
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolved {
+    pub public_key: PublicKey,
+    /// Relays the domain advertises for this key (may be empty)
+    pub relays: Vec<RelayUrl>,
+}
+
+/// Resolve `address` from a well-known document
+fn resolve_document(address: &Nip05Address, document: &Nip05Document) -> Result<Resolved> {
+    let hex = document
+        .names
+        .iter()
+        .find(|(name, _)| name.to_lowercase() == address.name)
+        .map(|(_, hex)| hex)
+        .with_context(|| format!("{} is not listed by {}", address, address.domain))?;
+    let public_key = PublicKey::from_hex(hex).with_context(|| format!("{} lists an invalid key for {}", address.domain, address))?;
+    let relays = document
+        .relays
+        .get(hex)
+        .into_iter()
+        .flatten()
+        .filter_map(|url| RelayUrl::parse(url).ok())
+        .collect();
+    Ok(Resolved { public_key, relays })
+}
+
+/// One cached resolution
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResolution {
+    pub pubkey: String,
+    #[serde(default)]
+    pub relays: Vec<String>,
+    pub resolved_at: String,
+}
+
+impl CachedResolution {
+    fn is_fresh(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        chrono::DateTime::parse_from_rfc3339(&self.resolved_at)
+            .is_ok_and(|at| now - at.with_timezone(&chrono::Utc) < chrono::Duration::hours(CACHE_TTL_HOURS))
+    }
+
+    fn resolved(&self) -> Result<Resolved> {
+        Ok(Resolved {
+            public_key: PublicKey::from_hex(&self.pubkey).context("Invalid key in NIP-05 cache")?,
+            relays: self.relays.iter().filter_map(|url| RelayUrl::parse(url).ok()).collect(),
+        })
+    }
+}
+
+/// Contents of `marmot.nip05.json`, keyed by identifier
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Nip05Cache {
+    #[serde(default)]
+    pub entries: BTreeMap<String, CachedResolution>,
+}
+
+impl Nip05Cache {
+    pub fn config_path(db_path: &Path) -> PathBuf {
+        db_path.with_extension("nip05.json")
+    }
+
+    /// Load the cache (missing or unreadable file = empty)
+    pub fn load(db_path: &Path) -> Self {
+        std::fs::read_to_string(Self::config_path(db_path))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    /// Save atomically with owner-only permissions
+    pub fn save(&self, db_path: &Path) -> Result<()> {
+        crate::storage::save_json_atomic(&Self::config_path(db_path), self, crate::storage::PRIVATE_FILE_MODE)
+    }
+}
+
+/// Resolves identifiers over HTTP, through the cache
+pub struct Nip05Resolver {
+    db_path: PathBuf,
+    http: reqwest::Client,
+    scheme: &'static str,
+}
+
+impl Nip05Resolver {
+    pub fn new(db_path: &Path, proxy: Option<SocketAddr>) -> Result<Self> {
+        // NIP-05: fetchers must ignore redirects
+        let http = crate::relays::http_client_builder(proxy)?
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .context("Failed to build HTTP client")?;
+        Ok(Self { db_path: db_path.to_path_buf(), http, scheme: "https" })
+    }
+
+    /// Resolve `identifier`, using a fresh cache entry when there is one
+    pub async fn resolve(&self, identifier: &str) -> Result<Resolved> {
+        let address = Nip05Address::parse(identifier)?;
+        let key = address.to_string();
+        let now = chrono::Utc::now();
+        let mut cache = Nip05Cache::load(&self.db_path);
+        if let Some(entry) = cache.entries.get(&key).filter(|e| e.is_fresh(now)) {
+            return entry.resolved();
+        }
+
+        let document: Nip05Document = self
+            .http
+            .get(address.url(self.scheme))
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", address.domain))?
+            .error_for_status()
+            .with_context(|| format!("{} refused the NIP-05 lookup", address.domain))?
+            .json()
+            .await
+            .with_context(|| format!("{} returned an invalid nostr.json", address.domain))?;
+        let resolved = resolve_document(&address, &document)?;
+
+        cache.entries.insert(
+            key,
+            CachedResolution {
+                pubkey: resolved.public_key.to_hex(),
+                relays: resolved.relays.iter().map(|r| r.to_string()).collect(),
+                resolved_at: now.to_rfc3339(),
+            },
+        );
+        if let Err(e) = cache.save(&self.db_path) {
+            tracing::warn!("Could not save NIP-05 cache: {}", e);
+        }
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// Serve `body` to each of `requests` connections; returns the domain and the request lines
+    fn stand_in(body: String, requests: usize) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let domain = listener.local_addr().unwrap().to_string();
+        let handle = std::thread::spawn(move || {
+            let mut seen = Vec::new();
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut buf = [0u8; 4096];
+                let n = stream.read(&mut buf).unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                seen.push(request.lines().next().unwrap_or_default().to_string());
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            seen
+        });
+        (domain, handle)
+    }
+
+    fn resolver(db_path: &Path) -> Nip05Resolver {
+        let mut resolver = Nip05Resolver::new(db_path, None).unwrap();
+        resolver.scheme = "http";
+        resolver
+    }
+
+    #[test]
+    fn test_parse_address() {
+        let address = Nip05Address::parse("Alice@Example.com").unwrap();
+        assert_eq!(address.to_string(), "alice@example.com");
+        assert!(Nip05Address::parse("alice").is_err());
+        assert!(Nip05Address::parse("@example.com").is_err());
+        assert!(Nip05Address::parse("alice@nodot").is_err());
+        assert!(Nip05Address::parse("alice@example.com/evil").is_err());
+        assert!(is_identifier("_@example.com"));
+        assert!(!is_identifier("npub1abc"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_relays_and_cache() {
+        let alice = Keys::generate().public_key();
+        let body = serde_json::json!({
+            "names": { "alice": alice.to_hex() },
+            "relays": { alice.to_hex(): ["wss://relay.example.com", "not a relay"] },
+        })
+        .to_string();
+        let (domain, server) = stand_in(body, 1);
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("marmot.db");
+        let resolver = resolver(&db_path);
+
+        let identifier = format!("alice@{}", domain);
+        let resolved = resolver.resolve(&identifier).await.unwrap();
+        assert_eq!(resolved.public_key, alice);
+        assert_eq!(resolved.relays, vec![RelayUrl::parse("wss://relay.example.com").unwrap()]);
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("GET /.well-known/nostr.json?name=alice "));
+
+        // The stand-in is gone; the cached answer is used
+        assert_eq!(resolver.resolve(&identifier).await.unwrap(), resolved);
+
+        // An expired entry is fetched again (and fails without a server)
+        let mut cache = Nip05Cache::load(&db_path);
+        for entry in cache.entries.values_mut() {
+            entry.resolved_at = (chrono::Utc::now() - chrono::Duration::hours(CACHE_TTL_HOURS + 1)).to_rfc3339();
+        }
+        cache.save(&db_path).unwrap();
+        assert!(resolver.resolve(&identifier).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_name_is_not_cached() {
+        let (domain, server) = stand_in(r#"{"names":{}}"#.to_string(), 1);
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("marmot.db");
+
+        let err = resolver(&db_path).resolve(&format!("bob@{}", domain)).await.unwrap_err();
+        assert!(err.to_string().contains("not listed"));
+        server.join().unwrap();
+        assert!(Nip05Cache::load(&db_path).entries.is_empty());
+    }
+}
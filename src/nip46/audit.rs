@@ -2,24 +2,149 @@
 //!
 //! Records all signing requests, bunker connections, and key migrations
 //! to a local log file for security review.
+//!
+//! Entries form a hash chain: each one carries the SHA-256 of the previous
+//! entry, and its own hash covers every other field. When a signing key is
+//! set (the user key in direct mode, the NIP-46 client key in bunker mode)
+//! the hash is also Schnorr-signed. The latest sequence number and hash are
+//! mirrored in `marmot.audit.head`, signed by the same key, so `audit verify`
+//! can detect truncation.
+//!
+//! Besides the human-readable `details`, entries carry a structured
+//! `context` object (event ID, kind, group ID, relay results, callback exit
+//! code, ...) that `audit show` filters on. Once the log exceeds its
+//! [`RotationPolicy`] it is gzipped to `marmot.audit.<timestamp>.jsonl.gz`
+//! and the fresh log opens with a `log_rotated` entry that continues the
+//! chain and records the archive's name and SHA-256, so archives and the live
+//! log verify as one sequence and a deleted or swapped archive is noticed.
 
+use std::io::{BufRead, Read, Write};
 use std::path::{Path, PathBuf};
-use std::io::Write;
 
-use serde::Serialize;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use nostr::hashes::{sha256, Hash};
+use nostr::prelude::*;
+use nostr::secp256k1::{schnorr, Message, Secp256k1, VerifyOnly};
+use serde::{Deserialize, Serialize};
+
+/// `prev_hash` of the first chained entry
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Operation that opens a log after rotation
+const ROTATED_OPERATION: &str = "log_rotated";
 
 /// Audit log entry
-#[derive(Debug, Serialize)]
-struct AuditEntry {
-    timestamp: String,
-    operation: String,
-    details: String,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Position in the chain (absent on entries written before chaining)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    pub timestamp: String,
+    pub operation: String,
+    pub details: String,
+    /// Structured fields (`event_id`, `kind`, `group_id`, `relays_ok`, ...)
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub context: serde_json::Map<String, serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prev_hash: Option<String>,
+    /// Public key (hex) whose signature is in `sig`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sig: Option<String>,
+}
+
+impl AuditEntry {
+    /// SHA-256 over every field except `hash` and `sig`
+    fn compute_hash(&self) -> [u8; 32] {
+        let unsealed = AuditEntry { hash: None, sig: None, ..self.clone() };
+        let json = serde_json::to_vec(&unsealed).unwrap_or_default();
+        sha256::Hash::hash(&json).to_byte_array()
+    }
+
+    /// Event kind recorded in the context, if any
+    pub fn kind(&self) -> Option<u16> {
+        self.context.get("kind").and_then(|v| v.as_u64()).and_then(|k| u16::try_from(k).ok())
+    }
+}
+
+/// Last chained entry, stored next to the log
+///
+/// Signed like the entry it points at, so a truncated log can't be hidden by
+/// rewriting the head to match.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ChainHead {
+    seq: u64,
+    hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sig: Option<String>,
+}
+
+impl ChainHead {
+    fn new(seq: u64, hash: String, signer: Option<String>) -> Self {
+        Self { seq, hash, signer, sig: None }
+    }
+
+    /// What the head signature covers (prefixed so it can't pass for an entry hash)
+    fn digest(&self) -> [u8; 32] {
+        sha256::Hash::hash(format!("marmot-audit-head:{}:{}", self.seq, self.hash).as_bytes()).to_byte_array()
+    }
+
+    /// Whether both point at the same entry
+    fn same_entry(&self, other: &ChainHead) -> bool {
+        self.seq == other.seq && self.hash == other.hash
+    }
+}
+
+/// Whether `sig` is `signer`'s Schnorr signature over `digest`
+fn signature_valid(secp: &Secp256k1<VerifyOnly>, signer: &str, sig: &str, digest: [u8; 32]) -> bool {
+    PublicKey::from_hex(signer).ok()
+        .and_then(|pk| pk.xonly().ok())
+        .zip(sig.parse::<schnorr::Signature>().ok())
+        .is_some_and(|(xonly, sig)| secp.verify_schnorr(&sig, &Message::from_digest(digest), &xonly).is_ok())
+}
+
+/// When the live log is archived
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RotationPolicy {
+    /// Rotate once the log reaches this many bytes (0 = never)
+    pub max_bytes: u64,
+    /// Rotate once the oldest entry in the log is this old
+    pub max_age: Option<chrono::Duration>,
+}
+
+impl RotationPolicy {
+    /// 10 MiB, no age limit
+    pub const DEFAULT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+    /// Policy from the profile's `audit_max_size` (MiB) and `audit_max_age` (days)
+    pub fn from_profile(max_size_mib: Option<u64>, max_age_days: Option<u64>) -> Self {
+        Self {
+            max_bytes: max_size_mib.map(|m| m.saturating_mul(1024 * 1024)).unwrap_or(Self::DEFAULT_MAX_BYTES),
+            max_age: max_age_days.filter(|d| *d > 0).map(|d| chrono::Duration::days(d as i64)),
+        }
+    }
+}
+
+impl Default for RotationPolicy {
+    fn default() -> Self {
+        Self { max_bytes: Self::DEFAULT_MAX_BYTES, max_age: None }
+    }
 }
 
 /// Append-only audit log for signing operations
 pub struct AuditLog {
     path: PathBuf,
     enabled: bool,
+    signing_keys: Option<Keys>,
+    rotation: RotationPolicy,
 }
 
 impl AuditLog {
@@ -29,6 +154,8 @@ impl AuditLog {
         Self {
             path,
             enabled: true,
+            signing_keys: None,
+            rotation: RotationPolicy::default(),
         }
     }
 
@@ -38,20 +165,114 @@ impl AuditLog {
         Self {
             path: PathBuf::from("/dev/null"),
             enabled: false,
+            signing_keys: None,
+            rotation: RotationPolicy::default(),
+        }
+    }
+
+    /// Sign subsequent entries with this key
+    pub fn set_signing_keys(&mut self, keys: Keys) {
+        self.signing_keys = Some(keys);
+    }
+
+    /// Archive the log according to `policy`
+    pub fn set_rotation(&mut self, policy: RotationPolicy) {
+        self.rotation = policy;
+    }
+
+    fn head_path(&self) -> PathBuf {
+        head_path(&self.path)
+    }
+
+    /// Current chain head: the head file, or the log's last line if the head is missing
+    fn current_head(&self) -> Option<ChainHead> {
+        if let Some(head) = std::fs::read_to_string(self.head_path())
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+        {
+            return Some(head);
         }
+        let file = std::fs::File::open(&self.path).ok()?;
+        let last = std::io::BufReader::new(file)
+            .lines()
+            .map_while(|l| l.ok())
+            .filter(|l| !l.trim().is_empty())
+            .last()?;
+        let entry: AuditEntry = serde_json::from_str(&last).ok()?;
+        Some(ChainHead::new(entry.seq?, entry.hash?, entry.signer))
     }
 
     /// Record an audit event
     pub fn record(&mut self, operation: &str, details: &str) {
+        self.record_with(operation, details, serde_json::Value::Null);
+    }
+
+    /// Record an audit event with structured context (a JSON object)
+    pub fn record_with(&mut self, operation: &str, details: &str, context: serde_json::Value) {
         if !self.enabled {
             return;
         }
+        // `listen` and `send`, or several accounts, may append at once: hold the
+        // lock from reading the head until the new one is written
+        let _lock = match self.lock() {
+            Ok(lock) => Some(lock),
+            Err(e) => {
+                tracing::warn!("Could not lock the audit log: {}", e);
+                None
+            }
+        };
+        if self.rotation_due() {
+            match self.rotate() {
+                Ok(archive) => {
+                    let name = archive.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+                    let sha256 = file_sha256(&archive).unwrap_or_default();
+                    self.append(
+                        ROTATED_OPERATION,
+                        "previous entries archived",
+                        serde_json::json!({ "archive": name, "sha256": sha256 }),
+                    );
+                }
+                Err(e) => tracing::warn!("Audit log rotation failed: {}", e),
+            }
+        }
+        self.append(operation, details, context);
+    }
+
+    /// Exclusive lock on `marmot.audit.lock`, released when the file is dropped
+    ///
+    /// A separate file, because rotation replaces the log itself.
+    fn lock(&self) -> std::io::Result<std::fs::File> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(self.path.with_extension("lock"))?;
+        file.lock()?;
+        Ok(file)
+    }
 
-        let entry = AuditEntry {
+    fn append(&mut self, operation: &str, details: &str, context: serde_json::Value) {
+        let head = self.current_head();
+        let mut entry = AuditEntry {
+            seq: Some(head.as_ref().map(|h| h.seq + 1).unwrap_or(0)),
             timestamp: chrono::Utc::now().to_rfc3339(),
             operation: operation.to_string(),
             details: details.to_string(),
+            context: match context {
+                serde_json::Value::Object(map) => map,
+                _ => serde_json::Map::new(),
+            },
+            prev_hash: Some(head.map(|h| h.hash).unwrap_or_else(|| GENESIS_HASH.to_string())),
+            signer: self.signing_keys.as_ref().map(|k| k.public_key().to_hex()),
+            hash: None,
+            sig: None,
         };
+        let digest = entry.compute_hash();
+        entry.hash = Some(hex::encode(digest));
+        if let Some(keys) = &self.signing_keys {
+            let sig = keys.sign_schnorr(&Message::from_digest(digest));
+            entry.sig = Some(sig.to_string());
+        }
 
         // Best-effort append — don't fail the operation if audit logging fails
         if let Ok(json) = serde_json::to_string(&entry) {
@@ -60,11 +281,67 @@ impl AuditLog {
                 .append(true)
                 .open(&self.path)
             {
-                let _ = writeln!(file, "{}", json);
+                if writeln!(file, "{}", json).is_ok() {
+                    let mut head = ChainHead::new(entry.seq.unwrap_or(0), entry.hash.unwrap_or_default(), entry.signer);
+                    if let Some(keys) = &self.signing_keys {
+                        head.sig = Some(keys.sign_schnorr(&Message::from_digest(head.digest())).to_string());
+                    }
+                    if let Err(e) = crate::storage::save_json_atomic(&self.head_path(), &head, crate::storage::PRIVATE_FILE_MODE) {
+                        tracing::warn!("Failed to write audit log head: {}", e);
+                    }
+                }
             }
         }
     }
 
+    /// Whether the live log has outgrown the rotation policy
+    fn rotation_due(&self) -> bool {
+        let Ok(metadata) = std::fs::metadata(&self.path) else {
+            return false;
+        };
+        if self.rotation.max_bytes > 0 && metadata.len() >= self.rotation.max_bytes {
+            return true;
+        }
+        let Some(max_age) = self.rotation.max_age else {
+            return false;
+        };
+        let oldest = std::fs::File::open(&self.path)
+            .ok()
+            .and_then(|f| std::io::BufReader::new(f).lines().map_while(|l| l.ok()).find(|l| !l.trim().is_empty()))
+            .and_then(|line| serde_json::from_str::<AuditEntry>(&line).ok())
+            .and_then(|entry| DateTime::parse_from_rfc3339(&entry.timestamp).ok());
+        oldest.is_some_and(|t| Utc::now() - t.with_timezone(&Utc) >= max_age)
+    }
+
+    /// Gzip the live log into an archive next to it and start a new one
+    ///
+    /// The head file is kept, so the next entry continues the chain.
+    pub fn rotate(&mut self) -> Result<PathBuf> {
+        let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let mut archive = archive_path(&self.path, &stamp);
+        let mut n = 1;
+        while archive.exists() {
+            archive = archive_path(&self.path, &format!("{}-{}", stamp, n));
+            n += 1;
+        }
+
+        let mut input = std::fs::File::open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        let output = std::fs::File::create(&archive)
+            .with_context(|| format!("Failed to create {}", archive.display()))?;
+        let mut encoder = GzEncoder::new(output, flate2::Compression::default());
+        std::io::copy(&mut input, &mut encoder)?;
+        encoder.finish()?.sync_all()?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&archive, std::fs::Permissions::from_mode(0o600))?;
+        }
+        std::fs::remove_file(&self.path)?;
+        Ok(archive)
+    }
+
     /// Get the audit log file path
     #[allow(dead_code)]
     pub fn path(&self) -> &Path {
@@ -72,6 +349,315 @@ impl AuditLog {
     }
 }
 
+fn head_path(log_path: &Path) -> PathBuf {
+    log_path.with_extension("head")
+}
+
+/// `marmot.audit.jsonl` -> `marmot.audit.<stamp>.jsonl.gz`
+fn archive_path(log_path: &Path, stamp: &str) -> PathBuf {
+    log_path.with_extension(format!("{}.jsonl.gz", stamp))
+}
+
+/// Hex SHA-256 of a file's bytes
+fn file_sha256(path: &Path) -> Result<String> {
+    let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(hex::encode(sha256::Hash::hash(&data).to_byte_array()))
+}
+
+/// Check the archive a `log_rotated` entry points at
+///
+/// `Ok(false)` when it no longer exists; an error when it was replaced.
+fn check_rotated_archive(log_path: &Path, entry: &AuditEntry) -> std::result::Result<bool, String> {
+    let Some(name) = entry.context.get("archive").and_then(|v| v.as_str()) else {
+        return Err("rotation marker does not name its archive".to_string());
+    };
+    let dir = log_path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let archive = dir.join(name);
+    if !archive.exists() {
+        return Ok(false);
+    }
+    // Markers written before archive hashes were recorded only name the file
+    if let Some(expected) = entry.context.get("sha256").and_then(|v| v.as_str()) {
+        let actual = file_sha256(&archive).map_err(|e| e.to_string())?;
+        if actual != expected {
+            return Err(format!("archive {} does not match the hash in its rotation marker (replaced)", name));
+        }
+    }
+    Ok(true)
+}
+
+/// Archives of `log_path`, oldest first
+pub fn archives(log_path: &Path) -> Vec<PathBuf> {
+    let Some(name) = log_path.file_name().map(|n| n.to_string_lossy().into_owned()) else {
+        return Vec::new();
+    };
+    let prefix = format!("{}.", name.trim_end_matches(".jsonl"));
+    let dir = log_path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut found: Vec<PathBuf> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .map(|n| n.to_string_lossy())
+                .is_some_and(|n| n.starts_with(&prefix) && n.ends_with(".jsonl.gz"))
+        })
+        .collect();
+    // Timestamps sort lexicographically
+    found.sort();
+    found
+}
+
+/// Archives followed by the live log (if present), each with its lines
+fn read_sources(log_path: &Path, include_archives: bool) -> Result<Vec<(PathBuf, Vec<String>)>> {
+    let mut sources = Vec::new();
+    if include_archives {
+        for archive in archives(log_path) {
+            let file = std::fs::File::open(&archive)
+                .with_context(|| format!("Failed to open {}", archive.display()))?;
+            let mut text = String::new();
+            GzDecoder::new(file)
+                .read_to_string(&mut text)
+                .with_context(|| format!("Failed to decompress {}", archive.display()))?;
+            sources.push((archive, text.lines().map(str::to_string).collect()));
+        }
+    }
+    if log_path.exists() || sources.is_empty() {
+        let file = std::fs::File::open(log_path)
+            .with_context(|| format!("Failed to open {}", log_path.display()))?;
+        let lines = std::io::BufReader::new(file).lines().collect::<std::io::Result<Vec<_>>>()?;
+        sources.push((log_path.to_path_buf(), lines));
+    }
+    Ok(sources)
+}
+
+/// Parsed entries of the live log (and, optionally, its archives), oldest first
+///
+/// Lines that don't parse are skipped; `audit verify` reports them.
+pub fn read_entries(log_path: &Path, include_archives: bool) -> Result<Vec<AuditEntry>> {
+    Ok(read_sources(log_path, include_archives)?
+        .into_iter()
+        .flat_map(|(_, lines)| lines)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect())
+}
+
+/// Selection for `audit show`
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub since: Option<DateTime<Utc>>,
+    /// Exact operation, or a prefix ending in `*` (`sign_*`)
+    pub operation: Option<String>,
+    pub kind: Option<u16>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        if let Some(since) = self.since {
+            let at = DateTime::parse_from_rfc3339(&entry.timestamp).map(|t| t.with_timezone(&Utc));
+            if !at.is_ok_and(|t| t >= since) {
+                return false;
+            }
+        }
+        if let Some(operation) = &self.operation {
+            let matched = match operation.strip_suffix('*') {
+                Some(prefix) => entry.operation.starts_with(prefix),
+                None => entry.operation == *operation,
+            };
+            if !matched {
+                return false;
+            }
+        }
+        self.kind.is_none() || entry.kind() == self.kind
+    }
+}
+
+/// Parse `--since`: a relative age (`90m`, `24h`, `7d`, `2w`), a date or an RFC 3339 time
+pub fn parse_since(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(t) = DateTime::parse_from_rfc3339(value) {
+        return Ok(t.with_timezone(&Utc));
+    }
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc());
+    }
+    let age = crate::config::parse_duration(value)
+        .with_context(|| format!("Invalid --since '{}': use 24h, 7d, YYYY-MM-DD or an RFC 3339 time", value))?;
+    Ok(now - age)
+}
+
+/// Where `audit verify` found a problem
+#[derive(Debug, Clone)]
+pub struct VerifyProblem {
+    /// Log or archive containing the bad entry
+    pub file: PathBuf,
+    /// 1-based line number (0 = the log as a whole, e.g. truncation)
+    pub line: usize,
+    pub reason: String,
+}
+
+/// Result of checking an audit log's hash chain
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Chained entries checked
+    pub entries: usize,
+    /// Entries written before chaining was introduced (not verifiable)
+    pub legacy: usize,
+    /// Entries whose signature verified
+    pub signed: usize,
+    /// Distinct keys that signed entries (hex)
+    pub signers: Vec<String>,
+    /// Archives checked before the live log
+    pub archives: usize,
+    /// Sequence number the chain was picked up at when older archives were deleted
+    pub anchored_at: Option<u64>,
+    /// Archives named by rotation markers that no longer exist (only with `allow_missing_archives`)
+    pub missing_archives: Vec<String>,
+    /// First problem found
+    pub problem: Option<VerifyProblem>,
+}
+
+impl VerifyReport {
+    pub fn is_intact(&self) -> bool {
+        self.problem.is_none()
+    }
+}
+
+/// Verify the hash chain, signatures and head of an audit log and its archives
+///
+/// A deleted archive is a failure unless `allow_missing_archives` is set, in
+/// which case it is listed in the report and the chain is picked up at the
+/// rotation marker that follows it.
+pub fn verify(log_path: &Path, allow_missing_archives: bool) -> Result<VerifyReport> {
+    let sources = read_sources(log_path, true)?;
+    let secp = Secp256k1::verification_only();
+    let mut report = VerifyReport {
+        archives: sources.iter().filter(|(path, _)| path != log_path).count(),
+        ..Default::default()
+    };
+    let mut expected: Option<ChainHead> = None;
+
+    for (file, lines) in &sources {
+        for (index, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fail = |report: &mut VerifyReport, reason: String| {
+                report.problem = Some(VerifyProblem { file: file.clone(), line: index + 1, reason })
+            };
+
+            let entry: AuditEntry = match serde_json::from_str(line) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    fail(&mut report, format!("not a valid entry ({})", e));
+                    return Ok(report);
+                }
+            };
+
+            let (Some(seq), Some(prev_hash), Some(hash)) = (entry.seq, &entry.prev_hash, &entry.hash) else {
+                if expected.is_some() {
+                    fail(&mut report, "unchained entry after the chain started".to_string());
+                    return Ok(report);
+                }
+                report.legacy += 1;
+                continue;
+            };
+
+            if entry.operation == ROTATED_OPERATION {
+                match check_rotated_archive(log_path, &entry) {
+                    Ok(true) => {}
+                    Ok(false) if allow_missing_archives => {
+                        let name = entry.context.get("archive").and_then(|v| v.as_str()).unwrap_or_default();
+                        report.missing_archives.push(name.to_string());
+                    }
+                    Ok(false) => {
+                        fail(&mut report, "the archive named by this rotation marker is missing (deleted)".to_string());
+                        return Ok(report);
+                    }
+                    Err(reason) => {
+                        fail(&mut report, reason);
+                        return Ok(report);
+                    }
+                }
+            }
+
+            let (want_seq, want_prev) = match &expected {
+                Some(head) => (head.seq + 1, head.hash.as_str()),
+                // Older archives were deleted (and allowed to be, checked above):
+                // pick the chain up at the first rotation marker
+                None if entry.operation == ROTATED_OPERATION && seq > 0 => {
+                    report.anchored_at = Some(seq);
+                    (seq, prev_hash.as_str())
+                }
+                None => (0, GENESIS_HASH),
+            };
+            if seq != want_seq {
+                fail(&mut report, format!("sequence {} where {} was expected (entries removed or reordered)", seq, want_seq));
+                return Ok(report);
+            }
+            if prev_hash != want_prev {
+                fail(&mut report, "previous-hash link broken (an earlier entry was modified)".to_string());
+                return Ok(report);
+            }
+            let digest = entry.compute_hash();
+            if hex::encode(digest) != *hash {
+                fail(&mut report, "hash mismatch (entry was modified)".to_string());
+                return Ok(report);
+            }
+
+            match (&entry.signer, &entry.sig) {
+                (Some(signer), Some(sig)) => {
+                    if !signature_valid(&secp, signer, sig, digest) {
+                        fail(&mut report, format!("invalid signature by {}", signer));
+                        return Ok(report);
+                    }
+                    report.signed += 1;
+                    if !report.signers.contains(signer) {
+                        report.signers.push(signer.clone());
+                    }
+                }
+                (None, None) => {}
+                _ => {
+                    fail(&mut report, "signer and signature must both be present".to_string());
+                    return Ok(report);
+                }
+            }
+
+            report.entries += 1;
+            expected = Some(ChainHead::new(seq, hash.clone(), entry.signer.clone()));
+        }
+    }
+
+    // The head file records how far the chain went; a shorter log was truncated
+    let head = std::fs::read_to_string(head_path(log_path))
+        .ok()
+        .and_then(|s| serde_json::from_str::<ChainHead>(&s).ok());
+    let head_problem = match (head, &expected) {
+        (None, None) => None,
+        (None, Some(_)) => Some("the head file is missing or unreadable (the log may have been truncated)".to_string()),
+        (Some(head), last) if !last.as_ref().is_some_and(|last| last.same_entry(&head)) => {
+            let have = last.as_ref().map(|h| h.seq.to_string()).unwrap_or_else(|| "none".to_string());
+            Some(format!("log ends at entry {} but the head records entry {} (truncated)", have, head.seq))
+        }
+        (Some(head), Some(last)) if head.signer != last.signer => {
+            Some("the head is not signed by the key that signed the last entry (head rewritten)".to_string())
+        }
+        (Some(head), _) => match (&head.signer, &head.sig) {
+            (Some(signer), Some(sig)) if !signature_valid(&secp, signer, sig, head.digest()) => {
+                Some(format!("invalid head signature by {}", signer))
+            }
+            (Some(_), None) => Some("the head is missing its signature (head rewritten)".to_string()),
+            _ => None,
+        },
+    };
+    if let Some(reason) = head_problem {
+        report.problem = Some(VerifyProblem { file: log_path.to_path_buf(), line: 0, reason });
+    }
+
+    Ok(report)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -100,4 +686,208 @@ mod tests {
         // Should not panic
         log.record("test", "test");
     }
+
+    fn signed_log(dir: &Path) -> AuditLog {
+        let mut log = AuditLog::new(&dir.join("marmot.db"));
+        log.set_signing_keys(Keys::generate());
+        for i in 0..4 {
+            log.record("sign_event_success", &format!("event {}", i));
+        }
+        log
+    }
+
+    #[test]
+    fn test_chain_verifies_and_continues_across_instances() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = signed_log(dir.path());
+
+        // A new process picks up where the chain left off
+        let mut reopened = AuditLog::new(&dir.path().join("marmot.db"));
+        reopened.record("bunker_connect", "unsigned entry");
+
+        let report = verify(log.path(), false).unwrap();
+        assert!(report.is_intact(), "{:?}", report.problem);
+        assert_eq!(report.entries, 5);
+        assert_eq!(report.signed, 4);
+        assert_eq!(report.signers.len(), 1);
+    }
+
+    #[test]
+    fn test_verify_detects_modification() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = signed_log(dir.path());
+        let content = std::fs::read_to_string(log.path()).unwrap();
+        std::fs::write(log.path(), content.replace("event 2", "event X")).unwrap();
+
+        let report = verify(log.path(), false).unwrap();
+        assert_eq!(report.problem.unwrap().line, 3);
+    }
+
+    #[test]
+    fn test_verify_detects_removal_and_truncation() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = signed_log(dir.path());
+        let content = std::fs::read_to_string(log.path()).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+
+        // Entry removed from the middle
+        std::fs::write(log.path(), format!("{}\n{}\n{}\n", lines[0], lines[2], lines[3])).unwrap();
+        assert!(verify(log.path(), false).unwrap().problem.unwrap().reason.contains("sequence"));
+
+        // Tail removed
+        std::fs::write(log.path(), format!("{}\n{}\n", lines[0], lines[1])).unwrap();
+        assert!(verify(log.path(), false).unwrap().problem.unwrap().reason.contains("truncated"));
+    }
+
+    #[test]
+    fn test_verify_rejects_missing_or_rewritten_head() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = signed_log(dir.path());
+        let head = head_path(log.path());
+        assert!(verify(log.path(), false).unwrap().is_intact());
+
+        // Truncate the log and rewrite the head to match, without the signing key
+        let content = std::fs::read_to_string(log.path()).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        std::fs::write(log.path(), format!("{}\n{}\n", lines[0], lines[1])).unwrap();
+        let second: AuditEntry = serde_json::from_str(lines[1]).unwrap();
+        let forged = ChainHead::new(1, second.hash.clone().unwrap(), None);
+        std::fs::write(&head, serde_json::to_string(&forged).unwrap()).unwrap();
+        assert!(verify(log.path(), false).unwrap().problem.unwrap().reason.contains("head"));
+
+        let forged = ChainHead { sig: Some("00".repeat(64)), ..ChainHead::new(1, second.hash.unwrap(), second.signer) };
+        std::fs::write(&head, serde_json::to_string(&forged).unwrap()).unwrap();
+        assert!(verify(log.path(), false).unwrap().problem.unwrap().reason.contains("invalid head signature"));
+
+        // Deleting the head doesn't help either
+        std::fs::remove_file(&head).unwrap();
+        assert!(verify(log.path(), false).unwrap().problem.unwrap().reason.contains("head file is missing"));
+    }
+
+    #[test]
+    fn test_legacy_entries_before_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("marmot.db");
+        std::fs::write(
+            db_path.with_extension("audit.jsonl"),
+            "{\"timestamp\":\"t\",\"operation\":\"old\",\"details\":\"\"}\n",
+        )
+        .unwrap();
+        let mut log = AuditLog::new(&db_path);
+        log.record("new", "chained");
+
+        let report = verify(log.path(), false).unwrap();
+        assert!(report.is_intact());
+        assert_eq!((report.legacy, report.entries), (1, 1));
+    }
+
+    #[test]
+    fn test_context_is_chained_and_filterable() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = AuditLog::new(&dir.path().join("marmot.db"));
+        log.record_with("sign_event_success", "", serde_json::json!({"event_id": "ab", "kind": 445}));
+        log.record_with("gift_wrap_success", "", serde_json::json!({"kind": 1059}));
+        log.record("bunker_connect", "no context");
+
+        let content = std::fs::read_to_string(log.path()).unwrap();
+        assert!(!content.lines().nth(2).unwrap().contains("context"));
+        std::fs::write(log.path(), content.replace("\"kind\":445", "\"kind\":444")).unwrap();
+        assert!(verify(log.path(), false).unwrap().problem.unwrap().reason.contains("hash mismatch"));
+        std::fs::write(log.path(), content).unwrap();
+
+        let entries = read_entries(log.path(), false).unwrap();
+        let by_kind = AuditFilter { kind: Some(445), ..Default::default() };
+        assert_eq!(entries.iter().filter(|e| by_kind.matches(e)).count(), 1);
+        let by_prefix = AuditFilter { operation: Some("g*".to_string()), ..Default::default() };
+        assert_eq!(entries.iter().filter(|e| by_prefix.matches(e)).count(), 1);
+        let future = AuditFilter { since: Some(Utc::now() + chrono::Duration::hours(1)), ..Default::default() };
+        assert!(!entries.iter().any(|e| future.matches(e)));
+    }
+
+    #[test]
+    fn test_concurrent_writers_keep_the_chain_intact() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("marmot.db");
+        let keys = Keys::generate();
+        let writers: Vec<_> = (0..4)
+            .map(|w| {
+                let (db_path, keys) = (db_path.clone(), keys.clone());
+                std::thread::spawn(move || {
+                    // Each writer has its own log handle, like a separate process
+                    let mut log = AuditLog::new(&db_path);
+                    log.set_signing_keys(keys);
+                    for i in 0..25 {
+                        log.record("sign_event_success", &format!("writer {} event {}", w, i));
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let report = verify(&db_path.with_extension("audit.jsonl"), false).unwrap();
+        assert!(report.is_intact(), "{:?}", report.problem);
+        assert_eq!(report.entries, 100);
+    }
+
+    #[test]
+    fn test_rotation_archives_and_chain_spans_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = signed_log(dir.path());
+        log.set_rotation(RotationPolicy { max_bytes: 1, max_age: None });
+        log.record("sign_event_success", "after rotation");
+
+        let found = archives(log.path());
+        assert_eq!(found.len(), 1);
+        let entries = read_entries(log.path(), false).unwrap();
+        assert_eq!(entries[0].operation, ROTATED_OPERATION);
+        assert_eq!(entries[0].seq, Some(4));
+        assert_eq!(read_entries(log.path(), true).unwrap().len(), 6);
+
+        let report = verify(log.path(), false).unwrap();
+        assert!(report.is_intact(), "{:?}", report.problem);
+        assert_eq!((report.archives, report.entries), (1, 6));
+
+        // A replaced archive fails the hash recorded in the rotation marker
+        let original = std::fs::read(&found[0]).unwrap();
+        std::fs::write(&found[0], b"not the archive").unwrap();
+        assert!(verify(log.path(), false).unwrap().problem.unwrap().reason.contains("replaced"));
+        std::fs::write(&found[0], original).unwrap();
+
+        // A deleted archive fails, unless explicitly allowed
+        std::fs::remove_file(&found[0]).unwrap();
+        let report = verify(log.path(), false).unwrap();
+        assert!(report.problem.unwrap().reason.contains("missing"));
+        let report = verify(log.path(), true).unwrap();
+        assert!(report.is_intact(), "{:?}", report.problem);
+        assert_eq!(report.anchored_at, Some(4));
+        assert_eq!(report.missing_archives.len(), 1);
+    }
+
+    #[test]
+    fn test_rotation_by_age() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("marmot.db");
+        std::fs::write(
+            db_path.with_extension("audit.jsonl"),
+            "{\"timestamp\":\"2020-01-01T00:00:00+00:00\",\"operation\":\"old\",\"details\":\"\"}\n",
+        )
+        .unwrap();
+        let mut log = AuditLog::new(&db_path);
+        log.set_rotation(RotationPolicy::from_profile(None, Some(30)));
+        log.record("new", "");
+        assert_eq!(archives(log.path()).len(), 1);
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let now = Utc::now();
+        assert_eq!(parse_since("24h", now).unwrap(), now - chrono::Duration::hours(24));
+        assert_eq!(parse_since("2w", now).unwrap(), now - chrono::Duration::days(14));
+        assert_eq!(parse_since("2026-01-02", now).unwrap().to_rfc3339(), "2026-01-02T00:00:00+00:00");
+        assert!(parse_since("2026-01-02T03:04:05Z", now).is_ok());
+        assert!(parse_since("soon", now).is_err());
+        assert!(parse_since("5y", now).is_err());
+    }
 }
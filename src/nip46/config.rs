@@ -10,6 +10,37 @@ use anyhow::{Context, Result};
 use nostr::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use crate::relays::AuthPolicy;
+
+/// NIP-46 permissions marmot-cli needs from a signer
+///
+/// Key packages (443), group messages (445), gift wraps and seals for
+/// welcomes (1059, 13), deletions (5), the key package relay list (10051),
+/// the mute list (10000, `block --publish`) and NIP-44 for gift wrapping.
+pub const MARMOT_PERMISSIONS: &[&str] = &[
+    "sign_event:443",
+    "sign_event:445",
+    "sign_event:1059",
+    "sign_event:13",
+    "sign_event:5",
+    "sign_event:10051",
+    "sign_event:10000",
+    "nip44_encrypt",
+    "nip44_decrypt",
+];
+
+/// NIP-42 AUTH events, signed by the relay pool for `--auth-relays`
+pub const AUTH_PERMISSION: &str = "sign_event:22242";
+
+/// Permissions to hold for this run: Marmot's own, plus AUTH when any relay is opted in
+pub fn required_permissions(auth: &AuthPolicy) -> Vec<String> {
+    let mut permissions: Vec<String> = MARMOT_PERMISSIONS.iter().map(|p| p.to_string()).collect();
+    if auth.is_enabled() {
+        permissions.push(AUTH_PERMISSION.to_string());
+    }
+    permissions
+}
+
 /// Persistent bunker connection configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BunkerConfig {
@@ -29,6 +60,9 @@ pub struct BunkerConfig {
     pub created_at: String,
     /// When last successfully connected to bunker
     pub last_connected: Option<String>,
+    /// NIP-46 permissions requested from the bunker on first connect
+    #[serde(default)]
+    pub requested_permissions: Vec<String>,
 }
 
 impl BunkerConfig {
@@ -54,7 +88,8 @@ impl BunkerConfig {
             NostrConnectURI::Client { .. } => {
                 anyhow::bail!(
                     "Expected bunker:// URI, got nostrconnect:// URI.\n\
-                     Use format: bunker://<pubkey>?relay=wss://...&secret=TOKEN"
+                     Use format: bunker://<pubkey>?relay=wss://...&secret=TOKEN\n\
+                     To pair by scanning a URI from your signer app, use: marmot-cli init --nostrconnect"
                 );
             }
         };
@@ -77,9 +112,27 @@ impl BunkerConfig {
             user_pubkey: None,
             created_at: chrono::Utc::now().to_rfc3339(),
             last_connected: None,
+            requested_permissions: Vec::new(),
         })
     }
 
+    /// Config for a signer paired via a client-initiated nostrconnect:// URI
+    ///
+    /// The pairing secret has already been consumed, so none is stored.
+    pub fn from_pairing(remote_signer: PublicKey, relays: Vec<String>, client_keys: &Keys) -> Self {
+        BunkerConfig {
+            remote_signer_pubkey: remote_signer.to_hex(),
+            relays,
+            secret: None,
+            client_secret_key: client_keys.secret_key().to_secret_hex(),
+            user_pubkey: None,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            last_connected: None,
+            // The nostrconnect:// URI already carried our permissions
+            requested_permissions: MARMOT_PERMISSIONS.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
     /// Reconstruct the NostrConnectURI from stored config
     pub fn to_nostr_connect_uri(&self) -> Result<NostrConnectURI> {
         let pubkey = PublicKey::from_hex(&self.remote_signer_pubkey)
@@ -131,29 +184,8 @@ impl BunkerConfig {
 
     /// Save bunker config to disk atomically
     pub fn save(&self, db_path: &Path) -> Result<()> {
-        let path = Self::config_path(db_path);
-        let tmp_path = path.with_extension("json.tmp");
-
-        let content = serde_json::to_string_pretty(self)
-            .context("Failed to serialize bunker config")?;
-
-        // Write to temp file first
-        std::fs::write(&tmp_path, &content)
-            .context("Failed to write bunker config temp file")?;
-
-        // Atomic rename
-        std::fs::rename(&tmp_path, &path)
-            .context("Failed to atomically save bunker config")?;
-
-        // Set restrictive permissions (config contains client secret key)
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let perms = std::fs::Permissions::from_mode(0o600);
-            std::fs::set_permissions(&path, perms)?;
-        }
-
-        Ok(())
+        // The config contains the client secret key
+        crate::storage::save_json_atomic(&Self::config_path(db_path), self, crate::storage::PRIVATE_FILE_MODE)
     }
 
     /// Delete bunker config from disk
@@ -176,21 +208,149 @@ impl BunkerConfig {
     }
 }
 
+/// NIP-49 encrypted secret key stored alongside the marmot.db
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NcryptsecConfig {
+    /// The `ncryptsec1...` string
+    pub ncryptsec: String,
+    /// Public key (hex), so identity can be shown without the passphrase
+    pub pubkey: String,
+    /// When this key was stored
+    pub created_at: String,
+}
+
+impl NcryptsecConfig {
+    /// Encrypt a secret key with a passphrase (scrypt log_n = 16)
+    pub fn encrypt(keys: &Keys, passphrase: &str) -> Result<Self> {
+        let encrypted = EncryptedSecretKey::new(keys.secret_key(), passphrase, 16, KeySecurity::Medium)
+            .context("Failed to encrypt secret key")?;
+        Ok(Self {
+            ncryptsec: encrypted.to_bech32()?,
+            pubkey: keys.public_key().to_hex(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+        })
+    }
+
+    /// Import an existing `ncryptsec1...` (the passphrase is needed once to learn the pubkey)
+    pub fn import(ncryptsec: &str, passphrase: &str) -> Result<Self> {
+        let keys = Self::decrypt_str(ncryptsec, passphrase)?;
+        Ok(Self {
+            ncryptsec: ncryptsec.trim().to_string(),
+            pubkey: keys.public_key().to_hex(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+        })
+    }
+
+    /// Decrypt with the passphrase, checking it yields the recorded pubkey
+    pub fn decrypt(&self, passphrase: &str) -> Result<Keys> {
+        let keys = Self::decrypt_str(&self.ncryptsec, passphrase)?;
+        if keys.public_key().to_hex() != self.pubkey {
+            anyhow::bail!("Stored ncryptsec does not match its recorded public key");
+        }
+        Ok(keys)
+    }
+
+    fn decrypt_str(ncryptsec: &str, passphrase: &str) -> Result<Keys> {
+        let encrypted = EncryptedSecretKey::from_bech32(ncryptsec.trim())
+            .context("Invalid ncryptsec")?;
+        let secret_key = encrypted
+            .decrypt(passphrase)
+            .map_err(|_| anyhow::anyhow!("Wrong passphrase for ncryptsec"))?;
+        Ok(Keys::new(secret_key))
+    }
+
+    /// Public key without decrypting
+    pub fn public_key(&self) -> Option<PublicKey> {
+        PublicKey::from_hex(&self.pubkey).ok()
+    }
+
+    /// Config file path derived from the database path
+    pub fn config_path(db_path: &Path) -> PathBuf {
+        db_path.with_extension("ncryptsec.json")
+    }
+
+    /// Load stored ncryptsec from disk
+    pub fn load(db_path: &Path) -> Result<Option<Self>> {
+        let path = Self::config_path(db_path);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path)
+            .context("Failed to read ncryptsec config")?;
+        let config = serde_json::from_str(&content)
+            .context("Failed to parse ncryptsec config")?;
+        Ok(Some(config))
+    }
+
+    /// Save to disk atomically with owner-only permissions
+    pub fn save(&self, db_path: &Path) -> Result<()> {
+        crate::storage::save_json_atomic(&Self::config_path(db_path), self, crate::storage::PRIVATE_FILE_MODE)
+    }
+}
+
+/// Where the ncryptsec passphrase comes from
+#[derive(Debug, Clone)]
+pub enum PassphraseSource {
+    /// Ask on the terminal
+    Prompt,
+    /// Read one line from an inherited file descriptor
+    Fd(i32),
+    /// Given directly (e.g. from an environment variable)
+    Value(String),
+}
+
+impl PassphraseSource {
+    /// Obtain the passphrase
+    pub fn read(&self, prompt: &str) -> Result<String> {
+        match self {
+            PassphraseSource::Prompt => {
+                rpassword::prompt_password(prompt).context("Failed to read passphrase")
+            }
+            PassphraseSource::Fd(fd) => read_passphrase_fd(*fd),
+            PassphraseSource::Value(value) => Ok(value.clone()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn read_passphrase_fd(fd: i32) -> Result<String> {
+    use std::io::BufRead;
+    use std::os::unix::io::FromRawFd;
+    // SAFETY: the fd was handed to us explicitly via --passphrase-fd and is
+    // owned (and closed) by this File from here on
+    let file = unsafe { std::fs::File::from_raw_fd(fd) };
+    let mut line = String::new();
+    std::io::BufReader::new(file)
+        .read_line(&mut line)
+        .with_context(|| format!("Failed to read passphrase from fd {}", fd))?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+#[cfg(not(unix))]
+fn read_passphrase_fd(_fd: i32) -> Result<String> {
+    anyhow::bail!("--passphrase-fd is only supported on Unix")
+}
+
 /// Signing mode for the CLI
 #[derive(Debug, Clone)]
 pub enum SigningMode {
     /// Direct nsec — keys available locally
     DirectKey(Keys),
+    /// NIP-49 ncryptsec decrypted at startup — keys available locally, encrypted at rest
+    EncryptedKey(Keys),
     /// NIP-46 remote signing via bunker
     Bunker(BunkerConfig),
 }
 
 impl SigningMode {
     /// Determine signing mode from CLI args and stored config
+    ///
+    /// The passphrase source is only consulted when a stored ncryptsec is used.
     pub fn resolve(
         nsec: Option<&str>,
         bunker_uri: Option<&str>,
         db_path: &Path,
+        passphrase: &PassphraseSource,
     ) -> Result<Self> {
         // Explicit bunker URI takes highest priority
         if let Some(uri) = bunker_uri {
@@ -214,6 +374,12 @@ impl SigningMode {
             return Ok(SigningMode::Bunker(config));
         }
 
+        // Check for stored NIP-49 encrypted key
+        if let Some(config) = NcryptsecConfig::load(db_path)? {
+            let passphrase = passphrase.read("ncryptsec passphrase: ")?;
+            return Ok(SigningMode::EncryptedKey(config.decrypt(&passphrase)?));
+        }
+
         // No credentials at all
         anyhow::bail!(
             "No credentials provided. Use one of:\n\
@@ -222,6 +388,10 @@ impl SigningMode {
              - marmot-cli --bunker \"bunker://<pubkey>?relay=wss://...&secret=TOKEN\" <command>\n\
              - Or run: marmot-cli init --bunker \"bunker://...\"\n\
              \n\
+             🔐 Encrypted key mode (NIP-49, no bunker needed):\n\
+             - marmot-cli init --nsec \"nsec1...\" --encrypt-key\n\
+             - Or: marmot-cli init --ncryptsec \"ncryptsec1...\"\n\
+             \n\
              🔑 Direct key mode:\n\
              - Set NOSTR_NSEC environment variable\n\
              - Or: marmot-cli --nsec \"nsec1...\" <command>\n\
@@ -236,6 +406,15 @@ impl SigningMode {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_required_permissions_include_auth_only_when_enabled() {
+        assert!(!required_permissions(&AuthPolicy::default()).iter().any(|p| p == AUTH_PERMISSION));
+        let auth = AuthPolicy::parse("wss://relay.example.com");
+        let permissions = required_permissions(&auth);
+        assert!(permissions.iter().any(|p| p == AUTH_PERMISSION));
+        assert!(MARMOT_PERMISSIONS.iter().all(|p| permissions.iter().any(|q| q == p)));
+    }
+
     #[test]
     fn test_parse_valid_bunker_uri() {
         let uri = "bunker://79dff8f82963424e0bb02708a22e44b4980893e3a4be0fa3cb60a43b946764e3?relay=wss://relay.nsec.app&secret=test123";
@@ -317,7 +496,7 @@ mod tests {
 
         let keys = Keys::generate();
         let nsec = keys.secret_key().to_bech32().unwrap();
-        let mode = SigningMode::resolve(Some(&nsec), None, &db_path).unwrap();
+        let mode = SigningMode::resolve(Some(&nsec), None, &db_path, &PassphraseSource::Prompt).unwrap();
         assert!(matches!(mode, SigningMode::DirectKey(_)));
     }
 
@@ -327,7 +506,7 @@ mod tests {
         let db_path = dir.path().join("marmot.db");
 
         let uri = "bunker://79dff8f82963424e0bb02708a22e44b4980893e3a4be0fa3cb60a43b946764e3?relay=wss://relay.nsec.app&secret=test";
-        let mode = SigningMode::resolve(None, Some(uri), &db_path).unwrap();
+        let mode = SigningMode::resolve(None, Some(uri), &db_path, &PassphraseSource::Prompt).unwrap();
         assert!(matches!(mode, SigningMode::Bunker(_)));
     }
 
@@ -342,7 +521,7 @@ mod tests {
         config.save(&db_path).unwrap();
 
         // Should auto-detect stored bunker config
-        let mode = SigningMode::resolve(None, None, &db_path).unwrap();
+        let mode = SigningMode::resolve(None, None, &db_path, &PassphraseSource::Prompt).unwrap();
         assert!(matches!(mode, SigningMode::Bunker(_)));
     }
 
@@ -351,7 +530,42 @@ mod tests {
         let dir = tempfile::tempdir().unwrap();
         let db_path = dir.path().join("marmot.db");
 
-        let result = SigningMode::resolve(None, None, &db_path);
+        let result = SigningMode::resolve(None, None, &db_path, &PassphraseSource::Prompt);
         assert!(result.is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_ncryptsec_save_load_decrypt() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("marmot.db");
+
+        let keys = Keys::generate();
+        let config = NcryptsecConfig::encrypt(&keys, "correct horse").unwrap();
+        assert!(config.ncryptsec.starts_with("ncryptsec1"));
+        config.save(&db_path).unwrap();
+
+        let loaded = NcryptsecConfig::load(&db_path).unwrap().unwrap();
+        assert_eq!(loaded.public_key(), Some(keys.public_key()));
+        assert_eq!(loaded.decrypt("correct horse").unwrap().public_key(), keys.public_key());
+        assert!(loaded.decrypt("wrong").is_err());
+
+        let imported = NcryptsecConfig::import(&config.ncryptsec, "correct horse").unwrap();
+        assert_eq!(imported.pubkey, config.pubkey);
+    }
+
+    #[test]
+    fn test_signing_mode_resolve_stored_ncryptsec() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("marmot.db");
+
+        let keys = Keys::generate();
+        NcryptsecConfig::encrypt(&keys, "pw").unwrap().save(&db_path).unwrap();
+
+        let passphrase = PassphraseSource::Value("pw".to_string());
+        let mode = SigningMode::resolve(None, None, &db_path, &passphrase).unwrap();
+        assert!(matches!(mode, SigningMode::EncryptedKey(k) if k.public_key() == keys.public_key()));
+
+        let wrong = PassphraseSource::Value("nope".to_string());
+        assert!(SigningMode::resolve(None, None, &db_path, &wrong).is_err());
+    }
+}
@@ -1,13 +1,22 @@
 //! NIP-46 Remote Signing Support
 //!
-//! This module provides bunker:// URI handling, persistent storage of bunker
-//! connection parameters, and a unified signer abstraction that supports both
-//! direct nsec and NIP-46 remote signing modes.
+//! This module provides bunker:// URI handling, client-initiated
+//! nostrconnect:// pairing, persistent storage of bunker connection
+//! parameters, and a unified signer abstraction that supports both direct
+//! nsec and NIP-46 remote signing modes under a local signing policy.
 
 pub mod config;
+pub mod pairing;
+pub mod permissions;
+pub mod policy;
+pub mod queue;
+pub mod reconnect;
 pub mod signer;
 pub mod audit;
 
-pub use config::{BunkerConfig, SigningMode};
-pub use signer::MarmotSigner;
+pub use config::{BunkerConfig, NcryptsecConfig, PassphraseSource, SigningMode};
+pub use queue::OfflineQueue;
+pub use signer::{connect_bunker, open_nostr_connect, MarmotSigner, DEFAULT_BUNKER_TIMEOUT};
 pub use audit::AuditLog;
+pub use pairing::NostrConnectPairing;
+pub use policy::{PolicyViolation, SigningPolicy};
@@ -0,0 +1,202 @@
+//! Client-initiated NIP-46 pairing (`nostrconnect://`)
+//!
+//! Instead of pasting a bunker:// URI into marmot-cli, the user scans a
+//! nostrconnect:// URI with their signer app (Amber, nsec.app, ...). The URI
+//! carries our client pubkey, the relays we listen on, the permissions we
+//! want and a one-time secret. The signer answers with a `connect` response
+//! whose result must echo that secret; its event author is the remote signer
+//! pubkey we persist in a [`BunkerConfig`].
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use nostr::prelude::*;
+use nostr_sdk::prelude::*;
+
+use super::config::{BunkerConfig, MARMOT_PERMISSIONS};
+
+/// Relay used for pairing when none is given
+pub const DEFAULT_PAIRING_RELAY: &str = "wss://relay.nsec.app";
+
+/// How long to wait for the signer to answer
+pub const PAIRING_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// A pending nostrconnect:// pairing
+pub struct NostrConnectPairing {
+    client_keys: Keys,
+    relays: Vec<RelayUrl>,
+    secret: String,
+}
+
+impl NostrConnectPairing {
+    /// Start a pairing with a fresh client keypair and secret
+    pub fn new(relays: &[String]) -> Result<Self> {
+        let relays: Vec<RelayUrl> = relays
+            .iter()
+            .map(|r| RelayUrl::parse(r.trim()).with_context(|| format!("Invalid relay URL: {}", r)))
+            .collect::<Result<_>>()?;
+        if relays.is_empty() {
+            anyhow::bail!("nostrconnect pairing needs at least one relay");
+        }
+        Ok(Self {
+            client_keys: Keys::generate(),
+            relays,
+            secret: hex::encode(crate::storage::random_bytes::<16>()),
+        })
+    }
+
+    /// The nostrconnect:// URI to show the signer
+    pub fn uri(&self) -> String {
+        let mut url = url::Url::parse(&format!("nostrconnect://{}", self.client_keys.public_key().to_hex()))
+            .expect("hex pubkey is a valid host");
+        {
+            let mut query = url.query_pairs_mut();
+            for relay in &self.relays {
+                query.append_pair("relay", relay.as_str());
+            }
+            query.append_pair("secret", &self.secret);
+            query.append_pair("perms", &MARMOT_PERMISSIONS.join(","));
+            query.append_pair("name", "marmot-cli");
+        }
+        url.to_string()
+    }
+
+    /// The URI rendered as a terminal QR code
+    pub fn qr_code(&self) -> Result<String> {
+        use qrcode::render::unicode::Dense1x2;
+        let code = qrcode::QrCode::new(self.uri().as_bytes()).context("URI too long for a QR code")?;
+        Ok(code
+            .render::<Dense1x2>()
+            .dark_color(Dense1x2::Light)
+            .light_color(Dense1x2::Dark)
+            .quiet_zone(true)
+            .build())
+    }
+
+    /// Check a kind-24133 event for the signer's connect response
+    ///
+    /// Returns `None` for events that aren't the answer to this pairing
+    /// (other requests, wrong secret), `Some(Err)` if the signer refused.
+    fn remote_signer_from(&self, event: &Event) -> Option<Result<PublicKey>> {
+        let plaintext = nip44::decrypt(self.client_keys.secret_key(), &event.pubkey, &event.content).ok()?;
+        let response: serde_json::Value = serde_json::from_str(&plaintext).ok()?;
+        if let Some(error) = response.get("error").and_then(|e| e.as_str()).filter(|e| !e.is_empty()) {
+            return Some(Err(anyhow::anyhow!("Signer refused the connection: {}", error)));
+        }
+        // The secret (not a bare "ack") proves the response is for our URI
+        match response.get("result").and_then(|r| r.as_str()) {
+            Some(result) if result == self.secret => Some(Ok(event.pubkey)),
+            _ => None,
+        }
+    }
+
+    /// Wait for the signer to scan the URI and answer
+    ///
+    /// Returns a `BunkerConfig` with the remote signer pubkey and our client
+    /// key; the user pubkey is filled in by the caller after `get_public_key`.
+    pub async fn wait(&self, proxy: Option<SocketAddr>, timeout: Duration) -> Result<BunkerConfig> {
+        let remote_signer = exchange(&self.relays, &self.client_keys, proxy, timeout, None, |event| {
+            self.remote_signer_from(event)
+        })
+        .await?;
+        Ok(BunkerConfig::from_pairing(
+            remote_signer,
+            self.relays.iter().map(|r| r.to_string()).collect(),
+            &self.client_keys,
+        ))
+    }
+}
+
+/// One NIP-46 round trip over a throwaway relay client
+///
+/// Listens for kind-24133 events addressed to `client_keys`, publishes
+/// `request` (if any) once subscribed, and returns the first result that
+/// `on_event` produces.
+pub(super) async fn exchange<T>(
+    relays: &[RelayUrl],
+    client_keys: &Keys,
+    proxy: Option<SocketAddr>,
+    timeout: Duration,
+    request: Option<Event>,
+    mut on_event: impl FnMut(&Event) -> Option<Result<T>>,
+) -> Result<T> {
+    let mut opts = ClientOptions::new();
+    if let Some(addr) = proxy {
+        opts = opts.connection(Connection::new().proxy(addr));
+    }
+    let client = Client::builder().opts(opts).build();
+    for relay in relays {
+        client.add_relay(relay.as_str()).await?;
+    }
+    client.connect().await;
+
+    let filter = Filter::new()
+        .kind(Kind::NostrConnect)
+        .pubkey(client_keys.public_key())
+        .since(Timestamp::now());
+    let mut notifications = client.notifications();
+    client.subscribe(filter, None).await?;
+    if let Some(request) = request {
+        client.send_event(&request).await?;
+    }
+
+    let result = tokio::time::timeout(timeout, async {
+        while let Ok(notification) = notifications.recv().await {
+            if let RelayPoolNotification::Event { event, .. } = notification {
+                if let Some(result) = on_event(&event) {
+                    return result;
+                }
+            }
+        }
+        anyhow::bail!("Relay connection closed while waiting for the signer")
+    })
+    .await;
+    client.shutdown().await;
+
+    result.map_err(|_| anyhow::anyhow!("No response from the signer within {}s", timeout.as_secs()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(signer: &Keys, pairing: &NostrConnectPairing, body: serde_json::Value) -> Event {
+        let content = nip44::encrypt(
+            signer.secret_key(),
+            &pairing.client_keys.public_key(),
+            body.to_string(),
+            nip44::Version::default(),
+        )
+        .unwrap();
+        EventBuilder::new(Kind::NostrConnect, content)
+            .sign_with_keys(signer)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_uri_contains_pairing_parameters() {
+        let pairing = NostrConnectPairing::new(&["wss://relay.nsec.app".to_string()]).unwrap();
+        let uri = pairing.uri();
+        assert!(uri.starts_with(&format!("nostrconnect://{}?", pairing.client_keys.public_key().to_hex())));
+        assert!(uri.contains("relay=wss%3A%2F%2Frelay.nsec.app"));
+        assert!(uri.contains(&format!("secret={}", pairing.secret)));
+        assert!(uri.contains("perms=sign_event%3A443"));
+        assert!(NostrConnectPairing::new(&[]).is_err());
+    }
+
+    #[test]
+    fn test_connect_response_requires_secret() {
+        let pairing = NostrConnectPairing::new(&["wss://relay.nsec.app".to_string()]).unwrap();
+        let signer = Keys::generate();
+
+        let ack = response(&signer, &pairing, serde_json::json!({"id": "x", "result": "ack"}));
+        assert!(pairing.remote_signer_from(&ack).is_none());
+
+        let ok = response(&signer, &pairing, serde_json::json!({"id": "x", "result": pairing.secret}));
+        assert_eq!(pairing.remote_signer_from(&ok).unwrap().unwrap(), signer.public_key());
+
+        let refused = response(&signer, &pairing, serde_json::json!({"id": "x", "error": "denied"}));
+        assert!(pairing.remote_signer_from(&refused).unwrap().is_err());
+    }
+}
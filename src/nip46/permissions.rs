@@ -0,0 +1,185 @@
+//! Scoped NIP-46 permission requests
+//!
+//! On the first connection with a bunker:// URI we send our own `connect`
+//! request carrying [`MARMOT_PERMISSIONS`] as its third parameter, so the
+//! bunker can grant exactly what Marmot needs instead of prompting for (or
+//! granting) everything. The one-time secret is consumed here; the regular
+//! NostrConnect session afterwards connects as an already-known client.
+//! When a later run needs permissions that were never requested (a newer
+//! build, or `--auth-relays` enabling NIP-42 AUTH), `connect` is sent again
+//! with the combined list.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use nostr::prelude::*;
+
+use super::config::BunkerConfig;
+use super::pairing::exchange;
+
+/// How long the bunker (or its user) has to approve the permissions
+pub const PERMISSION_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// What each requested permission is used for (shown by `signer-status --permissions`)
+pub fn describe(permission: &str) -> &'static str {
+    match permission {
+        "sign_event:443" => "publish key packages",
+        "sign_event:445" => "send group messages and commits",
+        "sign_event:1059" => "gift-wrap welcomes",
+        "sign_event:13" => "seal welcomes",
+        "sign_event:5" => "delete old key packages",
+        "sign_event:10051" => "publish the key package relay list",
+        "sign_event:10000" => "publish the mute list (block --publish)",
+        "sign_event:22242" => "authenticate to --auth-relays (NIP-42)",
+        "nip44_encrypt" => "encrypt gift wraps",
+        "nip44_decrypt" => "open incoming welcomes",
+        _ => "",
+    }
+}
+
+/// Build the `connect` request event for `config`
+fn connect_request(config: &BunkerConfig, permissions: &[String], client_keys: &Keys, id: &str) -> Result<Event> {
+    let remote = PublicKey::from_hex(&config.remote_signer_pubkey)
+        .context("Invalid stored remote signer pubkey")?;
+    let request = serde_json::json!({
+        "id": id,
+        "method": "connect",
+        "params": [
+            config.remote_signer_pubkey,
+            config.secret.clone().unwrap_or_default(),
+            permissions.join(","),
+        ],
+    });
+    let content = nip44::encrypt(
+        client_keys.secret_key(),
+        &remote,
+        request.to_string(),
+        nip44::Version::default(),
+    )?;
+    Ok(EventBuilder::new(Kind::NostrConnect, content)
+        .tag(Tag::public_key(remote))
+        .sign_with_keys(client_keys)?)
+}
+
+/// Interpret a response to our connect request
+///
+/// `None` means "not ours / keep waiting" (including `auth_url` challenges,
+/// whose URL is printed for the user to open).
+fn connect_response(config: &BunkerConfig, client_keys: &Keys, id: &str, event: &Event) -> Option<Result<()>> {
+    if event.pubkey.to_hex() != config.remote_signer_pubkey {
+        return None;
+    }
+    let plaintext = nip44::decrypt(client_keys.secret_key(), &event.pubkey, &event.content).ok()?;
+    let response: serde_json::Value = serde_json::from_str(&plaintext).ok()?;
+    if response.get("id").and_then(|v| v.as_str()) != Some(id) {
+        return None;
+    }
+    let result = response.get("result").and_then(|v| v.as_str());
+    let error = response.get("error").and_then(|v| v.as_str()).filter(|e| !e.is_empty());
+    match (result, error) {
+        (Some("auth_url"), Some(url)) => {
+            eprintln!("   Approve the connection in your signer: {}", url);
+            None
+        }
+        (_, Some(error)) => Some(Err(anyhow::anyhow!("Bunker refused the permissions: {}", error))),
+        (Some(_), None) => Some(Ok(())),
+        (None, None) => None,
+    }
+}
+
+/// Permissions in `required` that `config` has never asked the bunker for
+pub fn missing_permissions(config: &BunkerConfig, required: &[String]) -> Vec<String> {
+    required.iter().filter(|p| !config.requested_permissions.contains(p)).cloned().collect()
+}
+
+/// Send `connect` with `permissions` and wait for the bunker to accept
+///
+/// On success the secret is cleared (it is single-use for most bunkers) and
+/// the requested permissions are recorded in the config.
+pub async fn request_permissions(
+    config: &mut BunkerConfig,
+    permissions: &[String],
+    proxy: Option<SocketAddr>,
+) -> Result<()> {
+    let client_keys = config.client_keys()?;
+    let relays: Vec<RelayUrl> = config.relays.iter().filter_map(|r| RelayUrl::parse(r).ok()).collect();
+    let id = hex::encode(crate::storage::random_bytes::<8>());
+    let request = connect_request(config, permissions, &client_keys, &id)?;
+
+    eprintln!("🔐 Requesting Marmot permissions from bunker ({})...", permissions.join(", "));
+    let snapshot = config.clone();
+    exchange(&relays, &client_keys, proxy, PERMISSION_TIMEOUT, Some(request), |event| {
+        connect_response(&snapshot, &client_keys, &id, event)
+    })
+    .await
+    .context("Bunker did not accept the permission request")?;
+
+    config.secret = None;
+    config.requested_permissions = permissions.to_vec();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nip46::config::{AUTH_PERMISSION, MARMOT_PERMISSIONS};
+
+    fn reply(bunker: &Keys, client: &Keys, body: serde_json::Value) -> Event {
+        let content = nip44::encrypt(bunker.secret_key(), &client.public_key(), body.to_string(), nip44::Version::default())
+            .unwrap();
+        EventBuilder::new(Kind::NostrConnect, content).sign_with_keys(bunker).unwrap()
+    }
+
+    #[test]
+    fn test_connect_request_carries_permissions() {
+        let bunker = Keys::generate();
+        let uri = format!("bunker://{}?relay=wss://relay.nsec.app&secret=tok", bunker.public_key().to_hex());
+        let config = BunkerConfig::from_bunker_uri(&uri).unwrap();
+        let client = config.client_keys().unwrap();
+
+        let permissions: Vec<String> = MARMOT_PERMISSIONS.iter().map(|p| p.to_string()).collect();
+        let event = connect_request(&config, &permissions, &client, "req1").unwrap();
+        let plaintext = nip44::decrypt(bunker.secret_key(), &client.public_key(), &event.content).unwrap();
+        let request: serde_json::Value = serde_json::from_str(&plaintext).unwrap();
+        assert_eq!(request["method"], "connect");
+        assert_eq!(request["params"][1], "tok");
+        assert_eq!(request["params"][2], MARMOT_PERMISSIONS.join(","));
+    }
+
+    #[test]
+    fn test_connect_response() {
+        let bunker = Keys::generate();
+        let uri = format!("bunker://{}?relay=wss://relay.nsec.app", bunker.public_key().to_hex());
+        let config = BunkerConfig::from_bunker_uri(&uri).unwrap();
+        let client = config.client_keys().unwrap();
+
+        let ack = reply(&bunker, &client, serde_json::json!({"id": "req1", "result": "ack"}));
+        assert!(connect_response(&config, &client, "req1", &ack).unwrap().is_ok());
+        // Other request ids and strangers are ignored
+        assert!(connect_response(&config, &client, "other", &ack).is_none());
+        let stranger = reply(&Keys::generate(), &client, serde_json::json!({"id": "req1", "result": "ack"}));
+        assert!(connect_response(&config, &client, "req1", &stranger).is_none());
+
+        let auth = reply(&bunker, &client, serde_json::json!({"id": "req1", "result": "auth_url", "error": "https://x"}));
+        assert!(connect_response(&config, &client, "req1", &auth).is_none());
+        let denied = reply(&bunker, &client, serde_json::json!({"id": "req1", "error": "no"}));
+        assert!(connect_response(&config, &client, "req1", &denied).unwrap().is_err());
+    }
+
+    #[test]
+    fn test_missing_permissions() {
+        let bunker = Keys::generate();
+        let uri = format!("bunker://{}?relay=wss://relay.nsec.app", bunker.public_key().to_hex());
+        let mut config = BunkerConfig::from_bunker_uri(&uri).unwrap();
+        config.requested_permissions = vec!["sign_event:443".to_string(), "sign_event:445".to_string()];
+
+        let required = vec!["sign_event:443".to_string(), "sign_event:10000".to_string(), AUTH_PERMISSION.to_string()];
+        assert_eq!(
+            missing_permissions(&config, &required),
+            vec!["sign_event:10000".to_string(), AUTH_PERMISSION.to_string()]
+        );
+        config.requested_permissions.extend(required.iter().cloned());
+        assert!(missing_permissions(&config, &required).is_empty());
+    }
+}
@@ -0,0 +1,263 @@
+//! Local signing policy enforced by `MarmotSigner`
+//!
+//! An agent that relays untrusted input (chat messages, web content) to
+//! marmot-cli could be talked into signing arbitrary Nostr events with our
+//! identity. `marmot.policy.toml`, next to the database, limits what
+//! `MarmotSigner` will sign or gift-wrap regardless of signing mode:
+//!
+//! ```toml
+//! allowed_kinds = [443, 445, 1059, 5, 10051]
+//! max_events_per_minute = 30
+//! forbidden_patterns = ["nsec1", "BEGIN PRIVATE KEY"]
+//! allowed_recipients = ["npub1..."]
+//! ```
+//!
+//! Without a policy file only the kinds Marmot itself signs (the same list
+//! requested from bunkers) are allowed, with no rate limit, content filter or
+//! recipient restriction.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use nostr::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::config::MARMOT_PERMISSIONS;
+
+/// Window for `max_events_per_minute`
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Kinds allowed when the policy doesn't list any: those in `MARMOT_PERMISSIONS`
+pub fn default_kinds() -> Vec<u16> {
+    MARMOT_PERMISSIONS
+        .iter()
+        .filter_map(|p| p.strip_prefix("sign_event:"))
+        .filter_map(|k| k.parse().ok())
+        .collect()
+}
+
+/// Contents of `marmot.policy.toml`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SigningPolicy {
+    /// Event kinds that may be signed (default: Marmot's own kinds)
+    pub allowed_kinds: Option<Vec<u16>>,
+    /// Signatures allowed per rolling minute, gift wraps included
+    pub max_events_per_minute: Option<u32>,
+    /// Case-insensitive substrings that must not appear in content or tags
+    #[serde(default)]
+    pub forbidden_patterns: Vec<String>,
+    /// Who may receive gift wraps (npub or hex; default: anyone)
+    pub allowed_recipients: Option<Vec<String>>,
+}
+
+impl SigningPolicy {
+    /// Path of the policy file for a database
+    pub fn config_path(db_path: &Path) -> PathBuf {
+        db_path.with_extension("policy.toml")
+    }
+
+    /// Load the policy for a database (missing file = default policy)
+    pub fn load(db_path: &Path) -> Result<Self> {
+        let path = Self::config_path(db_path);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read signing policy {}", path.display()))?;
+        let policy: Self = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse signing policy {}", path.display()))?;
+        policy.recipients()?;
+        Ok(policy)
+    }
+
+    /// Effective list of allowed kinds
+    pub fn kinds(&self) -> Vec<u16> {
+        self.allowed_kinds.clone().unwrap_or_else(default_kinds)
+    }
+
+    /// Parsed `allowed_recipients`
+    fn recipients(&self) -> Result<Option<Vec<PublicKey>>> {
+        self.allowed_recipients
+            .as_ref()
+            .map(|list| {
+                list.iter()
+                    .map(|r| PublicKey::parse(r).with_context(|| format!("Invalid allowed_recipients entry '{}'", r)))
+                    .collect()
+            })
+            .transpose()
+    }
+}
+
+/// A signing request refused by the policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyViolation(pub String);
+
+impl std::fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Signing policy denied the request: {}", self.0)
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+/// Policy plus the rate-limit state of this process
+pub struct PolicyEngine {
+    policy: SigningPolicy,
+    kinds: Vec<u16>,
+    recipients: Option<Vec<PublicKey>>,
+    patterns: Vec<String>,
+    recent: std::sync::Mutex<VecDeque<Instant>>,
+}
+
+impl PolicyEngine {
+    pub fn new(policy: SigningPolicy) -> Result<Self> {
+        Ok(Self {
+            kinds: policy.kinds(),
+            recipients: policy.recipients()?,
+            patterns: policy.forbidden_patterns.iter().map(|p| p.to_lowercase()).collect(),
+            policy,
+            recent: std::sync::Mutex::new(VecDeque::new()),
+        })
+    }
+
+    pub fn load(db_path: &Path) -> Result<Self> {
+        Self::new(SigningPolicy::load(db_path)?)
+    }
+
+    pub fn policy(&self) -> &SigningPolicy {
+        &self.policy
+    }
+
+    /// Check a batch of events; either all may be signed or none
+    pub fn check_events(&self, events: &[UnsignedEvent]) -> Result<(), PolicyViolation> {
+        for event in events {
+            self.check_kind(event.kind)?;
+            self.check_content(event)?;
+        }
+        self.take_rate(events.len())
+    }
+
+    /// Check a gift wrap of `rumor` to `receiver`
+    pub fn check_gift_wrap(&self, receiver: &PublicKey, rumor: &UnsignedEvent) -> Result<(), PolicyViolation> {
+        self.check_kind(Kind::GiftWrap)?;
+        if let Some(allowed) = &self.recipients {
+            if !allowed.contains(receiver) {
+                return Err(PolicyViolation(format!(
+                    "recipient {} is not in allowed_recipients",
+                    receiver.to_bech32().unwrap_or_else(|_| receiver.to_hex())
+                )));
+            }
+        }
+        self.check_content(rumor)?;
+        self.take_rate(1)
+    }
+
+    fn check_kind(&self, kind: Kind) -> Result<(), PolicyViolation> {
+        if self.kinds.contains(&kind.as_u16()) {
+            Ok(())
+        } else {
+            Err(PolicyViolation(format!("kind {} is not in allowed_kinds", kind.as_u16())))
+        }
+    }
+
+    fn check_content(&self, event: &UnsignedEvent) -> Result<(), PolicyViolation> {
+        if self.patterns.is_empty() {
+            return Ok(());
+        }
+        let content = event.content.to_lowercase();
+        let tags: Vec<String> = event.tags.iter().map(|t| t.as_slice().join(" ").to_lowercase()).collect();
+        for pattern in &self.patterns {
+            if content.contains(pattern) || tags.iter().any(|t| t.contains(pattern)) {
+                return Err(PolicyViolation(format!("content matches forbidden pattern '{}'", pattern)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reserve `count` signatures in the rolling window
+    fn take_rate(&self, count: usize) -> Result<(), PolicyViolation> {
+        let Some(max) = self.policy.max_events_per_minute else {
+            return Ok(());
+        };
+        let mut recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        while recent.front().is_some_and(|t| now.duration_since(*t) >= RATE_WINDOW) {
+            recent.pop_front();
+        }
+        if recent.len() + count > max as usize {
+            return Err(PolicyViolation(format!("rate limit of {} events per minute reached", max)));
+        }
+        for _ in 0..count {
+            recent.push_back(now);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine(toml: &str) -> PolicyEngine {
+        PolicyEngine::new(toml::from_str(toml).unwrap()).unwrap()
+    }
+
+    fn event(kind: u16, content: &str) -> UnsignedEvent {
+        EventBuilder::new(Kind::from(kind), content).build(Keys::generate().public_key())
+    }
+
+    #[test]
+    fn test_default_allows_only_marmot_kinds() {
+        let engine = engine("");
+        assert!(engine.check_events(&[event(443, "")]).is_ok());
+        assert!(engine.check_events(&[event(1059, "")]).is_ok());
+        assert!(engine.check_events(&[event(1, "hello")]).is_err());
+        assert!(engine.check_events(&[event(0, "{}")]).is_err());
+    }
+
+    #[test]
+    fn test_forbidden_patterns_in_content_and_tags() {
+        let engine = engine("allowed_kinds = [1]\nforbidden_patterns = [\"NSEC1\"]");
+        assert!(engine.check_events(&[event(1, "fine")]).is_ok());
+        assert!(engine.check_events(&[event(1, "my key is nsec1abc")]).is_err());
+        let tagged = EventBuilder::new(Kind::TextNote, "")
+            .tag(Tag::custom(TagKind::custom("x"), ["Nsec1..."]))
+            .build(Keys::generate().public_key());
+        assert!(engine.check_events(&[tagged]).is_err());
+    }
+
+    #[test]
+    fn test_rate_limit_counts_batches() {
+        let engine = engine("max_events_per_minute = 3");
+        assert!(engine.check_events(&[event(443, ""), event(443, "")]).is_ok());
+        // A batch that would exceed the limit is refused as a whole
+        assert!(engine.check_events(&[event(443, ""), event(443, "")]).is_err());
+        assert!(engine.check_events(&[event(443, "")]).is_ok());
+        assert!(engine.check_events(&[event(443, "")]).is_err());
+    }
+
+    #[test]
+    fn test_gift_wrap_recipients() {
+        let friend = Keys::generate().public_key();
+        let engine = engine(&format!("allowed_recipients = [\"{}\"]", friend.to_bech32().unwrap()));
+        let rumor = event(444, "");
+        assert!(engine.check_gift_wrap(&friend, &rumor).is_ok());
+        let stranger = Keys::generate().public_key();
+        assert!(engine.check_gift_wrap(&stranger, &rumor).unwrap_err().0.contains("allowed_recipients"));
+    }
+
+    #[test]
+    fn test_load_rejects_bad_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("marmot.db");
+        assert!(SigningPolicy::load(&db_path).unwrap().allowed_kinds.is_none());
+
+        std::fs::write(SigningPolicy::config_path(&db_path), "allowed_kind = [1]").unwrap();
+        assert!(SigningPolicy::load(&db_path).is_err());
+        std::fs::write(SigningPolicy::config_path(&db_path), "allowed_recipients = [\"bob\"]").unwrap();
+        assert!(SigningPolicy::load(&db_path).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+//! Work deferred while the bunker is offline (listen mode)
+//!
+//! In bunker mode, unwrapping a gift wrap and signing an outgoing event both
+//! need the remote signer. Rather than dropping that work (or exiting
+//! `listen`), it is parked here and retried on the next poll. Events that
+//! were signed but could not be published are kept too, so they go out
+//! unchanged (and in order) once the relays are reachable again.
+
+use std::collections::VecDeque;
+
+use nostr::prelude::*;
+
+/// Upper bound per queue; the oldest entries are dropped beyond this
+const MAX_QUEUED: usize = 1000;
+
+#[derive(Default)]
+pub struct OfflineQueue {
+    gift_wraps: VecDeque<Event>,
+    outgoing: VecDeque<UnsignedEvent>,
+    /// Signed events no relay accepted yet; published before `outgoing`
+    unsent: VecDeque<Event>,
+}
+
+impl OfflineQueue {
+    /// Keep a gift wrap we couldn't decrypt (ignores duplicates)
+    pub fn defer_gift_wrap(&mut self, event: &Event) {
+        if self.gift_wraps.iter().any(|e| e.id == event.id) {
+            return;
+        }
+        if self.gift_wraps.len() == MAX_QUEUED {
+            self.gift_wraps.pop_front();
+        }
+        self.gift_wraps.push_back(event.clone());
+    }
+
+    /// Keep an event we couldn't get signed
+    pub fn defer_outgoing(&mut self, unsigned: UnsignedEvent) {
+        if self.outgoing.len() == MAX_QUEUED {
+            self.outgoing.pop_front();
+        }
+        self.outgoing.push_back(unsigned);
+    }
+
+    /// Keep a signed event that couldn't be published
+    pub fn defer_unsent(&mut self, event: Event) {
+        if self.unsent.len() == MAX_QUEUED {
+            self.unsent.pop_front();
+        }
+        self.unsent.push_back(event);
+    }
+
+    pub fn take_gift_wraps(&mut self) -> Vec<Event> {
+        self.gift_wraps.drain(..).collect()
+    }
+
+    pub fn take_outgoing(&mut self) -> Vec<UnsignedEvent> {
+        self.outgoing.drain(..).collect()
+    }
+
+    pub fn take_unsent(&mut self) -> Vec<Event> {
+        self.unsent.drain(..).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.gift_wraps.len() + self.outgoing.len() + self.unsent.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(content: &str) -> Event {
+        EventBuilder::text_note(content).sign_with_keys(&Keys::generate()).unwrap()
+    }
+
+    #[test]
+    fn test_gift_wraps_deduplicated_and_drained() {
+        let mut queue = OfflineQueue::default();
+        let wrap = event("a");
+        queue.defer_gift_wrap(&wrap);
+        queue.defer_gift_wrap(&wrap);
+        queue.defer_gift_wrap(&event("b"));
+        assert_eq!(queue.len(), 2);
+
+        let taken = queue.take_gift_wraps();
+        assert_eq!(taken[0].id, wrap.id);
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn test_outgoing_capped() {
+        let mut queue = OfflineQueue::default();
+        let pubkey = Keys::generate().public_key();
+        for i in 0..MAX_QUEUED + 5 {
+            queue.defer_outgoing(EventBuilder::text_note(i.to_string()).build(pubkey));
+        }
+        let taken = queue.take_outgoing();
+        assert_eq!(taken.len(), MAX_QUEUED);
+        assert_eq!(taken[0].content, "5");
+    }
+
+    #[test]
+    fn test_unsent_kept_in_order() {
+        let mut queue = OfflineQueue::default();
+        let (first, second) = (event("first"), event("second"));
+        queue.defer_unsent(first.clone());
+        queue.defer_unsent(second.clone());
+        assert_eq!(queue.len(), 2);
+
+        let taken = queue.take_unsent();
+        assert_eq!(taken.iter().map(|e| e.id).collect::<Vec<_>>(), vec![first.id, second.id]);
+        assert_eq!(queue.len(), 0);
+    }
+}
@@ -0,0 +1,218 @@
+//! Self-healing NIP-46 connection
+//!
+//! [`ReconnectingBunker`] wraps the NostrConnect session used by both
+//! `MarmotSigner` and the relay pool. When a request fails it rebuilds the
+//! session and retries once; if the bunker is still unreachable it is marked
+//! offline and further requests fail fast until an exponential backoff
+//! window has passed, so a long-running `listen` doesn't stall on every
+//! gift wrap while the bunker is down.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use nostr::prelude::*;
+use nostr_connect::prelude::*;
+use tokio::sync::{Mutex, RwLock};
+
+use super::audit::AuditLog;
+use super::config::BunkerConfig;
+use super::signer::open_nostr_connect;
+
+/// Longest wait between reconnect attempts while the bunker is offline
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Delay before the next attempt after `failures` consecutive failures
+pub fn backoff(failures: u32) -> Duration {
+    Duration::from_secs(1u64 << failures.min(16)).min(MAX_BACKOFF)
+}
+
+#[derive(Default)]
+struct LinkState {
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+/// NIP-46 signer that reconnects to the bunker on failure
+pub struct ReconnectingBunker {
+    current: RwLock<NostrConnect>,
+    config: BunkerConfig,
+    user_pubkey: PublicKey,
+    proxy: Option<SocketAddr>,
+    timeout: Duration,
+    audit: Arc<Mutex<AuditLog>>,
+    state: std::sync::Mutex<LinkState>,
+}
+
+impl std::fmt::Debug for ReconnectingBunker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReconnectingBunker")
+            .field("remote_signer", &self.config.remote_signer_pubkey)
+            .finish()
+    }
+}
+
+impl ReconnectingBunker {
+    pub fn new(
+        connect: NostrConnect,
+        config: BunkerConfig,
+        user_pubkey: PublicKey,
+        proxy: Option<SocketAddr>,
+        timeout: Duration,
+        audit: Arc<Mutex<AuditLog>>,
+    ) -> Self {
+        Self {
+            current: RwLock::new(connect),
+            config,
+            user_pubkey,
+            proxy,
+            timeout,
+            audit,
+            state: std::sync::Mutex::new(LinkState::default()),
+        }
+    }
+
+    /// Whether the last request reached the bunker
+    pub fn is_online(&self) -> bool {
+        self.state.lock().map(|s| s.failures == 0).unwrap_or(true)
+    }
+
+    /// Replace the session with a fresh one (same client key, no new handshake)
+    async fn reconnect(&self) {
+        let fresh = match open_nostr_connect(&self.config, self.proxy, self.timeout) {
+            Ok(fresh) => fresh,
+            Err(e) => {
+                tracing::warn!("Bunker reconnect failed: {}", e);
+                return;
+            }
+        };
+        let _ = fresh.non_secure_set_user_public_key(self.user_pubkey);
+        let old = std::mem::replace(&mut *self.current.write().await, fresh);
+        old.shutdown().await;
+        self.audit.lock().await.record_with(
+            "bunker_reconnect",
+            "rebuilt NIP-46 session",
+            serde_json::json!({ "remote_signer": self.config.remote_signer_pubkey }),
+        );
+    }
+
+    /// Run `op` against the current session, reconnecting and retrying once on failure
+    async fn call<T, F, Fut>(&self, name: &str, op: F) -> Result<T, SignerError>
+    where
+        F: Fn(NostrConnect) -> Fut,
+        Fut: Future<Output = Result<T, SignerError>>,
+    {
+        let waiting = self.state.lock().ok().and_then(|s| s.retry_at).filter(|at| Instant::now() < *at);
+        if let Some(at) = waiting {
+            return Err(SignerError::backend(std::io::Error::other(format!(
+                "bunker offline, next reconnect attempt in {}s",
+                at.saturating_duration_since(Instant::now()).as_secs()
+            ))));
+        }
+
+        let first = op(self.current.read().await.clone()).await;
+        let result = match first {
+            Ok(value) => Ok(value),
+            Err(e) => {
+                tracing::warn!("Bunker {} failed ({}), reconnecting", name, e);
+                self.reconnect().await;
+                op(self.current.read().await.clone()).await
+            }
+        };
+
+        if let Ok(mut state) = self.state.lock() {
+            match &result {
+                Ok(_) => {
+                    if state.failures > 0 {
+                        eprintln!("✓ Bunker is back online");
+                    }
+                    *state = LinkState::default();
+                }
+                Err(e) => {
+                    let delay = backoff(state.failures);
+                    state.failures += 1;
+                    state.retry_at = Some(Instant::now() + delay);
+                    eprintln!("⚠️  Bunker unreachable ({}), retrying in {}s", e, delay.as_secs());
+                }
+            }
+        }
+        result
+    }
+
+    pub async fn shutdown(&self) {
+        self.current.read().await.clone().shutdown().await;
+    }
+}
+
+impl NostrSigner for ReconnectingBunker {
+    fn backend(&self) -> SignerBackend<'_> {
+        SignerBackend::NostrConnect
+    }
+
+    fn get_public_key(&self) -> BoxedFuture<'_, Result<PublicKey, SignerError>> {
+        let user_pubkey = self.user_pubkey;
+        Box::pin(async move { Ok(user_pubkey) })
+    }
+
+    fn sign_event(&self, unsigned: UnsignedEvent) -> BoxedFuture<'_, Result<Event, SignerError>> {
+        Box::pin(self.call("sign_event", move |connect| {
+            let unsigned = unsigned.clone();
+            async move { connect.sign_event(unsigned).await }
+        }))
+    }
+
+    fn nip04_encrypt<'a>(
+        &'a self,
+        public_key: &'a PublicKey,
+        content: &'a str,
+    ) -> BoxedFuture<'a, Result<String, SignerError>> {
+        Box::pin(self.call("nip04_encrypt", move |connect| async move {
+            connect.nip04_encrypt(public_key, content).await
+        }))
+    }
+
+    fn nip04_decrypt<'a>(
+        &'a self,
+        public_key: &'a PublicKey,
+        encrypted_content: &'a str,
+    ) -> BoxedFuture<'a, Result<String, SignerError>> {
+        Box::pin(self.call("nip04_decrypt", move |connect| async move {
+            connect.nip04_decrypt(public_key, encrypted_content).await
+        }))
+    }
+
+    fn nip44_encrypt<'a>(
+        &'a self,
+        public_key: &'a PublicKey,
+        content: &'a str,
+    ) -> BoxedFuture<'a, Result<String, SignerError>> {
+        Box::pin(self.call("nip44_encrypt", move |connect| async move {
+            connect.nip44_encrypt(public_key, content).await
+        }))
+    }
+
+    fn nip44_decrypt<'a>(
+        &'a self,
+        public_key: &'a PublicKey,
+        payload: &'a str,
+    ) -> BoxedFuture<'a, Result<String, SignerError>> {
+        Box::pin(self.call("nip44_decrypt", move |connect| async move {
+            connect.nip44_decrypt(public_key, payload).await
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        assert_eq!(backoff(0), Duration::from_secs(1));
+        assert_eq!(backoff(1), Duration::from_secs(2));
+        assert_eq!(backoff(4), Duration::from_secs(16));
+        assert_eq!(backoff(9), MAX_BACKOFF);
+        assert_eq!(backoff(u32::MAX), MAX_BACKOFF);
+    }
+}
@@ -10,6 +10,7 @@
 //! - MDK operations need a PublicKey, not the full signer
 //! - Gift-wrap operations (NIP-59) need the full signer for encryption
 
+use std::net::SocketAddr;
 use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
@@ -20,11 +21,53 @@ use nostr_connect::prelude::*;
 use nostr_sdk::prelude::*;
 use tokio::sync::Mutex;
 
-use super::audit::AuditLog;
-use super::config::{BunkerConfig, SigningMode};
+use super::audit::{AuditLog, RotationPolicy};
+use super::config::{required_permissions, BunkerConfig, SigningMode};
+use super::permissions;
+use super::policy::{PolicyEngine, PolicyViolation};
+use super::reconnect::ReconnectingBunker;
+use crate::relays::AuthPolicy;
+
+/// Default NIP-46 request timeout (override with `--bunker-timeout`)
+pub const DEFAULT_BUNKER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Open a NIP-46 client for a stored bunker config
+///
+/// When `proxy` is set, the NIP-46 relay connections go through that SOCKS5 proxy.
+pub fn open_nostr_connect(config: &BunkerConfig, proxy: Option<SocketAddr>, timeout: Duration) -> Result<NostrConnect> {
+    let uri = config.to_nostr_connect_uri()?;
+    let client_keys = config.client_keys()?;
+    let opts = proxy.map(|addr| RelayOptions::new().connection_mode(ConnectionMode::proxy(addr)));
+    NostrConnect::new(uri, client_keys, timeout, opts)
+        .map_err(|e| anyhow::anyhow!("Failed to create NIP-46 client: {}", e))
+}
 
-/// NIP-46 connection timeout
-const BUNKER_TIMEOUT: Duration = Duration::from_secs(30);
+/// Open a NIP-46 client, first requesting any permissions this run needs
+/// (see [`required_permissions`]) that the config has not asked for yet
+///
+/// A bunker that never connected must accept them; for one that already has a
+/// session a refusal is only a warning, since it still holds the earlier grants.
+pub async fn connect_bunker(
+    config: &mut BunkerConfig,
+    proxy: Option<SocketAddr>,
+    timeout: Duration,
+    auth: &AuthPolicy,
+) -> Result<NostrConnect> {
+    let missing = permissions::missing_permissions(config, &required_permissions(auth));
+    if !missing.is_empty() {
+        let mut wanted = config.requested_permissions.clone();
+        wanted.extend(missing.iter().cloned());
+        if config.last_connected.is_none() && config.requested_permissions.is_empty() {
+            permissions::request_permissions(config, &wanted, proxy).await?;
+        } else {
+            eprintln!("🔐 This run needs permissions not granted before: {}", missing.join(", "));
+            if let Err(e) = permissions::request_permissions(config, &wanted, proxy).await {
+                eprintln!("⚠️  {:#} — continuing with the earlier permissions", e);
+            }
+        }
+    }
+    open_nostr_connect(config, proxy, timeout)
+}
 
 /// Unified signer that supports both direct keys and NIP-46 remote signing
 pub struct MarmotSigner {
@@ -37,16 +80,22 @@ pub struct MarmotSigner {
     /// DB path for persisting config updates
     #[allow(dead_code)]
     db_path: std::path::PathBuf,
+    /// SOCKS5 proxy for relay connections
+    proxy: Option<SocketAddr>,
+    /// Local signing policy (`marmot.policy.toml`)
+    policy: PolicyEngine,
 }
 
 enum SignerMode {
     /// Direct nsec signing (keys available locally)
     Direct {
         keys: Keys,
+        /// Decrypted from a stored NIP-49 ncryptsec
+        encrypted_at_rest: bool,
     },
-    /// NIP-46 remote signing via bunker
+    /// NIP-46 remote signing via bunker (reconnects on failure)
     Bunker {
-        connect: NostrConnect,
+        connect: Arc<ReconnectingBunker>,
         #[allow(dead_code)]
         config: BunkerConfig,
     },
@@ -58,8 +107,30 @@ impl MarmotSigner {
         signing_mode: SigningMode,
         db_path: &Path,
         audit: Arc<Mutex<AuditLog>>,
+        proxy: Option<SocketAddr>,
+        bunker_timeout: Duration,
+        auth: &AuthPolicy,
     ) -> Result<Self> {
+        // Audit entries are signed with the key this process holds: the user
+        // key in direct mode, the NIP-46 client key in bunker mode
+        let audit_key = match &signing_mode {
+            SigningMode::DirectKey(keys) | SigningMode::EncryptedKey(keys) => Some(keys.clone()),
+            SigningMode::Bunker(config) => config.client_keys().ok(),
+        };
+        if let Some(keys) = audit_key {
+            audit.lock().await.set_signing_keys(keys);
+        }
+        let policy = PolicyEngine::load(db_path)?;
+
         match signing_mode {
+            SigningMode::EncryptedKey(keys) => Ok(Self {
+                public_key: keys.public_key(),
+                mode: SignerMode::Direct { keys, encrypted_at_rest: true },
+                audit,
+                db_path: db_path.to_path_buf(),
+                proxy,
+                policy,
+            }),
             SigningMode::DirectKey(keys) => {
                 let public_key = keys.public_key();
 
@@ -67,26 +138,24 @@ impl MarmotSigner {
                 if std::env::var("MARMOT_NO_NSEC_WARNING").is_err() {
                     eprintln!(
                         "⚠️  Using direct nsec signing. For long-running agents, consider \
-                         bunker mode:\n   marmot-cli init --bunker \"bunker://...\"\n"
+                         bunker mode or an encrypted key:\n   marmot-cli init --bunker \"bunker://...\"\n   \
+                         marmot-cli init --nsec \"nsec1...\" --encrypt-key\n"
                     );
                 }
 
                 Ok(Self {
-                    mode: SignerMode::Direct { keys },
+                    mode: SignerMode::Direct { keys, encrypted_at_rest: false },
                     public_key,
                     audit,
                     db_path: db_path.to_path_buf(),
+                    proxy,
+                    policy,
                 })
             }
             SigningMode::Bunker(mut config) => {
-                // Create NostrConnect client
-                let uri = config.to_nostr_connect_uri()?;
-                let client_keys = config.client_keys()?;
-
                 eprintln!("🔐 Connecting to bunker...");
 
-                let connect = NostrConnect::new(uri, client_keys, BUNKER_TIMEOUT, None)
-                    .map_err(|e| anyhow::anyhow!("Failed to create NIP-46 client: {}", e))?;
+                let connect = connect_bunker(&mut config, proxy, bunker_timeout, auth).await?;
 
                 // If we have a cached user pubkey, set it to avoid an extra round trip
                 if let Some(cached_pk) = config.cached_user_pubkey() {
@@ -116,14 +185,32 @@ impl MarmotSigner {
 
                 {
                     let mut log = audit.lock().await;
-                    log.record("bunker_connect", &format!("Connected to bunker, user pubkey: {}", public_key.to_hex()));
+                    log.record_with(
+                        "bunker_connect",
+                        "Connected to bunker",
+                        serde_json::json!({
+                            "user_pubkey": public_key.to_hex(),
+                            "remote_signer": config.remote_signer_pubkey,
+                            "relays": config.relays,
+                        }),
+                    );
                 }
 
+                let connect = Arc::new(ReconnectingBunker::new(
+                    connect,
+                    config.clone(),
+                    public_key,
+                    proxy,
+                    bunker_timeout,
+                    audit.clone(),
+                ));
                 Ok(Self {
                     mode: SignerMode::Bunker { connect, config },
                     public_key,
                     audit,
                     db_path: db_path.to_path_buf(),
+                    proxy,
+                    policy,
                 })
             }
         }
@@ -138,7 +225,7 @@ impl MarmotSigner {
     #[allow(dead_code)]
     pub fn direct_keys(&self) -> Option<&Keys> {
         match &self.mode {
-            SignerMode::Direct { keys } => Some(keys),
+            SignerMode::Direct { keys, .. } => Some(keys),
             SignerMode::Bunker { .. } => None,
         }
     }
@@ -148,31 +235,69 @@ impl MarmotSigner {
         matches!(self.mode, SignerMode::Bunker { .. })
     }
 
+    /// False while the bunker is unreachable (always true in direct mode)
+    pub fn is_online(&self) -> bool {
+        match &self.mode {
+            SignerMode::Direct { .. } => true,
+            SignerMode::Bunker { connect, .. } => connect.is_online(),
+        }
+    }
+
     /// Get the signing mode description for display
     pub fn mode_description(&self) -> &str {
         match &self.mode {
+            SignerMode::Direct { encrypted_at_rest: true, .. } => "direct (NIP-49 ncryptsec)",
             SignerMode::Direct { .. } => "direct (nsec)",
             SignerMode::Bunker { .. } => "NIP-46 bunker",
         }
     }
 
+    /// Archive the audit log according to `policy`
+    pub async fn set_audit_rotation(&self, policy: RotationPolicy) {
+        self.audit.lock().await.set_rotation(policy);
+    }
+
+    /// Record an application-level outcome (send results, callbacks) in the audit log
+    pub async fn audit(&self, operation: &str, details: &str, context: serde_json::Value) {
+        self.audit.lock().await.record_with(operation, details, context);
+    }
+
     /// Sign an event using the appropriate method
     ///
     /// In direct mode: signs locally
     /// In bunker mode: sends sign_event request to bunker
     pub async fn sign_event(&self, builder: EventBuilder) -> Result<Event> {
+        self.sign_unsigned(builder.build(self.public_key)).await
+    }
+
+    /// Sign an already-built event (e.g. one deferred while the bunker was offline)
+    pub async fn sign_unsigned(&self, unsigned: UnsignedEvent) -> Result<Event> {
+        if let Err(violation) = self.policy.check_events(std::slice::from_ref(&unsigned)) {
+            return Err(self.deny(violation, serde_json::json!({ "kind": unsigned.kind.as_u16() })).await);
+        }
+        self.sign_checked(unsigned).await
+    }
+
+    /// Record a policy denial in the audit log and turn it into an error
+    async fn deny(&self, violation: PolicyViolation, mut context: serde_json::Value) -> anyhow::Error {
+        context["reason"] = violation.0.clone().into();
+        self.audit.lock().await.record_with("policy_denied", &violation.0, context);
+        violation.into()
+    }
+
+    /// Sign an event the policy has already allowed
+    async fn sign_checked(&self, unsigned: UnsignedEvent) -> Result<Event> {
         {
             let mut log = self.audit.lock().await;
-            log.record("sign_event_request", "signing event");
+            log.record_with("sign_event_request", "signing event", serde_json::json!({ "kind": unsigned.kind.as_u16() }));
         }
 
         let event = match &self.mode {
-            SignerMode::Direct { keys } => {
-                builder.sign(keys).await
+            SignerMode::Direct { keys, .. } => {
+                unsigned.sign_with_keys(keys)
                     .context("Failed to sign event with local keys")?
             }
             SignerMode::Bunker { connect, .. } => {
-                let unsigned = builder.build(self.public_key);
                 connect.sign_event(unsigned).await
                     .map_err(|e| anyhow::anyhow!("Bunker signing failed: {}\nIs the bunker still online?", e))?
             }
@@ -180,7 +305,11 @@ impl MarmotSigner {
 
         {
             let mut log = self.audit.lock().await;
-            log.record("sign_event_success", &format!("event_id: {}, kind: {}", event.id.to_hex(), event.kind.as_u16()));
+            log.record_with(
+                "sign_event_success",
+                "event signed",
+                serde_json::json!({ "event_id": event.id.to_hex(), "kind": event.kind.as_u16() }),
+            );
         }
 
         Ok(event)
@@ -193,20 +322,25 @@ impl MarmotSigner {
     #[allow(dead_code)]
     pub async fn sign_events(&self, builders: Vec<EventBuilder>) -> Result<Vec<Event>> {
         let count = builders.len();
+        let unsigned: Vec<UnsignedEvent> = builders.into_iter().map(|b| b.build(self.public_key)).collect();
+        if let Err(violation) = self.policy.check_events(&unsigned) {
+            let kinds: Vec<u16> = unsigned.iter().map(|u| u.kind.as_u16()).collect();
+            return Err(self.deny(violation, serde_json::json!({ "count": count, "kinds": kinds })).await);
+        }
 
         {
             let mut log = self.audit.lock().await;
-            log.record("sign_batch_request", &format!("count: {}", count));
+            log.record_with("sign_batch_request", "signing batch", serde_json::json!({ "count": count }));
         }
 
         let mut events = Vec::with_capacity(count);
-        for builder in builders {
-            events.push(self.sign_event(builder).await?);
+        for unsigned in unsigned {
+            events.push(self.sign_checked(unsigned).await?);
         }
 
         {
             let mut log = self.audit.lock().await;
-            log.record("sign_batch_complete", &format!("count: {}", count));
+            log.record_with("sign_batch_complete", "batch signed", serde_json::json!({ "count": count }));
         }
 
         Ok(events)
@@ -221,13 +355,21 @@ impl MarmotSigner {
         receiver: &PublicKey,
         rumor: UnsignedEvent,
     ) -> Result<Event> {
+        if let Err(violation) = self.policy.check_gift_wrap(receiver, &rumor) {
+            let context = serde_json::json!({ "receiver": receiver.to_hex(), "rumor_kind": rumor.kind.as_u16() });
+            return Err(self.deny(violation, context).await);
+        }
         {
             let mut log = self.audit.lock().await;
-            log.record("gift_wrap_request", &format!("receiver: {}", &receiver.to_hex()[..16]));
+            log.record_with(
+                "gift_wrap_request",
+                "wrapping rumor",
+                serde_json::json!({ "receiver": receiver.to_hex(), "rumor_kind": rumor.kind.as_u16() }),
+            );
         }
 
         let event = match &self.mode {
-            SignerMode::Direct { keys } => {
+            SignerMode::Direct { keys, .. } => {
                 EventBuilder::gift_wrap(keys, receiver, rumor, [])
                     .await
                     .context("Failed to create gift-wrapped event")?
@@ -235,7 +377,7 @@ impl MarmotSigner {
             SignerMode::Bunker { connect, .. } => {
                 // Gift-wrap with NIP-46 signer
                 // The nostr crate's gift_wrap function accepts NostrSigner
-                EventBuilder::gift_wrap(connect, receiver, rumor, [])
+                EventBuilder::gift_wrap(connect.as_ref(), receiver, rumor, [])
                     .await
                     .map_err(|e| anyhow::anyhow!(
                         "Bunker gift-wrap failed: {}\n\
@@ -247,7 +389,11 @@ impl MarmotSigner {
 
         {
             let mut log = self.audit.lock().await;
-            log.record("gift_wrap_success", &format!("event_id: {}", event.id.to_hex()));
+            log.record_with(
+                "gift_wrap_success",
+                "gift wrap created",
+                serde_json::json!({ "event_id": event.id.to_hex(), "kind": event.kind.as_u16(), "receiver": receiver.to_hex() }),
+            );
         }
 
         Ok(event)
@@ -259,13 +405,13 @@ impl MarmotSigner {
     /// In bunker mode: uses bunker for NIP-44 decryption
     pub async fn extract_rumor(&self, event: &Event) -> Result<UnwrappedGift> {
         match &self.mode {
-            SignerMode::Direct { keys } => {
+            SignerMode::Direct { keys, .. } => {
                 nostr::nips::nip59::extract_rumor(keys, event)
                     .await
                     .context("Failed to extract rumor from gift-wrap")
             }
             SignerMode::Bunker { connect, .. } => {
-                nostr::nips::nip59::extract_rumor(connect, event)
+                nostr::nips::nip59::extract_rumor(connect.as_ref(), event)
                     .await
                     .map_err(|e| anyhow::anyhow!(
                         "Bunker gift-unwrap failed: {}\n\
@@ -277,23 +423,131 @@ impl MarmotSigner {
     }
 
     /// Build a nostr-sdk Client with the appropriate signer
-    pub async fn build_client(&self) -> Result<Client> {
-        let client = match &self.mode {
-            SignerMode::Direct { keys } => {
-                Client::builder().signer(keys.clone()).build()
-            }
-            SignerMode::Bunker { connect, .. } => {
-                Client::builder().signer(connect.clone()).build()
-            }
+    ///
+    /// NIP-42 AUTH challenges are answered automatically, but only for relays
+    /// allowed by `auth`; the AUTH event is signed by the same key (or bunker)
+    /// as everything else and recorded in the audit log.
+    pub async fn build_client(&self, auth: AuthPolicy) -> Result<Client> {
+        let inner: Arc<dyn NostrSigner> = match &self.mode {
+            SignerMode::Direct { keys, .. } => Arc::new(keys.clone()),
+            SignerMode::Bunker { connect, .. } => connect.clone(),
+        };
+        let mut opts = ClientOptions::new().automatic_authentication(auth.is_enabled());
+        if let Some(addr) = self.proxy {
+            opts = opts.connection(Connection::new().proxy(addr));
+        }
+        let signer = PoolSigner {
+            inner,
+            audit: self.audit.clone(),
+            auth,
         };
-        Ok(client)
+        Ok(Client::builder().signer(signer).opts(opts).build())
     }
 
     /// Shutdown bunker connection (if in bunker mode)
     #[allow(dead_code)]
     pub async fn shutdown(self) {
-        if let SignerMode::Bunker { connect, .. } = self.mode {
+        if let SignerMode::Bunker { connect, .. } = &self.mode {
             connect.shutdown().await;
         }
     }
 }
+
+/// Signer handed to the nostr-sdk relay pool
+///
+/// nostr-sdk signs NIP-42 AUTH events on its own; this wrapper enforces the
+/// per-relay opt-in and records each AUTH in the audit log before delegating
+/// to the real signer.
+struct PoolSigner {
+    inner: Arc<dyn NostrSigner>,
+    audit: Arc<Mutex<AuditLog>>,
+    auth: AuthPolicy,
+}
+
+impl std::fmt::Debug for PoolSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PoolSigner").field("auth", &self.auth).finish()
+    }
+}
+
+impl PoolSigner {
+    async fn sign_auth(&self, unsigned: UnsignedEvent) -> Result<Event, SignerError> {
+        let relay = unsigned
+            .tags
+            .iter()
+            .find(|t| t.kind() == TagKind::Relay)
+            .and_then(|t| t.content())
+            .unwrap_or_default()
+            .to_string();
+
+        if !self.auth.allows(&relay) {
+            self.audit.lock().await.record_with(
+                "nip42_auth_denied",
+                "relay not in --auth-relays",
+                serde_json::json!({ "relay": relay }),
+            );
+            return Err(SignerError::backend(std::io::Error::other(format!(
+                "NIP-42 AUTH not enabled for {} (add it to --auth-relays)",
+                relay
+            ))));
+        }
+
+        let event = self.inner.sign_event(unsigned).await?;
+        self.audit.lock().await.record_with(
+            "nip42_auth",
+            "authenticated to relay",
+            serde_json::json!({ "relay": relay, "event_id": event.id.to_hex(), "kind": event.kind.as_u16() }),
+        );
+        Ok(event)
+    }
+}
+
+impl NostrSigner for PoolSigner {
+    fn backend(&self) -> SignerBackend<'_> {
+        self.inner.backend()
+    }
+
+    fn get_public_key(&self) -> BoxedFuture<'_, Result<PublicKey, SignerError>> {
+        self.inner.get_public_key()
+    }
+
+    fn sign_event(&self, unsigned: UnsignedEvent) -> BoxedFuture<'_, Result<Event, SignerError>> {
+        if unsigned.kind == Kind::Authentication {
+            Box::pin(self.sign_auth(unsigned))
+        } else {
+            self.inner.sign_event(unsigned)
+        }
+    }
+
+    fn nip04_encrypt<'a>(
+        &'a self,
+        public_key: &'a PublicKey,
+        content: &'a str,
+    ) -> BoxedFuture<'a, Result<String, SignerError>> {
+        self.inner.nip04_encrypt(public_key, content)
+    }
+
+    fn nip04_decrypt<'a>(
+        &'a self,
+        public_key: &'a PublicKey,
+        encrypted_content: &'a str,
+    ) -> BoxedFuture<'a, Result<String, SignerError>> {
+        self.inner.nip04_decrypt(public_key, encrypted_content)
+    }
+
+    fn nip44_encrypt<'a>(
+        &'a self,
+        public_key: &'a PublicKey,
+        content: &'a str,
+    ) -> BoxedFuture<'a, Result<String, SignerError>> {
+        self.inner.nip44_encrypt(public_key, content)
+    }
+
+    fn nip44_decrypt<'a>(
+        &'a self,
+        public_key: &'a PublicKey,
+        payload: &'a str,
+    ) -> BoxedFuture<'a, Result<String, SignerError>> {
+        self.inner.nip44_decrypt(public_key, payload)
+    }
+}
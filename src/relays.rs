@@ -0,0 +1,488 @@
+//! Relay health and latency diagnostics
+//!
+//! Collects every relay marmot-cli depends on (global relays, per-group
+//! relays stored in MDK, and the NIP-46 bunker relays) and probes each one
+//! for connectivity, round-trip latency, NIP-11 information, which Marmot
+//! kinds it has stored, and whether its published NIP-11 policy (retention,
+//! timestamp limits) would drop what Marmot publishes. Actual acceptance of a
+//! write can't be tested without publishing, so none is claimed.
+
+use std::collections::BTreeMap;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use nostr_sdk::prelude::*;
+use serde::Deserialize;
+
+/// Event kinds Marmot publishes: key packages, group messages, gift wraps
+///
+/// Welcomes (444) only ever travel inside a gift wrap, so relays never see them.
+pub const MARMOT_KINDS: [u16; 3] = [443, 445, 1059];
+
+/// How far NIP-59 backdates gift wraps (randomized `created_at`)
+const GIFT_WRAP_BACKDATE_SECS: u64 = 2 * 24 * 60 * 60;
+
+/// Shorter retention than this risks members offline for a while missing events
+const MIN_RETENTION_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Timeout for each individual probe (connect, query, NIP-11 fetch)
+const PROBE_TIMEOUT: Duration = Duration::from_secs(8);
+
+/// How long to wait for relays to connect before running a command
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectOptions {
+    /// Minimum number of connected relays before we proceed
+    pub quorum: usize,
+    /// Maximum time to wait for the quorum
+    pub timeout: Duration,
+}
+
+impl ConnectOptions {
+    /// Quorum can never exceed the number of relays we actually have
+    pub fn effective_quorum(&self, relay_count: usize) -> usize {
+        self.quorum.clamp(1, relay_count.max(1))
+    }
+}
+
+/// Everything `MarmotCli` needs to set up its relay pool
+#[derive(Debug, Clone)]
+pub struct NetworkOptions {
+    pub relay_urls: Vec<String>,
+    pub auth: AuthPolicy,
+    pub proxy: Option<SocketAddr>,
+    /// Timeout for each NIP-46 request to the bunker
+    pub bunker_timeout: Duration,
+    /// `None` skips connecting entirely (purely local commands)
+    pub connect: Option<ConnectOptions>,
+}
+
+/// Wait until at least `quorum` relays in the pool are connected, or the timeout elapses
+///
+/// Returns the number of connected relays when we stopped waiting.
+pub async fn wait_for_quorum(client: &Client, opts: ConnectOptions) -> usize {
+    let deadline = Instant::now() + opts.timeout;
+    loop {
+        let relays = client.relays().await;
+        let quorum = opts.effective_quorum(relays.len());
+        let connected = relays.values().filter(|r| r.is_connected()).count();
+        if connected >= quorum || Instant::now() >= deadline {
+            return connected;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+/// Which relays we will answer NIP-42 AUTH challenges for
+///
+/// AUTH reveals our identity to the relay, so it is opt-in per relay.
+#[derive(Debug, Clone, Default)]
+pub struct AuthPolicy {
+    all: bool,
+    relays: Vec<String>,
+}
+
+impl AuthPolicy {
+    /// Parse a comma-separated relay list; `*` enables AUTH for every relay
+    pub fn parse(spec: &str) -> Self {
+        let mut policy = Self::default();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            if entry == "*" {
+                policy.all = true;
+            } else {
+                policy.relays.push(normalize_relay(entry));
+            }
+        }
+        policy
+    }
+
+    /// Whether AUTH is enabled for at least one relay
+    pub fn is_enabled(&self) -> bool {
+        self.all || !self.relays.is_empty()
+    }
+
+    /// Whether we may authenticate to this relay
+    pub fn allows(&self, relay: &str) -> bool {
+        self.all || self.relays.contains(&normalize_relay(relay))
+    }
+}
+
+fn normalize_relay(url: &str) -> String {
+    url.trim().trim_end_matches('/').to_lowercase()
+}
+
+/// Why a relay is in our relay set
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RelaySource {
+    /// From `--relays` / configuration
+    Global,
+    /// Advertised in a group's relay list
+    Group(String),
+    /// Used to reach the NIP-46 bunker
+    Bunker,
+}
+
+impl std::fmt::Display for RelaySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RelaySource::Global => write!(f, "global"),
+            RelaySource::Group(name) => write!(f, "group '{}'", name),
+            RelaySource::Bunker => write!(f, "bunker"),
+        }
+    }
+}
+
+/// Merge relays from all sources, keeping track of every reason each relay is used
+pub fn collect_relay_sources(
+    global: &[RelayUrl],
+    groups: &[(String, Vec<RelayUrl>)],
+    bunker: &[String],
+) -> BTreeMap<RelayUrl, Vec<RelaySource>> {
+    let mut all: BTreeMap<RelayUrl, Vec<RelaySource>> = BTreeMap::new();
+    for relay in global {
+        all.entry(relay.clone()).or_default().push(RelaySource::Global);
+    }
+    for (name, relays) in groups {
+        for relay in relays {
+            let sources = all.entry(relay.clone()).or_default();
+            let source = RelaySource::Group(name.clone());
+            if !sources.contains(&source) {
+                sources.push(source);
+            }
+        }
+    }
+    for relay in bunker.iter().filter_map(|r| RelayUrl::parse(r).ok()) {
+        all.entry(relay).or_default().push(RelaySource::Bunker);
+    }
+    all
+}
+
+/// Subset of the NIP-11 relay information document we display
+#[derive(Debug, Default, Deserialize)]
+pub struct RelayInfo {
+    pub name: Option<String>,
+    pub software: Option<String>,
+    pub version: Option<String>,
+    #[serde(default)]
+    pub supported_nips: Vec<u16>,
+    pub limitation: Option<RelayLimitation>,
+    #[serde(default)]
+    pub retention: Vec<RetentionRule>,
+}
+
+impl RelayInfo {
+    /// The NIP-11 retention rule covering `kind`, if the relay publishes one
+    pub fn retention_for(&self, kind: u16) -> Option<&RetentionRule> {
+        self.retention.iter().find(|rule| rule.covers(kind))
+    }
+
+    /// Problems the relay's published policy would cause for Marmot events
+    pub fn policy_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for kind in MARMOT_KINDS {
+            match self.retention_for(kind) {
+                Some(rule) if rule.discards() => warnings.push(format!("Does not store kind {} (NIP-11 retention)", kind)),
+                Some(RetentionRule { time: Some(secs), .. }) if *secs < MIN_RETENTION_SECS => {
+                    warnings.push(format!("Keeps kind {} for only {}s (NIP-11 retention)", kind, secs))
+                }
+                _ => {}
+            }
+        }
+        let lower_limit = self.limitation.as_ref().and_then(|l| l.created_at_lower_limit);
+        if let Some(limit) = lower_limit.filter(|l| *l < GIFT_WRAP_BACKDATE_SECS) {
+            warnings.push(format!(
+                "Rejects events older than {}s; gift-wrapped welcomes are backdated up to {}s",
+                limit, GIFT_WRAP_BACKDATE_SECS
+            ));
+        }
+        warnings
+    }
+}
+
+/// A kind in a NIP-11 retention rule: a single kind or an inclusive range
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum KindSpec {
+    Single(u16),
+    Range([u16; 2]),
+}
+
+/// One entry of the NIP-11 `retention` array
+#[derive(Debug, Default, Deserialize)]
+pub struct RetentionRule {
+    /// Kinds the rule applies to (empty = all kinds)
+    #[serde(default)]
+    pub kinds: Vec<KindSpec>,
+    /// Seconds events are kept (`0` = not stored)
+    pub time: Option<u64>,
+    /// Events kept per kind (`0` = not stored)
+    pub count: Option<u64>,
+}
+
+impl RetentionRule {
+    fn covers(&self, kind: u16) -> bool {
+        self.kinds.is_empty()
+            || self.kinds.iter().any(|spec| match *spec {
+                KindSpec::Single(k) => k == kind,
+                KindSpec::Range([from, to]) => (from..=to).contains(&kind),
+            })
+    }
+
+    /// Whether events this rule covers aren't stored at all
+    pub fn discards(&self) -> bool {
+        self.time == Some(0) || self.count == Some(0)
+    }
+}
+
+/// NIP-11 `limitation` object (only fields relevant to Marmot)
+#[derive(Debug, Default, Deserialize)]
+pub struct RelayLimitation {
+    pub auth_required: Option<bool>,
+    pub payment_required: Option<bool>,
+    pub restricted_writes: Option<bool>,
+    /// Oldest `created_at` accepted, in seconds before now
+    pub created_at_lower_limit: Option<u64>,
+}
+
+/// Result of probing a single relay
+#[derive(Debug)]
+pub struct RelayProbe {
+    pub url: RelayUrl,
+    pub sources: Vec<RelaySource>,
+    pub connected: bool,
+    pub status: String,
+    pub connect_latency: Option<Duration>,
+    pub query_latency: Option<Duration>,
+    pub info: Option<RelayInfo>,
+    pub kinds_seen: Vec<u16>,
+    pub has_key_package: Option<bool>,
+    pub error: Option<String>,
+}
+
+/// Convert a `ws(s)://` relay URL into the `http(s)://` URL serving its NIP-11 document
+pub fn nip11_http_url(relay: &RelayUrl) -> String {
+    let url = relay.as_str();
+    if let Some(rest) = url.strip_prefix("wss://") {
+        format!("https://{}", rest)
+    } else if let Some(rest) = url.strip_prefix("ws://") {
+        format!("http://{}", rest)
+    } else {
+        url.to_string()
+    }
+}
+
+/// Parse a `--proxy` value (`socks5://host:port`, `socks5h://host:port` or `host:port`)
+pub fn parse_proxy(spec: &str) -> Result<SocketAddr> {
+    let addr = spec
+        .strip_prefix("socks5h://")
+        .or_else(|| spec.strip_prefix("socks5://"))
+        .unwrap_or(spec)
+        .trim_end_matches('/');
+    if addr.contains("://") {
+        anyhow::bail!("Unsupported proxy '{}': only SOCKS5 proxies are supported", spec);
+    }
+    addr.to_socket_addrs()
+        .with_context(|| format!("Invalid proxy address '{}'", spec))?
+        .next()
+        .with_context(|| format!("Proxy address '{}' did not resolve", spec))
+}
+
+/// HTTP client builder, routed through the SOCKS5 proxy if one is configured
+pub fn http_client_builder(proxy: Option<SocketAddr>) -> Result<reqwest::ClientBuilder> {
+    let mut builder = reqwest::Client::builder().timeout(PROBE_TIMEOUT);
+    if let Some(addr) = proxy {
+        // socks5h: let the proxy resolve hostnames so DNS doesn't leak
+        builder = builder.proxy(reqwest::Proxy::all(format!("socks5h://{}", addr))?);
+    }
+    Ok(builder)
+}
+
+/// Build an HTTP client, routed through the SOCKS5 proxy if one is configured
+pub fn http_client(proxy: Option<SocketAddr>) -> Result<reqwest::Client> {
+    http_client_builder(proxy)?.build().context("Failed to build HTTP client")
+}
+
+/// Fetch the NIP-11 relay information document
+pub async fn fetch_relay_info(relay: &RelayUrl, proxy: Option<SocketAddr>) -> Result<RelayInfo> {
+    let http = http_client(proxy)?;
+    let info = http
+        .get(nip11_http_url(relay))
+        .header("Accept", "application/nostr+json")
+        .send()
+        .await
+        .context("NIP-11 request failed")?
+        .error_for_status()
+        .context("NIP-11 request rejected")?
+        .json::<RelayInfo>()
+        .await
+        .context("Invalid NIP-11 document")?;
+    Ok(info)
+}
+
+/// Connect to a relay and measure connectivity, latency and Marmot readiness
+pub async fn probe_relay(
+    client: &Client,
+    url: RelayUrl,
+    sources: Vec<RelaySource>,
+    our_pubkey: PublicKey,
+    proxy: Option<SocketAddr>,
+) -> RelayProbe {
+    let mut probe = RelayProbe {
+        url: url.clone(),
+        sources,
+        connected: false,
+        status: "unknown".to_string(),
+        connect_latency: None,
+        query_latency: None,
+        info: None,
+        kinds_seen: Vec::new(),
+        has_key_package: None,
+        error: None,
+    };
+
+    if let Err(e) = client.add_relay(url.as_str()).await {
+        probe.error = Some(format!("Failed to add relay: {}", e));
+        return probe;
+    }
+
+    let started = Instant::now();
+    match client.try_connect_relay(url.as_str(), PROBE_TIMEOUT).await {
+        Ok(()) => {
+            probe.connected = true;
+            probe.connect_latency = Some(started.elapsed());
+        }
+        Err(e) => probe.error = Some(e.to_string()),
+    }
+    if let Ok(relay) = client.relay(url.as_str()).await {
+        probe.status = relay.status().to_string();
+    }
+
+    probe.info = fetch_relay_info(&url, proxy).await.ok();
+
+    if !probe.connected {
+        return probe;
+    }
+
+    // Round-trip latency: REQ → EOSE for our own key package
+    let filter = Filter::new().kind(Kind::MlsKeyPackage).author(our_pubkey).limit(1);
+    let started = Instant::now();
+    match client.fetch_events_from([url.as_str()], filter, PROBE_TIMEOUT).await {
+        Ok(events) => {
+            probe.query_latency = Some(started.elapsed());
+            probe.has_key_package = Some(!events.is_empty());
+        }
+        Err(e) => probe.error = Some(format!("Query failed: {}", e)),
+    }
+
+    // Which Marmot kinds does this relay have stored? (Says nothing about
+    // whether it would accept a new one.)
+    for kind in MARMOT_KINDS {
+        let filter = Filter::new().kind(Kind::from(kind)).limit(1);
+        if let Ok(events) = client.fetch_events_from([url.as_str()], filter, PROBE_TIMEOUT).await {
+            if !events.is_empty() {
+                probe.kinds_seen.push(kind);
+            }
+        }
+    }
+
+    probe
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effective_quorum_clamped() {
+        let opts = ConnectOptions { quorum: 5, timeout: Duration::from_secs(1) };
+        assert_eq!(opts.effective_quorum(3), 3);
+        assert_eq!(opts.effective_quorum(0), 1);
+        let opts = ConnectOptions { quorum: 0, timeout: Duration::from_secs(1) };
+        assert_eq!(opts.effective_quorum(3), 1);
+    }
+
+    #[test]
+    fn test_auth_policy_opt_in() {
+        let policy = AuthPolicy::parse("wss://private.example.com/, wss://Agents.Relay");
+        assert!(policy.is_enabled());
+        assert!(policy.allows("wss://private.example.com"));
+        assert!(policy.allows("wss://agents.relay/"));
+        assert!(!policy.allows("wss://relay.damus.io"));
+
+        let all = AuthPolicy::parse("*");
+        assert!(all.allows("wss://relay.damus.io"));
+
+        assert!(!AuthPolicy::parse("").is_enabled());
+    }
+
+    #[test]
+    fn test_parse_proxy() {
+        let expected: SocketAddr = "127.0.0.1:9050".parse().unwrap();
+        assert_eq!(parse_proxy("socks5://127.0.0.1:9050").unwrap(), expected);
+        assert_eq!(parse_proxy("socks5h://127.0.0.1:9050/").unwrap(), expected);
+        assert_eq!(parse_proxy("127.0.0.1:9050").unwrap(), expected);
+        assert!(parse_proxy("http://127.0.0.1:8080").is_err());
+        assert!(parse_proxy("127.0.0.1").is_err());
+    }
+
+    #[test]
+    fn test_nip11_http_url() {
+        let wss = RelayUrl::parse("wss://relay.damus.io").unwrap();
+        assert!(nip11_http_url(&wss).starts_with("https://relay.damus.io"));
+        let ws = RelayUrl::parse("ws://localhost:7777").unwrap();
+        assert!(nip11_http_url(&ws).starts_with("http://localhost:7777"));
+    }
+
+    #[test]
+    fn test_collect_relay_sources_merges() {
+        let damus = RelayUrl::parse("wss://relay.damus.io").unwrap();
+        let nos = RelayUrl::parse("wss://nos.lol").unwrap();
+        let all = collect_relay_sources(
+            &[damus.clone()],
+            &[("Team".to_string(), vec![damus.clone(), nos.clone()])],
+            &["wss://nos.lol".to_string(), "not a url".to_string()],
+        );
+
+        assert_eq!(all.len(), 2);
+        assert_eq!(
+            all[&damus],
+            vec![RelaySource::Global, RelaySource::Group("Team".to_string())]
+        );
+        assert_eq!(
+            all[&nos],
+            vec![RelaySource::Group("Team".to_string()), RelaySource::Bunker]
+        );
+    }
+
+    #[test]
+    fn test_policy_warnings_from_retention_and_limits() {
+        let json = r#"{
+            "limitation": {"created_at_lower_limit": 3600},
+            "retention": [
+                {"kinds": [0, [440, 444]], "time": 0},
+                {"kinds": [1059], "time": 86400},
+                {"time": 604800}
+            ]
+        }"#;
+        let info: RelayInfo = serde_json::from_str(json).unwrap();
+        assert!(info.retention_for(443).unwrap().discards());
+        assert_eq!(info.retention_for(445).unwrap().time, Some(604800));
+        let warnings = info.policy_warnings();
+        assert!(warnings.iter().any(|w| w.contains("Does not store kind 443")));
+        assert!(warnings.iter().any(|w| w.contains("kind 1059 for only 86400s")));
+        assert!(warnings.iter().any(|w| w.contains("Rejects events older than 3600s")));
+        // The catch-all week for 445 is fine
+        assert!(!warnings.iter().any(|w| w.contains("445")));
+
+        assert!(RelayInfo::default().policy_warnings().is_empty());
+    }
+
+    #[test]
+    fn test_relay_info_parses_partial_document() {
+        let json = r#"{"name":"test","supported_nips":[1,42],"limitation":{"auth_required":true}}"#;
+        let info: RelayInfo = serde_json::from_str(json).unwrap();
+        assert_eq!(info.name.as_deref(), Some("test"));
+        assert_eq!(info.supported_nips, vec![1, 42]);
+        assert_eq!(info.limitation.unwrap().auth_required, Some(true));
+    }
+}
@@ -0,0 +1,247 @@
+//! Local message retention ("disappearing messages")
+//!
+//! MDK keeps every decrypted message in SQLite indefinitely. A retention
+//! limit, set per group with `group retention` (stored in
+//! `marmot.retention.json`) or for all groups with the profile's
+//! `message_retention`, makes `receive` and `listen` delete stored messages
+//! older than the limit. Groups can also tag the messages we send with a
+//! NIP-40 `expiration` so other clients that honour it drop them too.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Value that keeps messages forever (overrides a profile default)
+pub const KEEP_FOREVER: &str = "off";
+
+/// Parse a retention limit: a duration (`7d`) or `off`
+pub fn parse_keep(value: &str) -> Result<Option<chrono::Duration>> {
+    if value.trim() == KEEP_FOREVER {
+        return Ok(None);
+    }
+    crate::config::parse_duration(value).map(Some)
+}
+
+/// Short form of a retention limit (`7d`, `36h`), in the largest whole unit
+pub fn format_keep(keep: chrono::Duration) -> String {
+    let minutes = keep.num_minutes();
+    for (unit, size) in [("w", 7 * 24 * 60), ("d", 24 * 60), ("h", 60)] {
+        if minutes % size == 0 {
+            return format!("{}{}", minutes / size, unit);
+        }
+    }
+    format!("{}m", minutes)
+}
+
+/// Effective retention for one group
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Retention {
+    /// Delete stored messages older than this (None = keep forever)
+    pub keep: Option<chrono::Duration>,
+    /// Add a NIP-40 expiration tag to messages we send
+    pub expire_outgoing: bool,
+}
+
+impl Retention {
+    /// Global default from the profile's `message_retention` / `message_expiration`
+    pub fn from_profile(keep: Option<&str>, expire_outgoing: Option<bool>) -> Result<Self> {
+        Ok(Self {
+            keep: keep.map(parse_keep).transpose().context("Invalid message_retention")?.flatten(),
+            expire_outgoing: expire_outgoing.unwrap_or(false),
+        })
+    }
+
+    /// Expiration timestamp for a message sent now, if outgoing messages are tagged
+    pub fn expiration(&self, now: chrono::DateTime<chrono::Utc>) -> Option<i64> {
+        self.keep.filter(|_| self.expire_outgoing).map(|keep| (now + keep).timestamp())
+    }
+}
+
+/// Per-group override
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupRetention {
+    /// Duration like `7d`, or `off` to keep messages forever
+    pub keep: String,
+    #[serde(default)]
+    pub expire_outgoing: bool,
+}
+
+/// Contents of `marmot.retention.json`, keyed by MLS group ID (hex)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RetentionSettings {
+    #[serde(default)]
+    pub groups: BTreeMap<String, GroupRetention>,
+}
+
+impl RetentionSettings {
+    pub fn config_path(db_path: &Path) -> PathBuf {
+        db_path.with_extension("retention.json")
+    }
+
+    /// Load settings (missing file = no overrides)
+    pub fn load(db_path: &Path) -> Result<Self> {
+        let path = Self::config_path(db_path);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Save atomically with owner-only permissions
+    pub fn save(&self, db_path: &Path) -> Result<()> {
+        crate::storage::save_json_atomic(&Self::config_path(db_path), self, crate::storage::PRIVATE_FILE_MODE)
+    }
+
+    /// Retention for `group_id`: its override, else `default`
+    pub fn effective(&self, group_id: &str, default: &Retention) -> Result<Retention> {
+        match self.groups.get(group_id) {
+            Some(group) => Ok(Retention {
+                keep: parse_keep(&group.keep)
+                    .with_context(|| format!("Invalid retention for group {}", group_id))?,
+                expire_outgoing: group.expire_outgoing,
+            }),
+            None => Ok(*default),
+        }
+    }
+}
+
+/// Column names of `table` in MDK's schema
+fn table_columns(conn: &rusqlite::Connection, table: &str) -> Result<Vec<String>> {
+    conn.prepare("SELECT name FROM pragma_table_info(?1)")?
+        .query_map([table], |r| r.get(0))?
+        .collect::<rusqlite::Result<_>>()
+        .with_context(|| format!("Failed to read {} table layout", table))
+}
+
+/// Delete stored messages of `group_id` created before `cutoff` (unix seconds)
+///
+/// MDK has no API for deleting messages, so this works on its SQLite schema
+/// directly, in one transaction, and refuses to run if the layout isn't the
+/// one it expects. Related state stays consistent:
+/// - `groups.last_message_id` / `last_message_at` are moved to the newest
+///   remaining message (or cleared) when the one they pointed at was purged;
+///   nothing else references `messages` rows.
+/// - `processed_messages` rows are kept: they hold event IDs and states, no
+///   plaintext, and are what stops a replayed wrapper from being processed
+///   (and stored) again.
+/// MDK reads messages from the database on every call rather than caching
+/// them, so a purge on a second connection is seen by the open `MDK` at once.
+///
+/// Runs with `secure_delete` so the plaintext doesn't linger in free pages.
+pub fn purge_messages(conn: &rusqlite::Connection, group_id: &[u8], cutoff: i64) -> Result<usize> {
+    let has_all = |columns: &[String], wanted: &[&str]| wanted.iter().all(|c| columns.iter().any(|name| name == c));
+    if !has_all(&table_columns(conn, "messages")?, &["id", "mls_group_id", "created_at"])
+        || !has_all(&table_columns(conn, "groups")?, &["mls_group_id", "last_message_id", "last_message_at"])
+    {
+        anyhow::bail!("Unexpected MDK database layout; not purging");
+    }
+    conn.pragma_update(None, "secure_delete", true)?;
+    let tx = conn.unchecked_transaction()?;
+    let purged = tx
+        .execute(
+            "DELETE FROM messages WHERE mls_group_id = ?1 AND created_at < ?2",
+            rusqlite::params![group_id, cutoff],
+        )
+        .context("Failed to delete expired messages")?;
+    if purged > 0 {
+        tx.execute(
+            "UPDATE groups SET (last_message_id, last_message_at) = \
+                 (SELECT id, created_at FROM messages WHERE mls_group_id = ?1 \
+                  ORDER BY created_at DESC LIMIT 1) \
+             WHERE mls_group_id = ?1 AND last_message_id IS NOT NULL \
+               AND last_message_id NOT IN (SELECT id FROM messages WHERE mls_group_id = ?1)",
+            rusqlite::params![group_id],
+        )
+        .context("Failed to update the group's last message")?;
+    }
+    tx.commit().context("Failed to commit message purge")?;
+    Ok(purged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effective_retention() {
+        let default = Retention::from_profile(Some("30d"), None).unwrap();
+        assert_eq!(default.keep, Some(chrono::Duration::days(30)));
+        assert_eq!(default.expiration(chrono::Utc::now()), None);
+
+        let mut settings = RetentionSettings::default();
+        settings.groups.insert("aa".into(), GroupRetention { keep: "7d".into(), expire_outgoing: true });
+        settings.groups.insert("bb".into(), GroupRetention { keep: KEEP_FOREVER.into(), expire_outgoing: true });
+
+        let now = chrono::Utc::now();
+        let aa = settings.effective("aa", &default).unwrap();
+        assert_eq!(aa.expiration(now), Some((now + chrono::Duration::days(7)).timestamp()));
+        let bb = settings.effective("bb", &default).unwrap();
+        assert_eq!(bb.keep, None);
+        assert_eq!(bb.expiration(now), None);
+        assert_eq!(settings.effective("cc", &default).unwrap(), default);
+
+        assert!(Retention::from_profile(Some("soon"), None).is_err());
+        assert_eq!(format_keep(chrono::Duration::days(14)), "2w");
+        assert_eq!(format_keep(chrono::Duration::hours(36)), "36h");
+        assert_eq!(format_keep(chrono::Duration::minutes(90)), "90m");
+    }
+
+    #[test]
+    fn test_purge_messages() {
+        use mdk_core::prelude::*;
+        use nostr::prelude::*;
+
+        // A real MDK database: Alice creates a group with Bob and stores two messages
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("alice.db");
+        let alice = Keys::generate();
+        let bob = Keys::generate();
+        let relays = vec![RelayUrl::parse("wss://relay.example.com").unwrap()];
+        let bob_mdk = MDK::new(crate::storage::open_storage(&dir.path().join("bob.db"), None).unwrap());
+        let (key_package, tags) = bob_mdk.create_key_package_for_event(&bob.public_key(), relays.clone()).unwrap();
+        let key_package_event = EventBuilder::new(Kind::MlsKeyPackage, key_package)
+            .tags(tags)
+            .sign_with_keys(&bob)
+            .unwrap();
+
+        let mdk = MDK::new(crate::storage::open_storage(&db_path, None).unwrap());
+        let config = NostrGroupConfigData::new(
+            "Test".to_string(), String::new(), None, None, None, relays, vec![alice.public_key()],
+        );
+        let group_id = mdk
+            .create_group(&alice.public_key(), vec![key_package_event], config)
+            .unwrap()
+            .group
+            .mls_group_id;
+        let now = chrono::Utc::now().timestamp();
+        for (text, created_at) in [("old", now - 3600), ("new", now)] {
+            let rumor = EventBuilder::new(Kind::Custom(9), text)
+                .custom_created_at(Timestamp::from(created_at as u64))
+                .build(alice.public_key());
+            mdk.create_message(&group_id, rumor).unwrap();
+        }
+        let newest = mdk.get_messages(&group_id).unwrap().into_iter().max_by_key(|m| m.created_at).unwrap();
+
+        let conn = crate::storage::open_connection(&db_path, None).unwrap();
+        assert_eq!(purge_messages(&conn, group_id.as_slice(), now - 60).unwrap(), 1);
+        let left = mdk.get_messages(&group_id).unwrap();
+        assert_eq!(left.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), vec!["new"]);
+        let group = mdk.get_group(&group_id).unwrap().unwrap();
+        assert_eq!(group.last_message_id, Some(newest.id));
+
+        // Purging the last message clears the group's pointer to it
+        assert_eq!(purge_messages(&conn, group_id.as_slice(), now + 60).unwrap(), 1);
+        assert!(mdk.get_messages(&group_id).unwrap().is_empty());
+        let group = mdk.get_group(&group_id).unwrap().unwrap();
+        assert_eq!(group.last_message_id, None);
+        assert_eq!(group.last_message_at, None);
+
+        let other = rusqlite::Connection::open_in_memory().unwrap();
+        other.execute_batch("CREATE TABLE messages (id BLOB, body TEXT);").unwrap();
+        assert!(purge_messages(&other, group_id.as_slice(), now).is_err());
+    }
+}
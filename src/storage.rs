@@ -0,0 +1,672 @@
+//! Database storage and encryption at rest
+//!
+//! The MDK SQLite store holds MLS secrets and decrypted message history.
+//! It can be opened unencrypted (legacy) or encrypted with SQLCipher using a
+//! 32-byte key from one of several sources:
+//!
+//! - `keyring`        — random key kept in the OS keyring
+//! - `prompt`         — passphrase typed at startup (Argon2id, salt in `marmot.dbkey.json`)
+//! - `file:<path>`    — 64 hex chars in a file
+//! - `env:<VAR>`      — 64 hex chars in an environment variable
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use mdk_sqlite_storage::{EncryptionConfig, MdkSqliteStorage};
+use serde::{Deserialize, Serialize};
+
+/// Service name used for OS keyring entries
+const KEYRING_SERVICE: &str = "marmot-cli";
+
+/// First 16 bytes of every plaintext SQLite database
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// Where the database encryption key comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DbKeySource {
+    Keyring,
+    Prompt,
+    File(PathBuf),
+    Env(String),
+}
+
+impl std::str::FromStr for DbKeySource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "keyring" => Ok(DbKeySource::Keyring),
+            "prompt" => Ok(DbKeySource::Prompt),
+            _ => {
+                if let Some(path) = s.strip_prefix("file:") {
+                    Ok(DbKeySource::File(crate::config::expand_home(path)))
+                } else if let Some(var) = s.strip_prefix("env:") {
+                    Ok(DbKeySource::Env(var.to_string()))
+                } else {
+                    anyhow::bail!(
+                        "Invalid database key source '{}'. Use keyring, prompt, file:<path> or env:<VAR>",
+                        s
+                    )
+                }
+            }
+        }
+    }
+}
+
+/// KDF parameters for passphrase-derived keys, stored next to the database
+#[derive(Debug, Serialize, Deserialize)]
+struct PassphraseParams {
+    kdf: String,
+    salt: String,
+}
+
+impl PassphraseParams {
+    fn path(db_path: &Path) -> PathBuf {
+        db_path.with_extension("dbkey.json")
+    }
+
+    fn load_or_create(db_path: &Path, create: bool) -> Result<Self> {
+        let path = Self::path(db_path);
+        if path.exists() {
+            let content = std::fs::read_to_string(&path).context("Failed to read key parameters")?;
+            return serde_json::from_str(&content).context("Failed to parse key parameters");
+        }
+        if !create {
+            anyhow::bail!(
+                "Missing {} — cannot derive the database key from a passphrase",
+                path.display()
+            );
+        }
+        let params = Self {
+            kdf: "argon2id".to_string(),
+            salt: hex::encode(random_bytes::<16>()),
+        };
+        std::fs::write(&path, serde_json::to_string_pretty(&params)?)
+            .context("Failed to write key parameters")?;
+        Ok(params)
+    }
+}
+
+impl DbKeySource {
+    /// Load the 32-byte database key
+    ///
+    /// With `create`, a missing keyring entry, key file or passphrase salt is
+    /// generated (used when creating or encrypting a database).
+    pub fn load_key(&self, db_path: &Path, create: bool) -> Result<[u8; 32]> {
+        match self {
+            DbKeySource::Keyring => {
+                let entry = keyring::Entry::new(KEYRING_SERVICE, &db_path.display().to_string())
+                    .context("Failed to access OS keyring")?;
+                match entry.get_password() {
+                    Ok(hex_key) => parse_hex_key(&hex_key),
+                    Err(keyring::Error::NoEntry) if create => {
+                        let key = random_bytes::<32>();
+                        entry.set_password(&hex::encode(key))
+                            .context("Failed to store database key in OS keyring")?;
+                        Ok(key)
+                    }
+                    Err(e) => Err(anyhow::anyhow!("No database key in OS keyring: {}", e)),
+                }
+            }
+            DbKeySource::Prompt => {
+                let params = PassphraseParams::load_or_create(db_path, create)?;
+                let passphrase = rpassword::prompt_password("Database passphrase: ")
+                    .context("Failed to read passphrase")?;
+                if create {
+                    let confirm = rpassword::prompt_password("Confirm passphrase: ")
+                        .context("Failed to read passphrase")?;
+                    if confirm != passphrase {
+                        anyhow::bail!("Passphrases do not match");
+                    }
+                }
+                derive_key(&passphrase, &params)
+            }
+            DbKeySource::File(path) => {
+                if !path.exists() && create {
+                    let key = random_bytes::<32>();
+                    write_with_mode(path, hex::encode(key).as_bytes(), PRIVATE_FILE_MODE, true)
+                        .context("Failed to write key file")?;
+                    return Ok(key);
+                }
+                let mut content = String::new();
+                std::fs::File::open(path)
+                    .and_then(|mut f| f.read_to_string(&mut content))
+                    .with_context(|| format!("Failed to read key file {}", path.display()))?;
+                parse_hex_key(&content)
+            }
+            DbKeySource::Env(var) => {
+                let value = std::env::var(var)
+                    .with_context(|| format!("Environment variable {} is not set", var))?;
+                parse_hex_key(&value)
+            }
+        }
+    }
+}
+
+fn parse_hex_key(value: &str) -> Result<[u8; 32]> {
+    let bytes = hex::decode(value.trim()).context("Database key must be hex")?;
+    bytes.try_into().map_err(|_| anyhow::anyhow!("Database key must be 32 bytes (64 hex chars)"))
+}
+
+fn derive_key(passphrase: &str, params: &PassphraseParams) -> Result<[u8; 32]> {
+    if params.kdf != "argon2id" {
+        anyhow::bail!("Unsupported key derivation '{}'", params.kdf);
+    }
+    let salt = hex::decode(&params.salt).context("Invalid salt in key parameters")?;
+    argon2_key(passphrase, &salt)
+}
+
+/// Derive a 32-byte key from a passphrase with Argon2id
+pub fn argon2_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+/// Cryptographically secure random bytes
+pub fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    getrandom::fill(&mut bytes).expect("OS random number generator unavailable");
+    bytes
+}
+
+/// SQLCipher raw-key literal for a 32-byte key
+fn sqlcipher_key(key: &[u8; 32]) -> String {
+    format!("x'{}'", hex::encode(key))
+}
+
+/// Whether the file at `path` is a plaintext (unencrypted) SQLite database
+pub fn is_plaintext_sqlite(path: &Path) -> Result<bool> {
+    let mut header = [0u8; 16];
+    let mut file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    match file.read_exact(&mut header) {
+        Ok(()) => Ok(&header == SQLITE_HEADER),
+        // Empty/short files are fresh databases, not encrypted ones
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(true),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Open the MDK store, encrypted when a key is given
+pub fn open_storage(db_path: &Path, key: Option<[u8; 32]>) -> Result<MdkSqliteStorage> {
+    match key {
+        Some(key) => MdkSqliteStorage::new_with_key(db_path, EncryptionConfig::new(key))
+            .context("Failed to open encrypted SQLite storage (wrong key?)"),
+        None => {
+            if db_path.exists() && !is_plaintext_sqlite(db_path)? {
+                anyhow::bail!(
+                    "Database {} is encrypted. Pass --db-key (keyring, prompt, file:<path> or env:<VAR>).",
+                    db_path.display()
+                );
+            }
+            MdkSqliteStorage::new_unencrypted(db_path).context("Failed to create SQLite storage")
+        }
+    }
+}
+
+/// `path` with `suffix` appended to its full file name
+///
+/// SQLite names its WAL and shared-memory files `<file>-wal` / `<file>-shm`
+/// whatever the database's extension, so `with_extension` would miss them.
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Mode for sidecar files: they hold contacts, verifications and other
+/// plaintext that shouldn't be readable next to an encrypted database
+pub const PRIVATE_FILE_MODE: u32 = 0o600;
+
+/// Write `bytes` to `path`, creating it with `mode` (on Unix) so the data is
+/// never readable with looser permissions, even briefly
+///
+/// With `create_new` an existing file is an error; otherwise it is truncated
+/// (and its permissions tightened first).
+pub fn write_with_mode(path: &Path, bytes: &[u8], mode: u32, create_new: bool) -> Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true);
+    if create_new {
+        options.create_new(true);
+    } else {
+        options.create(true).truncate(true);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(mode);
+        // An existing file keeps its old mode when opened
+        if !create_new && path.exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    file.write_all(bytes)
+        .and_then(|()| file.sync_all())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Write `value` as JSON to `path` atomically (temp file + rename), the temp
+/// file created with `mode`
+pub fn save_json_atomic<T: Serialize>(path: &Path, value: &T, mode: u32) -> Result<()> {
+    let tmp_path = with_suffix(path, ".tmp");
+    let content = serde_json::to_string_pretty(value)
+        .with_context(|| format!("Failed to serialize {}", path.display()))?;
+    write_with_mode(&tmp_path, content.as_bytes(), mode, false)?;
+    std::fs::rename(&tmp_path, path).with_context(|| format!("Failed to atomically save {}", path.display()))?;
+    Ok(())
+}
+
+/// Convert an unencrypted database to SQLCipher in place
+///
+/// The encrypted copy is written to a temp file and verified before it
+/// replaces the original, so a failure part-way leaves the original intact.
+pub fn encrypt_in_place(db_path: &Path, key: &[u8; 32], keep_plaintext: bool) -> Result<Option<PathBuf>> {
+    if !db_path.exists() {
+        anyhow::bail!("Database {} does not exist", db_path.display());
+    }
+    if !is_plaintext_sqlite(db_path)? {
+        anyhow::bail!("Database {} is already encrypted", db_path.display());
+    }
+
+    let tmp_path = with_suffix(db_path, ".encrypting");
+    let _ = std::fs::remove_file(&tmp_path);
+
+    {
+        let conn = rusqlite::Connection::open(db_path).context("Failed to open database")?;
+        // Fold any WAL contents into the main file so the export is complete
+        conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+        let user_version: i64 = conn.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+        conn.execute(
+            "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+            rusqlite::params![tmp_path.to_string_lossy(), sqlcipher_key(key)],
+        ).context("Failed to create encrypted copy")?;
+        conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))
+            .context("Failed to export data to encrypted copy")?;
+        conn.execute_batch(&format!("PRAGMA encrypted.user_version = {};", user_version))?;
+        conn.execute_batch("DETACH DATABASE encrypted;")?;
+    }
+
+    // Verify the copy opens with the key and matches the original schema
+    let original_tables = count_tables(db_path, None)?;
+    let encrypted_tables = count_tables(&tmp_path, Some(key))?;
+    if original_tables != encrypted_tables {
+        let _ = std::fs::remove_file(&tmp_path);
+        anyhow::bail!(
+            "Encrypted copy verification failed ({} vs {} tables); original left untouched",
+            encrypted_tables, original_tables
+        );
+    }
+
+    let backup_path = with_suffix(db_path, ".plaintext.bak");
+    std::fs::rename(db_path, &backup_path).context("Failed to move original database aside")?;
+    if let Err(e) = std::fs::rename(&tmp_path, db_path) {
+        std::fs::rename(&backup_path, db_path).context("Failed to restore original database")?;
+        return Err(e).context("Failed to move encrypted database into place");
+    }
+    for suffix in ["-wal", "-shm"] {
+        let _ = std::fs::remove_file(with_suffix(db_path, suffix));
+    }
+
+    if keep_plaintext {
+        Ok(Some(backup_path))
+    } else {
+        std::fs::remove_file(&backup_path).context("Failed to remove plaintext copy")?;
+        Ok(None)
+    }
+}
+
+/// Open a raw SQLite connection to the store (keyed when encrypted)
+pub fn open_connection(path: &Path, key: Option<&[u8; 32]>) -> Result<rusqlite::Connection> {
+    let conn = rusqlite::Connection::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    if let Some(key) = key {
+        conn.pragma_update(None, "key", sqlcipher_key(key))?;
+    }
+    Ok(conn)
+}
+
+/// An applied (or expected) MDK migration: version and name
+pub type Migration = (i64, String);
+
+/// Migrations recorded in the database (empty for a fresh or non-MDK database)
+pub fn migrations(conn: &rusqlite::Connection) -> Result<Vec<Migration>> {
+    let has_history: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'refinery_schema_history')",
+        [],
+        |r| r.get(0),
+    ).context("Failed to read database (wrong key or not a database?)")?;
+    if !has_history {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare("SELECT version, name FROM refinery_schema_history ORDER BY version")?;
+    let rows = stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?;
+    rows.collect::<rusqlite::Result<Vec<_>>>().context("Failed to read schema history")
+}
+
+/// Latest applied MDK migration version (None for a fresh or non-MDK database)
+pub fn schema_version(conn: &rusqlite::Connection) -> Result<Option<i64>> {
+    Ok(migrations(conn)?.last().map(|(version, _)| *version))
+}
+
+/// Migrations this binary's MDK applies
+///
+/// Determined once by migrating a scratch database, then cached next to
+/// `db_path` keyed by the running binary so later commands skip the scratch run.
+pub fn expected_migrations(db_path: &Path) -> Result<Vec<Migration>> {
+    let cache_path = db_path.with_extension("schema.json");
+    let binary = binary_fingerprint();
+    let cached = std::fs::read_to_string(&cache_path)
+        .ok()
+        .and_then(|data| serde_json::from_str::<SchemaCache>(&data).ok())
+        .filter(|cache| cache.binary == binary);
+    if let Some(cache) = cached {
+        return Ok(cache.migrations);
+    }
+
+    let migrations = scratch_migrations()?;
+    let cache = SchemaCache { binary, migrations };
+    // Best effort: an unreadable cache is simply recomputed next time
+    if let Ok(data) = serde_json::to_string_pretty(&cache) {
+        let _ = std::fs::write(&cache_path, data);
+    }
+    Ok(cache.migrations)
+}
+
+/// MDK migration version this binary creates
+pub fn expected_schema_version(db_path: &Path) -> Result<Option<i64>> {
+    Ok(expected_migrations(db_path)?.last().map(|(version, _)| *version))
+}
+
+/// Expected migrations as computed by a specific binary
+#[derive(Debug, Serialize, Deserialize)]
+struct SchemaCache {
+    binary: String,
+    migrations: Vec<Migration>,
+}
+
+/// Identifies the running executable: crate version plus the file's size and mtime,
+/// so a rebuild against a different MDK invalidates the cache
+fn binary_fingerprint() -> String {
+    let meta = std::env::current_exe().and_then(std::fs::metadata).ok();
+    let size = meta.as_ref().map(|m| m.len()).unwrap_or_default();
+    let mtime = meta
+        .and_then(|m| m.modified().ok())
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    format!("{}-{}-{}", env!("CARGO_PKG_VERSION"), size, mtime)
+}
+
+/// Migrate a throwaway database with this binary's MDK and read back its history
+fn scratch_migrations() -> Result<Vec<Migration>> {
+    let scratch = std::env::temp_dir().join(format!("marmot-schema-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&scratch);
+    let result = MdkSqliteStorage::new_unencrypted(&scratch)
+        .context("Failed to create scratch database")
+        .and_then(|storage| {
+            drop(storage);
+            migrations(&open_connection(&scratch, None)?)
+        });
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(with_suffix(&scratch, suffix));
+    }
+    result
+}
+
+/// How an on-disk schema relates to what this binary expects
+#[derive(Debug, PartialEq, Eq)]
+pub enum SchemaCompat {
+    /// No MDK tables yet; MDK will create them
+    Fresh,
+    /// Exactly the expected migrations
+    Current,
+    /// A prefix of the expected migrations; MDK will apply the rest
+    Upgradable { from: i64, to: i64 },
+    /// Migrations this binary doesn't know (newer build or incompatible MDK)
+    Incompatible(String),
+}
+
+/// Compare applied migrations with the ones this binary expects
+pub fn schema_compat(applied: &[Migration], expected: &[Migration]) -> SchemaCompat {
+    if applied.is_empty() {
+        return SchemaCompat::Fresh;
+    }
+    if let Some((version, name)) = applied.iter().find(|m| !expected.contains(m)) {
+        return SchemaCompat::Incompatible(format!(
+            "database has migration V{} '{}' which this build of marmot-cli does not know",
+            version, name
+        ));
+    }
+    let from = applied.last().map(|(v, _)| *v).unwrap_or_default();
+    let to = expected.last().map(|(v, _)| *v).unwrap_or_default();
+    if applied.len() == expected.len() {
+        SchemaCompat::Current
+    } else if applied.len() < expected.len() && expected.starts_with(applied) {
+        SchemaCompat::Upgradable { from, to }
+    } else {
+        SchemaCompat::Incompatible(format!(
+            "database migrations are not a prefix of this build's migrations (at V{}, expected up to V{})",
+            from, to
+        ))
+    }
+}
+
+/// Refuse to continue when the on-disk schema doesn't match this binary
+///
+/// Catches incompatible databases up front instead of failing deep inside MDK calls.
+pub fn ensure_compatible(db_path: &Path, key: Option<&[u8; 32]>) -> Result<()> {
+    if !db_path.exists() {
+        return Ok(());
+    }
+    let applied = migrations(&open_connection(db_path, key)?)?;
+    if applied.is_empty() {
+        return Ok(());
+    }
+    match schema_compat(&applied, &expected_migrations(db_path)?) {
+        SchemaCompat::Fresh | SchemaCompat::Current => Ok(()),
+        SchemaCompat::Upgradable { from, to } => {
+            tracing::info!("Upgrading database schema from V{} to V{}", from, to);
+            Ok(())
+        }
+        SchemaCompat::Incompatible(reason) => anyhow::bail!(
+            "Database {} is incompatible with this marmot-cli build: {}.\n\
+             \n\
+             Run `marmot-cli db check` for details. To start fresh:\n\
+             - marmot-cli backup <file>   (keep a copy)\n\
+             - move the database aside and run publish-key-package\n\
+             - ask your contacts to re-invite you",
+            db_path.display(),
+            reason
+        ),
+    }
+}
+
+/// Result of `PRAGMA integrity_check` ("ok" when healthy)
+pub fn integrity_check(conn: &rusqlite::Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let rows = stmt.query_map([], |r| r.get::<_, String>(0))?;
+    rows.collect::<rusqlite::Result<Vec<_>>>().context("Integrity check failed to run")
+}
+
+/// Consistent point-in-time copy of the database file (same encryption as the source)
+pub fn snapshot(db_path: &Path, key: Option<&[u8; 32]>) -> Result<Vec<u8>> {
+    let snapshot_path = with_suffix(db_path, ".snapshot");
+    let _ = std::fs::remove_file(&snapshot_path);
+    let result = (|| {
+        let source = open_connection(db_path, key)?;
+        let mut dest = open_connection(&snapshot_path, key)?;
+        {
+            let backup = rusqlite::backup::Backup::new(&source, &mut dest)?;
+            backup.run_to_completion(256, std::time::Duration::from_millis(10), None)?;
+        }
+        drop(dest);
+        std::fs::read(&snapshot_path).context("Failed to read database snapshot")
+    })();
+    let _ = std::fs::remove_file(&snapshot_path);
+    result
+}
+
+fn count_tables(path: &Path, key: Option<&[u8; 32]>) -> Result<i64> {
+    let conn = open_connection(path, key)?;
+    conn.query_row("SELECT count(*) FROM sqlite_master WHERE type = 'table'", [], |r| r.get(0))
+        .with_context(|| format!("Failed to read {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_json_atomic() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("marmot.contacts.json");
+        std::fs::write(&path, "{}").unwrap();
+
+        save_json_atomic(&path, &serde_json::json!({ "alice": "npub1" }), PRIVATE_FILE_MODE).unwrap();
+        let loaded: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(loaded["alice"], "npub1");
+        assert!(!with_suffix(&path, ".tmp").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, PRIVATE_FILE_MODE);
+        }
+    }
+
+    #[test]
+    fn test_parse_key_sources() {
+        assert_eq!("keyring".parse::<DbKeySource>().unwrap(), DbKeySource::Keyring);
+        assert_eq!("prompt".parse::<DbKeySource>().unwrap(), DbKeySource::Prompt);
+        assert_eq!(
+            "env:MARMOT_DB_HEX".parse::<DbKeySource>().unwrap(),
+            DbKeySource::Env("MARMOT_DB_HEX".to_string())
+        );
+        assert!(matches!("file:/tmp/key".parse::<DbKeySource>().unwrap(), DbKeySource::File(_)));
+        assert!("plaintext".parse::<DbKeySource>().is_err());
+    }
+
+    #[test]
+    fn test_file_key_created_and_reloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("marmot.db");
+        let source = DbKeySource::File(dir.path().join("db.key"));
+
+        assert!(source.load_key(&db_path, false).is_err());
+        let created = source.load_key(&db_path, true).unwrap();
+        let loaded = source.load_key(&db_path, false).unwrap();
+        assert_eq!(created, loaded);
+    }
+
+    #[test]
+    fn test_parse_hex_key_length() {
+        assert!(parse_hex_key(&"ab".repeat(32)).is_ok());
+        assert!(parse_hex_key("abcd").is_err());
+        assert!(parse_hex_key("not hex").is_err());
+    }
+
+    #[test]
+    fn test_derive_key_is_deterministic() {
+        let params = PassphraseParams { kdf: "argon2id".to_string(), salt: "00".repeat(16) };
+        let a = derive_key("correct horse", &params).unwrap();
+        let b = derive_key("correct horse", &params).unwrap();
+        let c = derive_key("battery staple", &params).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_schema_version_and_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("marmot.db");
+        {
+            let conn = open_connection(&db_path, None).unwrap();
+            assert_eq!(schema_version(&conn).unwrap(), None);
+            conn.execute_batch(
+                "CREATE TABLE refinery_schema_history (version INTEGER, name TEXT); \
+                 INSERT INTO refinery_schema_history VALUES (1, 'a'), (3, 'b');",
+            ).unwrap();
+            assert_eq!(schema_version(&conn).unwrap(), Some(3));
+        }
+
+        let bytes = snapshot(&db_path, None).unwrap();
+        assert!(bytes.starts_with(SQLITE_HEADER));
+        assert!(!with_suffix(&db_path, ".snapshot").exists());
+    }
+
+    #[test]
+    fn test_with_suffix_keeps_full_file_name() {
+        assert_eq!(with_suffix(Path::new("/data/marmot.db"), "-wal"), PathBuf::from("/data/marmot.db-wal"));
+        assert_eq!(with_suffix(Path::new("/data/state"), "-shm"), PathBuf::from("/data/state-shm"));
+        assert_eq!(with_suffix(Path::new("/data/chat.sqlite3"), ".snapshot"), PathBuf::from("/data/chat.sqlite3.snapshot"));
+    }
+
+    #[test]
+    fn test_expected_migrations_cached_per_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("marmot.db");
+        let migrations: Vec<Migration> = vec![(1, "initial".into()), (2, "cached".into())];
+        let cache = SchemaCache { binary: binary_fingerprint(), migrations: migrations.clone() };
+        std::fs::write(db_path.with_extension("schema.json"), serde_json::to_string(&cache).unwrap()).unwrap();
+
+        // A cache from this binary is used as-is, without migrating a scratch database
+        assert_eq!(expected_migrations(&db_path).unwrap(), migrations);
+        assert_eq!(expected_schema_version(&db_path).unwrap(), Some(2));
+    }
+
+    #[test]
+    fn test_schema_compat() {
+        let expected: Vec<Migration> = vec![(1, "initial".into()), (2, "welcomes".into()), (3, "relays".into())];
+
+        assert_eq!(schema_compat(&[], &expected), SchemaCompat::Fresh);
+        assert_eq!(schema_compat(&expected, &expected), SchemaCompat::Current);
+        assert_eq!(
+            schema_compat(&expected[..2], &expected),
+            SchemaCompat::Upgradable { from: 2, to: 3 }
+        );
+
+        // Old V100-style history from a previous MDK
+        let legacy = vec![(100, "initial".into())];
+        assert!(matches!(schema_compat(&legacy, &expected), SchemaCompat::Incompatible(_)));
+
+        // Newer build added a migration we don't know
+        let mut newer = expected.clone();
+        newer.push((4, "future".into()));
+        assert!(matches!(schema_compat(&newer, &expected), SchemaCompat::Incompatible(_)));
+
+        // Same version, different migration
+        let forked = vec![(1, "initial".into()), (2, "other".into())];
+        assert!(matches!(schema_compat(&forked, &expected), SchemaCompat::Incompatible(_)));
+    }
+
+    #[test]
+    fn test_encrypt_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("marmot.db");
+        {
+            let conn = rusqlite::Connection::open(&db_path).unwrap();
+            conn.execute_batch("CREATE TABLE t (v TEXT); INSERT INTO t VALUES ('secret');").unwrap();
+        }
+        assert!(is_plaintext_sqlite(&db_path).unwrap());
+
+        let key = [7u8; 32];
+        let backup = encrypt_in_place(&db_path, &key, false).unwrap();
+        assert!(backup.is_none());
+        assert!(!is_plaintext_sqlite(&db_path).unwrap());
+        assert_eq!(count_tables(&db_path, Some(&key)).unwrap(), 1);
+
+        // Encrypting twice is refused
+        assert!(encrypt_in_place(&db_path, &key, false).is_err());
+    }
+}
@@ -0,0 +1,779 @@
+//! Interactive terminal chat (`marmot-cli tui`)
+//!
+//! A chat list, the selected chat's messages and an input line, kept current
+//! by live relay subscriptions instead of `receive` polling. The message pane
+//! is read back from MDK storage after every change, so it shows what
+//! `history` would. Replies and reactions are ordinary group messages: a
+//! kind 9 with an `e` tag marked `reply`, and a NIP-25 kind 7 reaction.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::{Context, Result};
+use mdk_core::prelude::*;
+use nostr_sdk::prelude::*;
+use ratatui::crossterm::event::{self, Event as TermEvent, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use tokio::sync::{broadcast, mpsc};
+
+use crate::blocklist::BlockList;
+use crate::contacts::Contacts;
+use crate::health::HealthLog;
+use crate::metadata::{self, ProfileCache};
+use crate::retention::Retention;
+use crate::verification::{MemberStatus, Verifications};
+use crate::MarmotCli;
+
+/// Subscription for group messages, replaced whenever our groups change
+const GROUPS_SUBSCRIPTION: &str = "marmot-tui-groups";
+
+/// Reaction sent by `+` and a bare `/react`
+const DEFAULT_REACTION: &str = "👍";
+
+/// Characters of the replied-to message quoted above a reply
+const QUOTE_LEN: usize = 40;
+
+/// How often messages past their retention limit are purged
+const PURGE_INTERVAL_SECS: u64 = 60;
+
+fn single_letter(letter: Alphabet) -> TagKind<'static> {
+    TagKind::SingleLetter(SingleLetterTag::lowercase(letter))
+}
+
+/// A stored group message, reduced to what the message pane needs
+#[derive(Debug, Clone)]
+pub struct ChatMessage {
+    pub id: EventId,
+    pub pubkey: PublicKey,
+    pub kind: Kind,
+    pub content: String,
+    pub created_at: Timestamp,
+    pub tags: Tags,
+}
+
+impl ChatMessage {
+    /// Event this message refers to (the last `e` tag, as NIP-10 and NIP-25 read it)
+    fn target(&self) -> Option<EventId> {
+        let e = single_letter(Alphabet::E);
+        self.tags
+            .iter()
+            .filter(|tag| tag.kind() == e)
+            .filter_map(|tag| tag.content())
+            .filter_map(|id| EventId::from_hex(id).ok())
+            .last()
+    }
+}
+
+/// A chat message as shown, with its reply context and reactions folded in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatLine {
+    pub id: EventId,
+    pub pubkey: PublicKey,
+    pub content: String,
+    pub created_at: Timestamp,
+    /// Author and start of the message this one replies to
+    pub reply_to: Option<(PublicKey, String)>,
+    /// Emoji -> number of members who reacted with it
+    pub reactions: BTreeMap<String, usize>,
+}
+
+/// First line of a message, shortened for quoting
+fn quote(content: &str) -> String {
+    let mut lines = content.lines();
+    let first = lines.next().unwrap_or_default();
+    if first.chars().count() > QUOTE_LEN || lines.next().is_some() {
+        format!("{}…", first.chars().take(QUOTE_LEN).collect::<String>())
+    } else {
+        first.to_string()
+    }
+}
+
+/// Turn stored messages into pane lines, oldest first
+///
+/// Reactions to messages we no longer have (purged or never received) are dropped.
+pub fn build_lines(messages: &[ChatMessage]) -> Vec<ChatLine> {
+    let mut sorted: Vec<&ChatMessage> = messages.iter().collect();
+    sorted.sort_by_key(|m| m.created_at);
+
+    let mut lines: Vec<ChatLine> = Vec::new();
+    let mut index: HashMap<EventId, usize> = HashMap::new();
+    for message in sorted.iter().filter(|m| m.kind == Kind::Custom(9)) {
+        let reply_to = message
+            .target()
+            .and_then(|id| index.get(&id).copied())
+            .map(|i| (lines[i].pubkey, quote(&lines[i].content)));
+        index.insert(message.id, lines.len());
+        lines.push(ChatLine {
+            id: message.id,
+            pubkey: message.pubkey,
+            content: message.content.clone(),
+            created_at: message.created_at,
+            reply_to,
+            reactions: BTreeMap::new(),
+        });
+    }
+
+    // One reaction per member and emoji, however often it was sent
+    let mut seen: HashSet<(usize, PublicKey, String)> = HashSet::new();
+    for reaction in sorted.iter().filter(|m| m.kind == Kind::Reaction) {
+        let Some(i) = reaction.target().and_then(|id| index.get(&id).copied()) else { continue };
+        let emoji = match reaction.content.trim() {
+            // NIP-25: "+" (or nothing) is a like
+            "" | "+" => DEFAULT_REACTION.to_string(),
+            other => other.to_string(),
+        };
+        if seen.insert((i, reaction.pubkey, emoji.clone())) {
+            *lines[i].reactions.entry(emoji).or_default() += 1;
+        }
+    }
+    lines
+}
+
+/// What a key press or submitted input line asks for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Send a chat message (a reply if one is armed)
+    Send(String),
+    /// React to the selected message
+    React(String),
+    /// Accept the n-th pending invite (1-based)
+    Accept(usize),
+    /// Switch to the chat at this index
+    Select(usize),
+    Quit,
+    /// Input that couldn't be understood; the text says why
+    Invalid(String),
+}
+
+/// Interpret the input line; `None` if there is nothing to do
+pub fn parse_input(input: &str) -> Option<Action> {
+    let input = input.trim();
+    if input.is_empty() {
+        return None;
+    }
+    // `//text` sends a message that starts with '/'
+    if let Some(text) = input.strip_prefix("//") {
+        return Some(Action::Send(format!("/{}", text)));
+    }
+    let Some(command) = input.strip_prefix('/') else {
+        return Some(Action::Send(input.to_string()));
+    };
+    let (name, arg) = command
+        .split_once(char::is_whitespace)
+        .map(|(name, arg)| (name, arg.trim()))
+        .unwrap_or((command, ""));
+    Some(match name {
+        "react" if arg.is_empty() => Action::React(DEFAULT_REACTION.to_string()),
+        "react" => Action::React(arg.to_string()),
+        "accept" if arg.is_empty() => Action::Accept(1),
+        "accept" => match arg.parse() {
+            Ok(n) if n > 0 => Action::Accept(n),
+            _ => Action::Invalid("Usage: /accept [invite number]".to_string()),
+        },
+        "quit" | "q" => Action::Quit,
+        other => Action::Invalid(format!("Unknown command /{} (try /react, /accept or /quit)", other)),
+    })
+}
+
+/// Pane with keyboard focus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Chats,
+    Messages,
+    Input,
+}
+
+struct App {
+    me: PublicKey,
+    groups: Vec<mdk_storage_traits::groups::types::Group>,
+    selected: usize,
+    lines: Vec<ChatLine>,
+    statuses: BTreeMap<PublicKey, MemberStatus>,
+    /// Highlighted message (None = follow the newest)
+    cursor: Option<usize>,
+    /// Message the next send replies to
+    reply: Option<ChatLine>,
+    input: String,
+    focus: Focus,
+    /// Unread messages per group (MLS group ID hex)
+    unread: BTreeMap<String, usize>,
+    /// Invites from users we haven't blocked
+    pending: Vec<mdk_storage_traits::welcomes::types::Welcome>,
+    contacts: Contacts,
+    profiles: ProfileCache,
+    /// Result of the last action (help text when empty)
+    status: String,
+}
+
+impl App {
+    fn new(me: PublicKey, contacts: Contacts, profiles: ProfileCache) -> Self {
+        Self {
+            me,
+            groups: Vec::new(),
+            selected: 0,
+            lines: Vec::new(),
+            statuses: BTreeMap::new(),
+            cursor: None,
+            reply: None,
+            input: String::new(),
+            focus: Focus::Input,
+            unread: BTreeMap::new(),
+            pending: Vec::new(),
+            contacts,
+            profiles,
+            status: String::new(),
+        }
+    }
+
+    fn current_group(&self) -> Option<&mdk_storage_traits::groups::types::Group> {
+        self.groups.get(self.selected)
+    }
+
+    fn label(&self, public_key: &PublicKey) -> String {
+        if *public_key == self.me {
+            "You".to_string()
+        } else {
+            metadata::label(public_key, &self.contacts, &self.profiles)
+        }
+    }
+
+    /// Message a reaction goes to: the highlighted one, else the reply target, else the newest
+    fn react_target(&self) -> Option<&ChatLine> {
+        self.cursor
+            .and_then(|c| self.lines.get(c))
+            .or(self.reply.as_ref())
+            .or(self.lines.last())
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Option<Action> {
+        if key.kind != KeyEventKind::Press {
+            return None;
+        }
+        if key.modifiers.contains(KeyModifiers::CONTROL) && matches!(key.code, KeyCode::Char('c' | 'q')) {
+            return Some(Action::Quit);
+        }
+        match key.code {
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::Chats => Focus::Messages,
+                    Focus::Messages => Focus::Input,
+                    Focus::Input => Focus::Chats,
+                };
+                if self.focus == Focus::Messages && self.cursor.is_none() {
+                    self.cursor = self.lines.len().checked_sub(1);
+                }
+                return None;
+            }
+            KeyCode::Esc => {
+                self.reply = None;
+                self.cursor = None;
+                self.focus = Focus::Input;
+                return None;
+            }
+            _ => {}
+        }
+        match self.focus {
+            Focus::Chats => match key.code {
+                KeyCode::Up if self.selected > 0 => Some(Action::Select(self.selected - 1)),
+                KeyCode::Down if self.selected + 1 < self.groups.len() => Some(Action::Select(self.selected + 1)),
+                KeyCode::Enter => {
+                    self.focus = Focus::Input;
+                    None
+                }
+                _ => None,
+            },
+            Focus::Messages if self.lines.is_empty() => None,
+            Focus::Messages => {
+                let last = self.lines.len() - 1;
+                match key.code {
+                    KeyCode::Up => {
+                        self.cursor = Some(self.cursor.map_or(last, |c| c.saturating_sub(1)));
+                        None
+                    }
+                    KeyCode::Down => {
+                        self.cursor = Some(self.cursor.map_or(last, |c| (c + 1).min(last)));
+                        None
+                    }
+                    KeyCode::Char('r') | KeyCode::Enter => {
+                        self.reply = self.cursor.and_then(|c| self.lines.get(c)).cloned();
+                        self.focus = Focus::Input;
+                        None
+                    }
+                    KeyCode::Char('+') => Some(Action::React(DEFAULT_REACTION.to_string())),
+                    _ => None,
+                }
+            }
+            Focus::Input => match key.code {
+                KeyCode::Char(c) => {
+                    self.input.push(c);
+                    None
+                }
+                KeyCode::Backspace => {
+                    self.input.pop();
+                    None
+                }
+                KeyCode::Enter => parse_input(&std::mem::take(&mut self.input)),
+                _ => None,
+            },
+        }
+    }
+
+    /// Re-read groups and pending invites, keeping the selected chat if it still exists
+    fn reload_groups(&mut self, marmot: &MarmotCli) -> Result<()> {
+        let current = self.current_group().map(|g| g.mls_group_id.clone());
+        self.groups = marmot.mdk.get_groups()?;
+        self.selected = current
+            .and_then(|id| self.groups.iter().position(|g| g.mls_group_id == id))
+            .unwrap_or(0);
+        let blocklist = BlockList::load(&marmot.db_path)?;
+        self.pending = marmot
+            .mdk
+            .get_pending_welcomes(None)?
+            .into_iter()
+            .filter(|w| !blocklist.is_blocked(&w.welcomer))
+            .collect();
+        self.reload_messages(marmot)
+    }
+
+    /// Re-read the selected chat's messages from storage
+    fn reload_messages(&mut self, marmot: &MarmotCli) -> Result<()> {
+        let Some(group_id) = self.current_group().map(|g| g.mls_group_id.clone()) else {
+            self.lines.clear();
+            return Ok(());
+        };
+        let blocklist = BlockList::load(&marmot.db_path)?;
+        let messages: Vec<ChatMessage> = marmot
+            .mdk
+            .get_messages(&group_id)?
+            .into_iter()
+            .filter(|m| !blocklist.is_blocked(&m.pubkey))
+            .map(|m| ChatMessage {
+                id: m.id,
+                pubkey: m.pubkey,
+                kind: m.kind,
+                content: m.content,
+                created_at: m.created_at,
+                tags: m.tags,
+            })
+            .collect();
+        self.lines = build_lines(&messages);
+        self.statuses = marmot.member_statuses(&group_id, &Verifications::load(&marmot.db_path)?);
+        self.unread.remove(&hex::encode(group_id.as_slice()));
+        self.cursor = self.cursor.filter(|&c| c < self.lines.len());
+        Ok(())
+    }
+
+    async fn apply(&mut self, marmot: &MarmotCli, action: Action, retention: &Retention) -> Result<()> {
+        match action {
+            Action::Select(index) => {
+                self.selected = index;
+                self.cursor = None;
+                self.reply = None;
+                self.reload_messages(marmot)?;
+            }
+            Action::Send(text) => {
+                let group_id = self.current_group().context("No chat selected")?.mls_group_id.clone();
+                let mut builder = EventBuilder::new(Kind::Custom(9), text);
+                if let Some(target) = &self.reply {
+                    builder = builder.tags([
+                        Tag::custom(single_letter(Alphabet::E), [target.id.to_hex(), String::new(), "reply".to_string()]),
+                        Tag::public_key(target.pubkey),
+                    ]);
+                }
+                let output = marmot.publish_group_message(&group_id, builder, retention).await?;
+                self.status = format!("✓ Sent to {} relays", output.success.len());
+                self.reply = None;
+                self.cursor = None;
+                self.reload_messages(marmot)?;
+            }
+            Action::React(emoji) => {
+                let group_id = self.current_group().context("No chat selected")?.mls_group_id.clone();
+                let target = self.react_target().context("No message to react to")?.clone();
+                let builder = EventBuilder::new(Kind::Reaction, emoji.clone()).tags([
+                    Tag::event(target.id),
+                    Tag::public_key(target.pubkey),
+                    Tag::custom(single_letter(Alphabet::K), ["9"]),
+                ]);
+                marmot.publish_group_message(&group_id, builder, retention).await?;
+                self.status = format!("✓ Reacted {} to {}", emoji, self.label(&target.pubkey));
+                self.reload_messages(marmot)?;
+            }
+            Action::Accept(n) => {
+                let welcome = self.pending.get(n - 1).cloned().with_context(|| format!("No invite #{}", n))?;
+                marmot.mdk.accept_welcome(&welcome)?;
+                marmot.audit_welcome_accepted(&welcome, false).await;
+                self.reload_groups(marmot)?;
+                if let Some(index) = self.groups.iter().position(|g| g.mls_group_id == welcome.mls_group_id) {
+                    self.selected = index;
+                    self.reload_messages(marmot)?;
+                }
+                subscribe_groups(marmot, &self.groups).await?;
+                self.status = format!("✓ Joined '{}'", welcome.group_name);
+            }
+            Action::Invalid(message) => self.status = message,
+            Action::Quit => {}
+        }
+        Ok(())
+    }
+
+    /// Process an event from the live subscriptions
+    async fn on_relay_event(&mut self, marmot: &MarmotCli, event: &Event) -> Result<()> {
+        if event.kind == Kind::GiftWrap {
+            let mut blocklist = BlockList::load(&marmot.db_path)?;
+            if marmot.process_gift_wrap(event, &mut blocklist, chrono::Utc::now().timestamp()).await {
+                blocklist.save(&marmot.db_path)?;
+                self.reload_groups(marmot)?;
+                self.status = format!("📨 New invite ({} pending) — /accept <n> to join", self.pending.len());
+            }
+            return Ok(());
+        }
+        if event.kind != Kind::MlsGroupMessage {
+            return Ok(());
+        }
+        // Same health recording as `receive`
+        let group_id = event
+            .tags
+            .find(TagKind::SingleLetter(SingleLetterTag::lowercase(Alphabet::H)))
+            .and_then(|tag| tag.content())
+            .and_then(|h| self.groups.iter().find(|g| hex::encode(g.nostr_group_id) == h))
+            .map(|g| g.mls_group_id.clone());
+        let mut health = HealthLog::load(&marmot.db_path);
+        let mut verifications = Verifications::load(&marmot.db_path)?;
+        let processed = marmot.process_group_message(event, group_id.as_ref(), &mut health, &mut verifications);
+        if let Err(e) = health.save(&marmot.db_path) {
+            self.status = format!("⚠️  Could not save group health: {}", e);
+        }
+        match processed {
+            Ok(MessageProcessingResult::ApplicationMessage(msg)) => {
+                if self.current_group().is_some_and(|g| g.mls_group_id == msg.mls_group_id) {
+                    self.reload_messages(marmot)?;
+                } else if msg.kind == Kind::Custom(9)
+                    && msg.pubkey != self.me
+                    && !BlockList::load(&marmot.db_path)?.is_blocked(&msg.pubkey)
+                {
+                    *self.unread.entry(hex::encode(msg.mls_group_id.as_slice())).or_default() += 1;
+                }
+            }
+            // Commits and proposals can rename groups or change who is in them
+            Ok(_) => {
+                self.reload_groups(marmot)?;
+                subscribe_groups(marmot, &self.groups).await?;
+            }
+            Err(_) => {}
+        }
+        Ok(())
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [main, input_area, status_area] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(3), Constraint::Length(1)]).areas(frame.area());
+        let [chats_area, messages_area] =
+            Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)]).areas(main);
+        let border = |focus: Focus| if self.focus == focus { Style::new().bold() } else { Style::new().dim() };
+
+        let mut chats: Vec<ListItem> = self
+            .groups
+            .iter()
+            .map(|g| {
+                let name = if g.name.is_empty() { "(unnamed)" } else { g.name.as_str() };
+                match self.unread.get(&hex::encode(g.mls_group_id.as_slice())) {
+                    Some(n) => ListItem::new(format!("{} ({})", name, n)).bold(),
+                    None => ListItem::new(name.to_string()),
+                }
+            })
+            .collect();
+        chats.extend(
+            self.pending
+                .iter()
+                .enumerate()
+                .map(|(i, w)| ListItem::new(format!("📨 {} — /accept {}", w.group_name, i + 1)).italic()),
+        );
+        let mut state = ListState::default().with_selected((!self.groups.is_empty()).then_some(self.selected));
+        let chat_list = List::new(chats)
+            .block(Block::bordered().title(" Chats ").border_style(border(Focus::Chats)))
+            .highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(chat_list, chats_area, &mut state);
+
+        let title = match self.current_group() {
+            Some(group) => format!(" {} ", group.name),
+            None => " No chats yet — accept an invite or run create-chat ".to_string(),
+        };
+        let highlight = if self.focus == Focus::Messages { Style::new().reversed() } else { Style::new() };
+        let messages = List::new(self.lines.iter().map(|line| self.message_item(line)))
+            .block(Block::bordered().title(title).border_style(border(Focus::Messages)))
+            .highlight_style(highlight);
+        // Selecting the newest line keeps the pane scrolled to the bottom
+        let mut state = ListState::default().with_selected(self.cursor.or(self.lines.len().checked_sub(1)));
+        frame.render_stateful_widget(messages, messages_area, &mut state);
+
+        let title = match &self.reply {
+            Some(line) => format!(" Reply to {} (Esc to cancel) ", self.label(&line.pubkey)),
+            None => " Message ".to_string(),
+        };
+        let input = Paragraph::new(self.input.as_str())
+            .block(Block::bordered().title(title).border_style(border(Focus::Input)));
+        frame.render_widget(input, input_area);
+        if self.focus == Focus::Input {
+            let typed = u16::try_from(self.input.chars().count()).unwrap_or(u16::MAX);
+            let x = (input_area.x + 1).saturating_add(typed).min(input_area.right().saturating_sub(2));
+            frame.set_cursor_position((x, input_area.y + 1));
+        }
+
+        let status = if self.status.is_empty() {
+            "Tab: switch pane · ↑↓: select · r: reply · +: 👍 · /react <emoji> · /accept [n] · Ctrl-C: quit"
+        } else {
+            self.status.as_str()
+        };
+        frame.render_widget(Paragraph::new(status).dim(), status_area);
+    }
+
+    fn message_item(&self, line: &ChatLine) -> ListItem<'static> {
+        // Remote content must not be able to drive the terminal
+        let clean = |text: &str| text.chars().filter(|c| !c.is_control()).collect::<String>();
+        let mut lines = Vec::new();
+        if let Some((author, quoted)) = &line.reply_to {
+            lines.push(Line::from(format!("  ↪ {}: {}", self.label(author), clean(quoted))).dim());
+        }
+        let when = chrono::DateTime::from_timestamp(line.created_at.as_secs() as i64, 0)
+            .map(|t| t.format("%H:%M").to_string())
+            .unwrap_or_default();
+        let warning = match self.statuses.get(&line.pubkey) {
+            Some(MemberStatus::KeyChanged) if line.pubkey != self.me => " ⚠️  KEY CHANGED",
+            _ => "",
+        };
+        let mut content = line.content.lines();
+        lines.push(Line::from(vec![
+            Span::from(format!("[{}] ", when)).dim(),
+            Span::from(self.label(&line.pubkey)).bold(),
+            Span::from(warning).red(),
+            Span::from(format!(": {}", clean(content.next().unwrap_or_default()))),
+        ]));
+        lines.extend(content.map(|rest| Line::from(format!("    {}", clean(rest)))));
+        if !line.reactions.is_empty() {
+            let reactions: Vec<String> = line
+                .reactions
+                .iter()
+                .map(|(emoji, count)| format!("{} {}", clean(emoji), count))
+                .collect();
+            lines.push(Line::from(format!("    {}", reactions.join("  "))).dim());
+        }
+        ListItem::new(lines)
+    }
+}
+
+/// Live subscriptions: gift wraps addressed to us and every group's messages
+async fn subscribe(marmot: &MarmotCli, groups: &[mdk_storage_traits::groups::types::Group]) -> Result<()> {
+    let welcomes = Filter::new()
+        .kind(Kind::GiftWrap)
+        .pubkey(marmot.signer.public_key())
+        .limit(100);
+    marmot.client.subscribe(welcomes, None).await?;
+    subscribe_groups(marmot, groups).await
+}
+
+/// (Re)subscribe to group messages; reusing the ID replaces the previous filter
+async fn subscribe_groups(marmot: &MarmotCli, groups: &[mdk_storage_traits::groups::types::Group]) -> Result<()> {
+    if groups.is_empty() {
+        return Ok(());
+    }
+    let filter = Filter::new()
+        .kind(Kind::MlsGroupMessage)
+        .custom_tags(
+            SingleLetterTag::lowercase(Alphabet::H),
+            groups.iter().map(|g| hex::encode(&g.nostr_group_id)),
+        )
+        .limit(200);
+    marmot
+        .client
+        .subscribe_with_id(SubscriptionId::new(GROUPS_SUBSCRIPTION), filter, None)
+        .await?;
+    Ok(())
+}
+
+/// Read the terminal on a blocking thread and forward its events
+fn spawn_input_reader() -> mpsc::UnboundedReceiver<TermEvent> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if tx.send(event).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// `tui`: run the interactive chat until the user quits
+pub async fn run(marmot: &MarmotCli, retention: &Retention) -> Result<()> {
+    let mut app = App::new(
+        marmot.signer.public_key(),
+        Contacts::load(&marmot.db_path)?,
+        ProfileCache::load(&marmot.db_path),
+    );
+    app.reload_groups(marmot)?;
+    let members: Vec<PublicKey> = app
+        .groups
+        .iter()
+        .filter_map(|g| marmot.mdk.get_members(&g.mls_group_id).ok())
+        .flatten()
+        .collect();
+    app.profiles = marmot.refresh_profiles(&members).await;
+
+    // Listen before subscribing so stored events aren't missed
+    let notifications = marmot.client.notifications();
+    subscribe(marmot, &app.groups).await?;
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut app, marmot, retention, notifications).await;
+    ratatui::restore();
+    result
+}
+
+async fn event_loop(
+    terminal: &mut DefaultTerminal,
+    app: &mut App,
+    marmot: &MarmotCli,
+    retention: &Retention,
+    mut notifications: broadcast::Receiver<RelayPoolNotification>,
+) -> Result<()> {
+    let mut input = spawn_input_reader();
+    let mut purge = tokio::time::interval(std::time::Duration::from_secs(PURGE_INTERVAL_SECS));
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        tokio::select! {
+            event = input.recv() => {
+                let key = match event {
+                    Some(TermEvent::Key(key)) => key,
+                    // Anything else (a resize) just needs the redraw above
+                    Some(_) => continue,
+                    None => return Ok(()),
+                };
+                let Some(action) = app.handle_key(key) else { continue };
+                if action == Action::Quit {
+                    return Ok(());
+                }
+                app.status.clear();
+                if let Err(e) = app.apply(marmot, action, retention).await {
+                    app.status = format!("⚠️  {}", e);
+                }
+            }
+            notification = notifications.recv() => match notification {
+                Ok(RelayPoolNotification::Event { event, .. }) => {
+                    if let Err(e) = app.on_relay_event(marmot, &event).await {
+                        app.status = format!("⚠️  {}", e);
+                    }
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => anyhow::bail!("Relay pool shut down"),
+            },
+            _ = purge.tick() => {
+                let purged = marmot.purge_expired_messages(retention).await;
+                if let Err(e) = purged.and_then(|_| app.reload_messages(marmot)) {
+                    app.status = format!("⚠️  Message purge failed: {}", e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(keys: &Keys, kind: Kind, content: &str, tags: Vec<Tag>, at: u64) -> ChatMessage {
+        let event = EventBuilder::new(kind, content)
+            .tags(tags)
+            .custom_created_at(Timestamp::from(at))
+            .sign_with_keys(keys)
+            .unwrap();
+        ChatMessage {
+            id: event.id,
+            pubkey: event.pubkey,
+            kind: event.kind,
+            content: event.content.clone(),
+            created_at: event.created_at,
+            tags: event.tags.clone(),
+        }
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_parse_input() {
+        assert_eq!(parse_input("  "), None);
+        assert_eq!(parse_input(" hello "), Some(Action::Send("hello".into())));
+        assert_eq!(parse_input("//shrug"), Some(Action::Send("/shrug".into())));
+        assert_eq!(parse_input("/react"), Some(Action::React(DEFAULT_REACTION.into())));
+        assert_eq!(parse_input("/react 🎉"), Some(Action::React("🎉".into())));
+        assert_eq!(parse_input("/accept"), Some(Action::Accept(1)));
+        assert_eq!(parse_input("/accept 2"), Some(Action::Accept(2)));
+        assert!(matches!(parse_input("/accept 0"), Some(Action::Invalid(_))));
+        assert!(matches!(parse_input("/nope"), Some(Action::Invalid(_))));
+        assert_eq!(parse_input("/quit"), Some(Action::Quit));
+    }
+
+    #[test]
+    fn test_build_lines_folds_replies_and_reactions() {
+        let alice = Keys::generate();
+        let bob = Keys::generate();
+        let hello = message(&alice, Kind::Custom(9), "hello there, this is a rather long opening message", vec![], 100);
+        let reply_tag = Tag::custom(single_letter(Alphabet::E), [hello.id.to_hex(), String::new(), "reply".to_string()]);
+        let reply = message(&bob, Kind::Custom(9), "hi!", vec![reply_tag], 200);
+        let like = |keys: &Keys, content: &str, at: u64| {
+            message(keys, Kind::Reaction, content, vec![Tag::event(hello.id), Tag::public_key(alice.public_key())], at)
+        };
+        let messages = vec![
+            reply.clone(),
+            like(&bob, "+", 300),
+            like(&bob, "👍", 301),
+            like(&alice, "🎉", 302),
+            // Reaction to a message we don't have
+            message(&bob, Kind::Reaction, "👍", vec![Tag::event(EventId::all_zeros())], 303),
+            hello.clone(),
+        ];
+
+        let lines = build_lines(&messages);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].id, hello.id);
+        assert_eq!(lines[0].reactions.get("👍"), Some(&1));
+        assert_eq!(lines[0].reactions.get("🎉"), Some(&1));
+        let (author, quoted) = lines[1].reply_to.clone().unwrap();
+        assert_eq!(author, alice.public_key());
+        assert_eq!(quoted, format!("{}…", &hello.content[..QUOTE_LEN]));
+    }
+
+    #[test]
+    fn test_keys_arm_reply_and_submit() {
+        let alice = Keys::generate();
+        let mut app = App::new(alice.public_key(), Contacts::default(), ProfileCache::default());
+        let hello = message(&alice, Kind::Custom(9), "hello", vec![], 100);
+        app.lines = build_lines(&[hello.clone()]);
+
+        // Tab twice: chats, then messages (highlighting the newest)
+        app.handle_key(key(KeyCode::Tab));
+        app.handle_key(key(KeyCode::Tab));
+        assert_eq!(app.focus, Focus::Messages);
+        assert_eq!(app.cursor, Some(0));
+        assert_eq!(app.handle_key(key(KeyCode::Char('+'))), Some(Action::React(DEFAULT_REACTION.into())));
+
+        app.handle_key(key(KeyCode::Char('r')));
+        assert_eq!(app.focus, Focus::Input);
+        assert_eq!(app.reply.as_ref().map(|l| l.id), Some(hello.id));
+
+        for c in "ok".chars() {
+            app.handle_key(key(KeyCode::Char(c)));
+        }
+        assert_eq!(app.handle_key(key(KeyCode::Enter)), Some(Action::Send("ok".into())));
+        assert!(app.input.is_empty());
+
+        app.handle_key(key(KeyCode::Esc));
+        assert!(app.reply.is_none());
+        let quit = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(app.handle_key(quit), Some(Action::Quit));
+    }
+}
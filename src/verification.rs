@@ -0,0 +1,349 @@
+//! Out-of-band verification of group members
+//!
+//! A member's safety number is derived from their MLS leaf signature key and
+//! credential identity (their Nostr pubkey) together with ours, the same way
+//! on both devices, so two people can read it to each other over a trusted
+//! channel. Members confirmed that way are recorded in `marmot.verified.json`
+//! with the leaf key they had; if the key later changes (re-added from another
+//! device, or a malicious add) `list-chats` and `receive` flag it. A key the
+//! member rotated themselves (an update of the verified leaf, which that leaf's
+//! key had to sign) is followed instead, see [`Verifications::follow_key_updates`].
+//!
+//! The epoch code is a digest of the group's MLS epoch authenticator: every
+//! member in the same epoch sees the same code, so comparing it confirms that
+//! nobody is looking at a forked group.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use mdk_core::prelude::*;
+use mdk_sqlite_storage::MdkSqliteStorage;
+use nostr::hashes::{sha256, Hash};
+use nostr::prelude::*;
+use openmls::prelude::{BasicCredential, GroupId as MlsGroupId, MlsGroup, OpenMlsProvider};
+use serde::{Deserialize, Serialize};
+
+/// Hash iterations per fingerprint (slows down searching for a colliding key)
+const ITERATIONS: usize = 5200;
+
+/// Version byte mixed into every fingerprint
+const FINGERPRINT_VERSION: u8 = 0;
+
+/// 30 digits identifying one party's identity and leaf key
+fn fingerprint(identity: &PublicKey, leaf_key: &[u8]) -> String {
+    let mut input = vec![FINGERPRINT_VERSION];
+    input.extend_from_slice(leaf_key);
+    input.extend_from_slice(&identity.to_bytes());
+    let mut digest = sha256::Hash::hash(&input).to_byte_array();
+    for _ in 0..ITERATIONS {
+        let mut round = digest.to_vec();
+        round.extend_from_slice(leaf_key);
+        digest = sha256::Hash::hash(&round).to_byte_array();
+    }
+    digits(&digest, 6)
+}
+
+/// `groups` blocks of 5 decimal digits from 5-byte chunks of `bytes`
+fn digits(bytes: &[u8], groups: usize) -> String {
+    bytes
+        .chunks(5)
+        .take(groups)
+        .map(|chunk| {
+            let value = chunk.iter().fold(0u64, |acc, b| (acc << 8) | u64::from(*b));
+            format!("{:05}", value % 100_000)
+        })
+        .collect()
+}
+
+/// Safety number for a pair of members: 60 digits, identical from both sides
+pub fn safety_number(ours: (&PublicKey, &[u8]), theirs: (&PublicKey, &[u8])) -> String {
+    let mut parts = [fingerprint(ours.0, ours.1), fingerprint(theirs.0, theirs.1)];
+    parts.sort();
+    parts.concat()
+}
+
+/// Digest of the MLS epoch authenticator, shared by everyone in the same epoch
+pub fn epoch_code(authenticator: &[u8]) -> String {
+    digits(&sha256::Hash::hash(authenticator).to_byte_array(), 5)
+}
+
+/// Split digits into blocks of five, `per_line` blocks per line
+pub fn format_digits(number: &str, per_line: usize) -> Vec<String> {
+    let blocks: Vec<&str> = number
+        .as_bytes()
+        .chunks(5)
+        .map(|c| std::str::from_utf8(c).unwrap_or_default())
+        .collect();
+    blocks.chunks(per_line).map(|line| line.join(" ")).collect()
+}
+
+/// Keys and epoch data needed for verification, read from the MLS group
+pub struct GroupKeys {
+    /// Leaf signature key of each member
+    pub leaf_keys: BTreeMap<PublicKey, Vec<u8>>,
+    /// Position of each member's leaf in the tree
+    pub leaf_indices: BTreeMap<PublicKey, u32>,
+    /// Members whose leaf comes from their own commit (a key update), not a key package
+    pub self_updated: BTreeSet<PublicKey>,
+    pub epoch: u64,
+    pub epoch_authenticator: Vec<u8>,
+}
+
+/// Load the OpenMLS group state behind an MDK group
+pub fn load_mls_group(mdk: &MDK<MdkSqliteStorage>, group_id: &GroupId) -> Result<MlsGroup> {
+    let mls_group_id = MlsGroupId::from_slice(group_id.as_slice());
+    MlsGroup::load(mdk.provider.storage(), &mls_group_id)
+        .map_err(|e| anyhow::anyhow!("Failed to load MLS group: {:?}", e))?
+        .context("MLS group state not found")
+}
+
+/// Load the MLS group behind `group_id` and collect its members' leaf keys
+pub fn group_keys(mdk: &MDK<MdkSqliteStorage>, group_id: &GroupId) -> Result<GroupKeys> {
+    let group = load_mls_group(mdk, group_id)?;
+
+    let mut leaf_keys = BTreeMap::new();
+    let mut leaf_indices = BTreeMap::new();
+    let mut self_updated = BTreeSet::new();
+    for member in group.members() {
+        let credential = BasicCredential::try_from(member.credential.clone())
+            .map_err(|e| anyhow::anyhow!("Member has an unsupported credential: {:?}", e))?;
+        let identity = PublicKey::from_slice(credential.identity())
+            .or_else(|_| PublicKey::from_hex(&String::from_utf8_lossy(credential.identity())))
+            .context("Member credential is not a Nostr public key")?;
+        // Only leaves created from a key package carry a lifetime
+        let from_commit = group
+            .public_group()
+            .leaf(member.index)
+            .is_some_and(|leaf| leaf.life_time().is_none());
+        if from_commit {
+            self_updated.insert(identity);
+        }
+        leaf_keys.insert(identity, member.signature_key.clone());
+        leaf_indices.insert(identity, member.index.u32());
+    }
+    Ok(GroupKeys {
+        leaf_keys,
+        leaf_indices,
+        self_updated,
+        epoch: group.epoch().as_u64(),
+        epoch_authenticator: group.epoch_authenticator().as_slice().to_vec(),
+    })
+}
+
+/// Verification state of one member
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemberStatus {
+    Verified,
+    Unverified,
+    /// Verified earlier, but the leaf key is no longer the one we checked
+    KeyChanged,
+}
+
+impl MemberStatus {
+    /// Value used in `--on-message` payloads
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MemberStatus::Verified => "verified",
+            MemberStatus::Unverified => "unverified",
+            MemberStatus::KeyChanged => "key_changed",
+        }
+    }
+}
+
+/// A member confirmed out-of-band
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifiedMember {
+    /// Leaf signature key (hex) at the time of verification
+    pub leaf_key: String,
+    pub safety_number: String,
+    pub verified_at: String,
+    /// When `leaf_key` last followed a key update by the member
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_updated_at: Option<String>,
+}
+
+/// Contents of `marmot.verified.json`: group ID (hex) -> member (hex) -> record
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Verifications {
+    #[serde(default)]
+    pub groups: BTreeMap<String, BTreeMap<String, VerifiedMember>>,
+}
+
+impl Verifications {
+    pub fn config_path(db_path: &Path) -> PathBuf {
+        db_path.with_extension("verified.json")
+    }
+
+    /// Load verifications (missing file = none verified)
+    pub fn load(db_path: &Path) -> Result<Self> {
+        let path = Self::config_path(db_path);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Save atomically with owner-only permissions
+    pub fn save(&self, db_path: &Path) -> Result<()> {
+        crate::storage::save_json_atomic(&Self::config_path(db_path), self, crate::storage::PRIVATE_FILE_MODE)
+    }
+
+    /// Status of `member` whose current leaf key is `leaf_key`
+    pub fn status(&self, group_id: &str, member: &PublicKey, leaf_key: Option<&[u8]>) -> MemberStatus {
+        let Some(record) = self.groups.get(group_id).and_then(|g| g.get(&member.to_hex())) else {
+            return MemberStatus::Unverified;
+        };
+        match leaf_key {
+            Some(key) if hex::encode(key) == record.leaf_key => MemberStatus::Verified,
+            _ => MemberStatus::KeyChanged,
+        }
+    }
+
+    pub fn mark(&mut self, group_id: &str, member: &PublicKey, leaf_key: &[u8], safety_number: &str) {
+        self.groups.entry(group_id.to_string()).or_default().insert(
+            member.to_hex(),
+            VerifiedMember {
+                leaf_key: hex::encode(leaf_key),
+                safety_number: safety_number.to_string(),
+                verified_at: chrono::Utc::now().to_rfc3339(),
+                key_updated_at: None,
+            },
+        );
+    }
+
+    /// Carry verifications over to keys the members rotated themselves
+    ///
+    /// Call after every processed commit with the group's keys from `before`
+    /// and `after` it. A member is followed only if, before the commit, their
+    /// leaf still held the key we verified and the same leaf now carries a key
+    /// from an update, which only the verified key could sign. A re-add (new
+    /// key package) keeps showing as changed, including after the re-added
+    /// device's own later updates. Returns the members followed.
+    pub fn follow_key_updates(&mut self, group_id: &str, before: &GroupKeys, after: &GroupKeys) -> Vec<PublicKey> {
+        let Some(members) = self.groups.get_mut(group_id) else {
+            return Vec::new();
+        };
+        let mut followed = Vec::new();
+        for (member, leaf_key) in &after.leaf_keys {
+            let Some(record) = members.get_mut(&member.to_hex()) else { continue };
+            let was_verified = before.leaf_keys.get(member).is_some_and(|key| hex::encode(key) == record.leaf_key);
+            let same_leaf = before.leaf_indices.get(member) == after.leaf_indices.get(member);
+            let updated = record.leaf_key != hex::encode(leaf_key) && after.self_updated.contains(member);
+            if updated && was_verified && same_leaf {
+                record.leaf_key = hex::encode(leaf_key);
+                record.key_updated_at = Some(chrono::Utc::now().to_rfc3339());
+                followed.push(*member);
+            }
+        }
+        followed
+    }
+
+    /// Forget a verification; returns whether one existed
+    pub fn unmark(&mut self, group_id: &str, member: &PublicKey) -> bool {
+        let removed = self
+            .groups
+            .get_mut(group_id)
+            .and_then(|g| g.remove(&member.to_hex()))
+            .is_some();
+        self.groups.retain(|_, members| !members.is_empty());
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safety_number_is_symmetric_and_key_bound() {
+        let (alice, bob) = (Keys::generate().public_key(), Keys::generate().public_key());
+        let (alice_leaf, bob_leaf) = ([1u8; 32], [2u8; 32]);
+
+        let from_alice = safety_number((&alice, &alice_leaf), (&bob, &bob_leaf));
+        let from_bob = safety_number((&bob, &bob_leaf), (&alice, &alice_leaf));
+        assert_eq!(from_alice, from_bob);
+        assert_eq!(from_alice.len(), 60);
+        assert!(from_alice.chars().all(|c| c.is_ascii_digit()));
+
+        let new_leaf = safety_number((&alice, &alice_leaf), (&bob, &[3u8; 32]));
+        assert_ne!(from_alice, new_leaf);
+    }
+
+    #[test]
+    fn test_format_digits() {
+        let lines = format_digits(&"0123456789".repeat(6), 4);
+        assert_eq!(lines, vec!["01234 56789 01234 56789", "01234 56789 01234 56789", "01234 56789 01234 56789"]);
+        assert_eq!(epoch_code(b"auth").len(), 25);
+    }
+
+    #[test]
+    fn test_status_tracks_leaf_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("marmot.db");
+        let bob = Keys::generate().public_key();
+
+        let mut verifications = Verifications::load(&db_path).unwrap();
+        assert_eq!(verifications.status("g", &bob, Some(&[2u8; 32])), MemberStatus::Unverified);
+        verifications.mark("g", &bob, &[2u8; 32], "123");
+        verifications.save(&db_path).unwrap();
+
+        let reloaded = Verifications::load(&db_path).unwrap();
+        assert_eq!(reloaded.status("g", &bob, Some(&[2u8; 32])), MemberStatus::Verified);
+        assert_eq!(reloaded.status("g", &bob, Some(&[9u8; 32])), MemberStatus::KeyChanged);
+        assert_eq!(reloaded.status("other", &bob, Some(&[2u8; 32])), MemberStatus::Unverified);
+
+        let mut verifications = reloaded;
+        assert!(verifications.unmark("g", &bob));
+        assert!(verifications.groups.is_empty());
+    }
+
+    fn keys(members: &[(PublicKey, u8, u32)], self_updated: &[PublicKey]) -> GroupKeys {
+        GroupKeys {
+            leaf_keys: members.iter().map(|(pk, key, _)| (*pk, vec![*key; 32])).collect(),
+            leaf_indices: members.iter().map(|(pk, _, index)| (*pk, *index)).collect(),
+            self_updated: self_updated.iter().copied().collect(),
+            epoch: 2,
+            epoch_authenticator: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_follow_key_updates_only_for_self_updates() {
+        let (bob, carol) = (Keys::generate().public_key(), Keys::generate().public_key());
+        let mut verifications = Verifications::default();
+        verifications.mark("g", &bob, &[2u8; 32], "123");
+        verifications.mark("g", &carol, &[4u8; 32], "456");
+
+        // Bob rotated his key in a self-update commit; Carol was removed and re-added
+        let before = keys(&[(bob, 2, 1), (carol, 4, 2)], &[]);
+        let after = keys(&[(bob, 3, 1), (carol, 5, 2)], &[bob]);
+        assert_eq!(verifications.follow_key_updates("g", &before, &after), vec![bob]);
+        assert_eq!(verifications.status("g", &bob, Some(&[3u8; 32])), MemberStatus::Verified);
+        assert_eq!(verifications.status("g", &carol, Some(&[5u8; 32])), MemberStatus::KeyChanged);
+
+        // Nothing left to follow, and other groups are untouched
+        assert!(verifications.follow_key_updates("g", &after, &after).is_empty());
+        assert!(verifications.follow_key_updates("other", &before, &after).is_empty());
+    }
+
+    #[test]
+    fn test_readded_member_stays_changed_after_self_update() {
+        let bob = Keys::generate().public_key();
+        let mut verifications = Verifications::default();
+        verifications.mark("g", &bob, &[2u8; 32], "123");
+
+        // Re-added from another device (key package leaf), then that device updates its key
+        let readded = keys(&[(bob, 7, 1)], &[]);
+        let updated = keys(&[(bob, 8, 1)], &[bob]);
+        assert!(verifications.follow_key_updates("g", &readded, &updated).is_empty());
+        assert_eq!(verifications.status("g", &bob, Some(&[8u8; 32])), MemberStatus::KeyChanged);
+
+        // A verified key whose leaf moved (removed and re-added elsewhere) isn't followed either
+        let before = keys(&[(bob, 2, 1)], &[]);
+        let moved = keys(&[(bob, 9, 3)], &[bob]);
+        assert!(verifications.follow_key_updates("g", &before, &moved).is_empty());
+    }
+}